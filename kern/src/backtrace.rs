@@ -0,0 +1,318 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use shim::path::Path;
+
+use crate::console::kprintln;
+use crate::elfparser::{ELF, RawELFFile, SectionTable, SymbolTable, SymbolType};
+use crate::log::{info, warn};
+use crate::mutex::Mutex;
+use crate::param::IO_BASE;
+use crate::process::Process;
+use crate::traps::TrapFrame;
+use crate::SCHEDULER;
+
+#[cfg(test)]
+mod tests;
+
+/// The most frames a backtrace shows.
+const MAX_FRAMES: usize = 32;
+
+/// The kernel ELF on the file system, whose symbols name the functions of kernel
+/// backtraces.
+const KERNEL_ELF: &str = "/kernel.elf";
+
+/// A function of an ELF file.
+#[derive(Debug)]
+struct Function {
+    start: u64,
+    size: u64,
+    name: String,
+}
+
+/// The functions of an ELF file, sorted by address, used to name the addresses of
+/// a backtrace.
+#[derive(Debug)]
+pub struct Symbolizer {
+    functions: Vec<Function>,
+}
+
+impl Symbolizer {
+    fn new(mut functions: Vec<Function>) -> Symbolizer {
+        functions.sort_by_key(|function| function.start);
+        Symbolizer { functions: functions }
+    }
+
+    /// Returns a `Symbolizer` naming the functions of `table`.
+    pub fn from(table: &SymbolTable) -> Symbolizer {
+        Symbolizer::new(table.symbols.iter()
+            .filter(|symbol| symbol.get_type() == SymbolType::FUNC && symbol.st_value != 0)
+            .map(|symbol| Function {
+                start: symbol.st_value,
+                size: symbol.st_size,
+                name: String::from_utf8_lossy(&table.get_name(symbol.st_name)).into_owned(),
+            })
+            .collect())
+    }
+
+    /// Reads the symbols of the ELF file `elf`. Returns `None` if it has no
+    /// symbol table, as when it is stripped.
+    pub fn read(elf: &RawELFFile) -> Option<Symbolizer> {
+        let sections = SectionTable::from(elf).ok()?;
+        let symbols = SymbolTable::from(&sections).ok()?;
+        Some(Symbolizer::from(&symbols))
+    }
+
+    /// Reads the symbols of the ELF file at `path`. Returns `None` if the file
+    /// can't be read or has no symbol table.
+    pub fn load<P: AsRef<Path>>(path: P) -> Option<Symbolizer> {
+        let mut elf = ELF::new();
+        if !elf.initialize_to_execute(path) {
+            return None;
+        }
+        Symbolizer::read(&elf.raw)
+    }
+
+    /// Returns the name of the function containing `addr` and the offset of `addr`
+    /// in it, or `None` if no function does.
+    pub fn resolve(&self, addr: u64) -> Option<(&str, u64)> {
+        let index = match self.functions.binary_search_by_key(&addr, |function| function.start) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let function = &self.functions[index];
+        let offset = addr - function.start;
+        if function.size != 0 && offset >= function.size {
+            return None;
+        }
+        Some((&function.name, offset))
+    }
+
+    /// Returns `true` if a function named `name`, or whose path ends with `name`,
+    /// starts at `addr`.
+    fn starts(&self, addr: u64, name: &str) -> bool {
+        match self.resolve(addr) {
+            Some((symbol, 0)) => {
+                let path = format!("{}", Demangle(symbol));
+                path == name || path.ends_with(&format!("::{}", name))
+            },
+            _ => false,
+        }
+    }
+}
+
+/// The symbols of the kernel, loaded by `initialize()`.
+static KERNEL_SYMBOLS: Mutex<Option<Symbolizer>> = Mutex::new(None);
+
+/// Loads the symbols of the kernel from `KERNEL_ELF`. Without them, kernel
+/// backtraces only show addresses. The file system has to be initialized.
+///
+/// The symbols are only kept if they place `kmain` where it is, so that a
+/// `KERNEL_ELF` left from another build doesn't name the wrong functions.
+pub fn initialize() {
+    let symbols = match Symbolizer::load(KERNEL_ELF) {
+        Some(symbols) => symbols,
+        None => {
+            warn!("No kernel symbols in {}: backtraces show addresses only", KERNEL_ELF);
+            return;
+        },
+    };
+    if !symbols.starts(crate::kmain as usize as u64, "kmain") {
+        warn!("{} is not the running kernel: backtraces show addresses only", KERNEL_ELF);
+        return;
+    }
+    info!("Loaded {} kernel symbols from {}", symbols.functions.len(), KERNEL_ELF);
+    *KERNEL_SYMBOLS.lock() = Some(symbols);
+}
+
+/// Walks the frame-pointer chain from the frame record at `fp`, reading records
+/// with `read`, and stores the return addresses in `frames`. Returns how many
+/// were stored.
+///
+/// A frame record holds the frame pointer of the caller and the return address.
+/// The records of callers are higher up the stack: the walk stops at a frame
+/// pointer that is null, misaligned, unreadable or doesn't move up.
+fn walk<F: FnMut(u64) -> Option<[u64; 2]>>(mut fp: u64, mut read: F, frames: &mut [u64]) -> usize {
+    let mut count = 0;
+    while count < frames.len() && fp != 0 && fp % 8 == 0 {
+        let record = match read(fp) {
+            Some(record) => record,
+            None => break,
+        };
+        if record[1] == 0 {
+            break;
+        }
+        frames[count] = record[1];
+        count += 1;
+        if record[0] <= fp {
+            break;
+        }
+        fp = record[0];
+    }
+    count
+}
+
+/// Reads the kernel frame record at `fp`, which has to be in memory below the
+/// peripherals.
+fn read_kernel(fp: u64) -> Option<[u64; 2]> {
+    if fp < 16 || fp.checked_add(16)? > IO_BASE as u64 {
+        return None;
+    }
+    let record = fp as *const u64;
+    unsafe { Some([record.read_volatile(), record.add(1).read_volatile()]) }
+}
+
+/// Reads the frame record at the user address `fp` of `process`.
+fn read_user(process: &mut Process, fp: u64) -> Option<[u64; 2]> {
+    let mut bytes = [0u8; 16];
+    process.copy_from_user(fp as usize, &mut bytes).ok()?;
+    let mut next = [0u8; 8];
+    let mut lr = [0u8; 8];
+    next.copy_from_slice(&bytes[..8]);
+    lr.copy_from_slice(&bytes[8..]);
+    Some([u64::from_le_bytes(next), u64::from_le_bytes(lr)])
+}
+
+/// Prints the backtrace of a stop at `pc` whose callers return to `frames`,
+/// naming the functions with `symbols` if there are any. Each line goes to `out`.
+fn print_frames(pc: u64, frames: &[u64], symbols: Option<&Symbolizer>, out: &dyn Fn(fmt::Arguments)) {
+    out(format_args!("backtrace:"));
+    print_frame(0, pc, symbols, out);
+    for (index, &lr) in frames.iter().enumerate() {
+        // the call is the instruction before the return address
+        print_frame(index + 1, lr.wrapping_sub(4), symbols, out);
+    }
+}
+
+fn print_frame(index: usize, addr: u64, symbols: Option<&Symbolizer>, out: &dyn Fn(fmt::Arguments)) {
+    match symbols.and_then(|symbols| symbols.resolve(addr)) {
+        Some((name, offset)) => out(format_args!("  #{:<2} 0x{:016x} {}+0x{:x}", index, addr, Demangle(name), offset)),
+        None => out(format_args!("  #{:<2} 0x{:016x} ??", index, addr)),
+    }
+}
+
+/// Prints the backtrace of the kernel stopped at `pc` with the frame pointer `fp`
+/// to the console. Nothing is allocated, and the symbols are left out if another
+/// core holds them, so that it is safe to call on a panic.
+pub fn print_kernel(pc: u64, fp: u64) {
+    let mut frames = [0u64; MAX_FRAMES];
+    let count = walk(fp, read_kernel, &mut frames);
+    let guard = KERNEL_SYMBOLS.try_lock();
+    let symbols = guard.as_ref().and_then(|symbols| (**symbols).as_ref());
+    print_frames(pc, &frames[..count], symbols, &|args| kprintln!("{}", args));
+}
+
+/// Logs the backtrace of the user thread stopped at `tf`, naming the functions
+/// with the symbols of the program it runs, read when it was loaded. The
+/// scheduler must not be locked.
+pub fn print_user(tf: &TrapFrame) {
+    let mut frames = [0u64; MAX_FRAMES];
+    let (count, symbols) = SCHEDULER.with_process(tf.tpidr, |process| {
+        let count = walk(tf.x29, |fp| read_user(process, fp), &mut frames);
+        Ok((count, process.symbols.clone()))
+    }).unwrap_or((0, None));
+    print_frames(tf.elr, &frames[..count], symbols.as_ref().map(|symbols| &**symbols), &|args| warn!("{}", args));
+}
+
+/// Formats a Rust symbol name mangled with the legacy scheme, such as
+/// `_ZN6kernel5traps16handle_exception17h0123456789abcdefE`, as a path without its
+/// hash: `kernel::traps::handle_exception`. Other names are formatted as they are.
+struct Demangle<'a>(&'a str);
+
+impl<'a> fmt::Display for Demangle<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut rest = match demangle_body(self.0) {
+            Some(rest) => rest,
+            None => return f.write_str(self.0),
+        };
+        let mut first = true;
+        while !rest.starts_with('E') {
+            let digits = rest.bytes().take_while(|byte| byte.is_ascii_digit()).count();
+            let len: usize = rest[..digits].parse().map_err(|_| fmt::Error)?;
+            let ident = &rest[digits..digits + len];
+            rest = &rest[digits + len..];
+            if rest.starts_with('E') && is_hash(ident) {
+                break;
+            }
+            if !first {
+                f.write_str("::")?;
+            }
+            first = false;
+            write_ident(f, ident)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the identifiers of the legacy mangled name `name` followed by its `E`,
+/// or `None` if `name` isn't one or is malformed.
+fn demangle_body(name: &str) -> Option<&str> {
+    let body = if name.starts_with("_ZN") {
+        &name[3..]
+    } else if name.starts_with("__ZN") {
+        &name[4..]
+    } else {
+        return None;
+    };
+    // check that the identifiers are well-formed before printing any of them
+    let mut rest = body;
+    loop {
+        if rest.starts_with('E') {
+            return Some(body);
+        }
+        let digits = rest.bytes().take_while(|byte| byte.is_ascii_digit()).count();
+        let len: usize = rest[..digits].parse().ok()?;
+        if len == 0 || digits + len > rest.len() || !rest.is_char_boundary(digits + len) {
+            return None;
+        }
+        rest = &rest[digits + len..];
+    }
+}
+
+/// Returns `true` if `ident` is the hash ending a legacy mangled name.
+fn is_hash(ident: &str) -> bool {
+    ident.len() == 17 && ident.starts_with('h') && ident[1..].bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Writes the identifier `ident` of a mangled name, undoing its escapes.
+fn write_ident(f: &mut fmt::Formatter, ident: &str) -> fmt::Result {
+    let mut rest = if ident.starts_with("_$") { &ident[1..] } else { ident };
+    while !rest.is_empty() {
+        if rest.starts_with("..") {
+            f.write_str("::")?;
+            rest = &rest[2..];
+        } else if rest.starts_with('$') {
+            let end = match rest[1..].find('$') {
+                Some(end) => end + 1,
+                None => return f.write_str(rest),
+            };
+            let escape = &rest[1..end];
+            let unescaped = match escape {
+                "SP" => Some('@'),
+                "BP" => Some('*'),
+                "RF" => Some('&'),
+                "LT" => Some('<'),
+                "GT" => Some('>'),
+                "LP" => Some('('),
+                "RP" => Some(')'),
+                "C" => Some(','),
+                _ if escape.starts_with('u') => u32::from_str_radix(&escape[1..], 16).ok()
+                    .and_then(core::char::from_u32),
+                _ => None,
+            };
+            match unescaped {
+                Some(c) => write!(f, "{}", c)?,
+                None => f.write_str(&rest[..end + 1])?,
+            }
+            rest = &rest[end + 1..];
+        } else {
+            let end = rest.find(|c: char| c == '$' || c == '.').unwrap_or(rest.len());
+            let end = if end == 0 { 1 } else { end }; // a single '.'
+            f.write_str(&rest[..end])?;
+            rest = &rest[end..];
+        }
+    }
+    Ok(())
+}
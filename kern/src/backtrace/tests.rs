@@ -0,0 +1,125 @@
+mod demangle {
+    use crate::backtrace::Demangle;
+
+    fn demangle(name: &str) -> String {
+        format!("{}", Demangle(name))
+    }
+
+    #[test]
+    fn test_mangled() {
+        assert_eq!(demangle("_ZN6kernel5traps16handle_exceptionE"), "kernel::traps::handle_exception");
+        assert_eq!(demangle("__ZN6kernel5kmainE"), "kernel::kmain");
+    }
+
+    #[test]
+    fn test_hash_dropped() {
+        assert_eq!(demangle("_ZN6kernel5traps16handle_exception17h0123456789abcdefE"),
+            "kernel::traps::handle_exception");
+        assert_eq!(demangle("_ZN6kernel5kmain17hfedcba9876543210E"), "kernel::kmain");
+    }
+
+    #[test]
+    fn test_hash_like_kept() {
+        // not 16 hex digits, or not the last identifier
+        assert_eq!(demangle("_ZN6kernel5inner17h0123456789abcdeZE"), "kernel::inner::h0123456789abcdeZ");
+        assert_eq!(demangle("_ZN17h0123456789abcdef6kernelE"), "h0123456789abcdef::kernel");
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(demangle("_ZN4core3ptr13drop_in_place17h0123456789abcdefE"), "core::ptr::drop_in_place");
+        assert_eq!(demangle("_ZN54_$LT$kernel..mutex..Mutex$LT$T$GT$$u20$as$u20$Drop$GT$4drop17h0123456789abcdefE"),
+            "<kernel::mutex::Mutex<T> as Drop>::drop");
+        assert_eq!(demangle("_ZN6kernel4main28_$u7b$$u7b$closure$u7d$$u7d$17h0123456789abcdefE"),
+            "kernel::main::{{closure}}");
+        assert_eq!(demangle("_ZN6kernel11$RF$$BP$$C$E"), "kernel::&*,");
+    }
+
+    #[test]
+    fn test_plain() {
+        assert_eq!(demangle("memcpy"), "memcpy");
+        assert_eq!(demangle("kmain"), "kmain");
+        assert_eq!(demangle(""), "");
+        assert_eq!(demangle("_start"), "_start");
+    }
+
+    #[test]
+    fn test_malformed_kept() {
+        assert_eq!(demangle("_ZN6kernel"), "_ZN6kernel");
+        assert_eq!(demangle("_ZN20kernelE"), "_ZN20kernelE");
+        assert_eq!(demangle("_ZN0E"), "_ZN0E");
+        assert_eq!(demangle("_ZNkernelE"), "_ZNkernelE");
+    }
+}
+
+mod symbolizer {
+    use crate::backtrace::{Function, Symbolizer};
+
+    fn function(start: u64, size: u64, name: &str) -> Function {
+        Function { start: start, size: size, name: name.into() }
+    }
+
+    /// `first` at 0x1000..0x1010, `second` at 0x1020..0x1040 and `last` at
+    /// 0x1040..0x1048, given out of order.
+    fn symbolizer() -> Symbolizer {
+        Symbolizer::new(vec![
+            function(0x1040, 0x8, "last"),
+            function(0x1000, 0x10, "first"),
+            function(0x1020, 0x20, "second"),
+        ])
+    }
+
+    #[test]
+    fn test_resolve_inside() {
+        let symbols = symbolizer();
+        assert_eq!(symbols.resolve(0x1000), Some(("first", 0)));
+        assert_eq!(symbols.resolve(0x100c), Some(("first", 0xc)));
+        assert_eq!(symbols.resolve(0x1020), Some(("second", 0)));
+        assert_eq!(symbols.resolve(0x103f), Some(("second", 0x1f)));
+        assert_eq!(symbols.resolve(0x1040), Some(("last", 0)));
+        assert_eq!(symbols.resolve(0x1047), Some(("last", 0x7)));
+    }
+
+    #[test]
+    fn test_resolve_between() {
+        let symbols = symbolizer();
+        assert_eq!(symbols.resolve(0x1010), None);
+        assert_eq!(symbols.resolve(0x101c), None);
+    }
+
+    #[test]
+    fn test_resolve_outside() {
+        let symbols = symbolizer();
+        assert_eq!(symbols.resolve(0), None);
+        assert_eq!(symbols.resolve(0xfff), None);
+        assert_eq!(symbols.resolve(0x1048), None);
+        assert_eq!(symbols.resolve(u64::max_value()), None);
+    }
+
+    #[test]
+    fn test_resolve_unsized() {
+        // a function of unknown size runs up to the next one
+        let symbols = Symbolizer::new(vec![function(0x2000, 0, "asm"), function(0x3000, 0x10, "next")]);
+        assert_eq!(symbols.resolve(0x2ffc), Some(("asm", 0xffc)));
+        assert_eq!(symbols.resolve(0x3000), Some(("next", 0)));
+    }
+
+    #[test]
+    fn test_resolve_empty() {
+        assert_eq!(Symbolizer::new(vec![]).resolve(0x1000), None);
+    }
+
+    #[test]
+    fn test_starts() {
+        let symbols = Symbolizer::new(vec![
+            function(0x1000, 0x10, "_ZN6kernel5kmain17h0123456789abcdefE"),
+            function(0x2000, 0x10, "kmain2"),
+            function(0x3000, 0x10, "kmain"),
+        ]);
+        assert!(symbols.starts(0x1000, "kmain"));
+        assert!(!symbols.starts(0x1004, "kmain"));
+        assert!(!symbols.starts(0x2000, "kmain"));
+        assert!(symbols.starts(0x3000, "kmain"));
+        assert!(!symbols.starts(0x4000, "kmain"));
+    }
+}
@@ -36,6 +36,12 @@ impl Console {
         self.inner().read_byte()
     }
 
+    /// Returns `true` if a byte is available, so that `read_byte()` returns
+    /// immediately.
+    pub fn has_byte(&mut self) -> bool {
+        self.inner().has_byte()
+    }
+
     /// Writes the byte `byte` to the UART device.
     pub fn write_byte(&mut self, byte: u8) {
         self.inner().write_byte(byte)
@@ -67,6 +73,23 @@ impl fmt::Write for Console {
 /// Global `Console` singleton.
 pub static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
 
+/// A handle writing through to `CONSOLE`, which it locks for each write only.
+/// Code holding it may print with `kprint!` in between, as the shell does.
+pub struct WriteThrough;
+
+impl WriteThrough {
+    /// Writes the byte `byte` to the console.
+    pub fn write_byte(&mut self, byte: u8) {
+        CONSOLE.lock().write_byte(byte)
+    }
+}
+
+impl fmt::Write for WriteThrough {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        CONSOLE.lock().write_str(s)
+    }
+}
+
 /// Internal function called by the `kprint[ln]!` macros.
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
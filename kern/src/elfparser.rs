@@ -12,4 +12,5 @@ pub use self::section::{SectionTable, SectionEntry64};
 pub use self::symbol::{Symbol64, SymbolTable, DynamicSymbolTable};
 pub use self::version::{GnuVersionReq, Version64, GnuVersion};
 pub use self::relocation::{RelaTable, RelaPLT};
-pub use self::dynamic::{DynamicTable};
\ No newline at end of file
+pub use self::dynamic::{DynamicTable};
+pub use self::values::{FileHeaderClass, FileHeaderMachine, FileHeaderType, ProgHeaderType, ProgHeaderFlag, SymbolType};
\ No newline at end of file
@@ -61,39 +61,40 @@ impl RawELFFile {
         file_length
     }
 
-    // This function is only for demo-ing the fact that I can get the elf file to execute in my first demo.
-    // Use read_file for any other case. If I use read_file instead of this for the process, the buffer of 
-    // 2500000 cause an error periodically when I run it... Not stable at all. But there is virtually no difference between the two
+    // read_file_to_execute into this elf file struct
+    // Same as read_file, but the file is read straight into a heap buffer sized to the
+    // file instead of going through a fixed stack buffer, so executables of any size
+    // can be loaded without blowing up the kernel stack.
+    //
+    // returns length of the file if succeeds, 0 otherwise
     pub fn read_file_to_execute<P: AsRef<Path>>(&mut self, path: P) -> usize {
-        let mut working_dir = PathBuf::from("/");
+        let working_dir = PathBuf::from("/");
         let mut dir = working_dir.clone();
         dir.push(path);
         let entry = FILESYSTEM.open(dir.as_path());
 
-        if entry.is_err() {
-            kprintln!("Can't open file at path: {:?}", dir.to_str().unwrap());
-            return 0;
-        }
-        let entry = entry.unwrap();
-        let mut buffer = [0u8; 80000usize];                              // change this to something huge
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => return 0,
+        };
         let mut file_length : usize = 0usize;
 
-        if let Some(mut file) = entry.into_file() {                 
+        if let Some(mut file) = entry.into_file() {
             use shim::io::Read;
-            let length = match file.read(&mut buffer) {                 // read the file into the buffer
+            use fat32::traits::File;
+            self.raw.resize(file.size() as usize, 0u8);
+            let length = match file.read(&mut self.raw) {               // read the file into the vec
                 Ok(length) => {
                     length
                 },
-                Err(error)=> {
-                    kprintln!("Can't read file {:?}", error);
+                Err(_) => {
+                    self.raw.clear();
                     return 0;
                 }
             };
             file_length = length;
         }
-        for byte in buffer[..file_length].iter() {                  // iterate through buffer, read it in vec
-            self.raw.push(byte.clone());
-        }
+        self.raw.truncate(file_length);
         file_length
     }
 }
@@ -388,8 +389,7 @@ impl ProgHeader64 {
 
     // Parser Program header from ELF file. 
     // Index is the header table index.
-    // #Panic
-    // panic if index >= RawELFFile.e_phnum
+    // Returns Err if index >= RawELFFile.e_phnum or the entry lies outside of the file
     pub fn from(elf: &RawELFFile, index: usize) -> Result<ProgHeader64, usize> {
         let elfheader = match ELFHeader::from(elf) {
             Ok(header) => {header},
@@ -398,10 +398,19 @@ impl ProgHeader64 {
             }
         };
 
-        let start = elfheader.e_phoff as usize + index * size_of::<ProgHeader64>();
+        if index >= elfheader.e_phnum as usize {
+            return Err(0usize);
+        }
         let raw = elf.as_slice();
+        let (start, end) = match index.checked_mul(size_of::<ProgHeader64>())
+            .and_then(|offset| offset.checked_add(elfheader.e_phoff as usize))
+            .and_then(|start| Some((start, start.checked_add(size_of::<ProgHeader64>())?)))
+        {
+            Some((start, end)) if end <= raw.len() => (start, end),
+            _ => return Err(0usize), // header table runs past the end of the file
+        };
         let mut buffer = [0u8; size_of::<ProgHeader64>()];
-        buffer.copy_from_slice(&raw[start..(start + size_of::<ProgHeader64>())]);
+        buffer.copy_from_slice(&raw[start..end]);
 
         // buffer now has the program header in it
         // Parsing
@@ -411,7 +420,7 @@ impl ProgHeader64 {
             1 => true,
             2 => false,
             _ => {
-                panic!("EI_DATA not valid");
+                return Err(0usize);
             }
         };
 
@@ -451,8 +460,8 @@ impl ProgHeader64 {
         };
 
         program_header.p_align = match is_little {
-            true => u64::from_le_bytes([buffer[47], buffer[48], buffer[49], buffer[50], buffer[52], buffer[53], buffer[54], buffer[55]]),
-            false => u64::from_be_bytes([buffer[47], buffer[48], buffer[49], buffer[50], buffer[52], buffer[53], buffer[54], buffer[55]])
+            true => u64::from_le_bytes([buffer[48], buffer[49], buffer[50], buffer[51], buffer[52], buffer[53], buffer[54], buffer[55]]),
+            false => u64::from_be_bytes([buffer[48], buffer[49], buffer[50], buffer[51], buffer[52], buffer[53], buffer[54], buffer[55]])
         };
         Ok(program_header)
     }
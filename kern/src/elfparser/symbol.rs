@@ -130,7 +130,6 @@ impl SymbolTable {
         }
 
         let mut offset = symbol_string_table.sh_offset as usize;
-        kprintln!("0x{:x}", offset);
         let size = symbol_string_table.sh_size as usize;
         let mut buffer = Vec::new();
         let end = offset + size;
@@ -87,6 +87,14 @@ pub mod ProgHeaderType {
     pub const GNU_RELRO: u32 = 0x6474e552;
 }
 
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod ProgHeaderFlag {
+    pub const X: u32 = 0x1;
+    pub const W: u32 = 0x2;
+    pub const R: u32 = 0x4;
+}
+
 #[allow(non_snake_case)]
 #[allow(non_upper_case_globals)]
 pub mod SectionType {
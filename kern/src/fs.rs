@@ -1,6 +1,6 @@
 pub mod sd;
 
-use alloc::rc::Rc;
+use alloc::sync::Arc;
 use core::fmt::{self, Debug};
 use shim::io;
 use shim::path::Path;
@@ -10,13 +10,12 @@ use self::sd::Sd;
 use crate::mutex::Mutex;
 
 #[derive(Clone)]
-pub struct PiVFatHandle(Rc<Mutex<VFat<Self>>>);
+pub struct PiVFatHandle(Arc<Mutex<VFat<Self>>>);
 
-// These impls are *unsound*. We should use `Arc` instead of `Rc` to implement
-// `Sync` and `Send` trait for `PiVFatHandle`. However, `Arc` uses atomic memory
-// access, which requires MMU to be initialized on ARM architecture. Since we
-// have enabled only one core of the board, these unsound impls will not cause
-// any immediate harm for now. We will fix this in the future.
+// `Arc` uses atomic memory access, which requires MMU to be initialized on ARM
+// architecture: the file system is initialized once every core enabled its MMU.
+// `VFat` is not `Send` only because its block device is a `Box<dyn BlockDevice>`,
+// and the SD card driver may be used from any core as long as the mutex is held.
 unsafe impl Send for PiVFatHandle {}
 unsafe impl Sync for PiVFatHandle {}
 
@@ -28,7 +27,7 @@ impl Debug for PiVFatHandle {
 
 impl VFatHandle for PiVFatHandle {
     fn new(val: VFat<PiVFatHandle>) -> Self {
-        PiVFatHandle(Rc::new(Mutex::new(val)))
+        PiVFatHandle(Arc::new(Mutex::new(val)))
     }
 
     fn lock<R>(&self, f: impl FnOnce(&mut VFat<PiVFatHandle>) -> R) -> R {
@@ -60,6 +59,12 @@ impl FileSystem {
         *self.0.lock() = Some(vfat);
    } 
 
+    /// Writes the modified sectors of the file system cache to the disk.
+    pub fn flush(&self) -> io::Result<()> {
+        let vfat = self.get_vfat()?;
+        vfat.lock(|vfat| vfat.flush())
+    }
+
     fn get_vfat(&self) -> io::Result<PiVFatHandle>{
         match *self.0.lock() {
             Some(ref vfat) => {
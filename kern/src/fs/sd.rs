@@ -1,8 +1,11 @@
 
+use core::time::Duration;
 use shim::io;
 
 use fat32::traits::BlockDevice;
-use crate::console::{kprintln};
+use pi::timer::current_time;
+use crate::param::IO_BASE;
+
 extern "C" {
     /// A global representing the last SD controller error that occured.
     static sd_err: i64;
@@ -34,13 +37,44 @@ extern "C" {
 #[no_mangle]
 fn wait_micros(time: u32) {
     use crate::spin_sleep_ms;
-    kprintln!("init..");
     spin_sleep_ms((time * 10) as usize);
 }
 
+/// The EMMC controller registers `libsd` drives, which writes go through directly.
+const EMMC_BASE: usize = IO_BASE + 0x300000;
+const EMMC_BLKSIZECNT: *mut u32 = (EMMC_BASE + 0x04) as *mut u32;
+const EMMC_ARG1: *mut u32 = (EMMC_BASE + 0x08) as *mut u32;
+const EMMC_CMDTM: *mut u32 = (EMMC_BASE + 0x0c) as *mut u32;
+const EMMC_RESP0: *mut u32 = (EMMC_BASE + 0x10) as *mut u32;
+const EMMC_DATA: *mut u32 = (EMMC_BASE + 0x20) as *mut u32;
+const EMMC_STATUS: *mut u32 = (EMMC_BASE + 0x24) as *mut u32;
+const EMMC_INTERRUPT: *mut u32 = (EMMC_BASE + 0x30) as *mut u32;
+
+/// `CMD24`, WRITE_SINGLE_BLOCK, with a 48-bit response and a data transfer from
+/// the host.
+const CMD_WRITE_SINGLE: u32 = 0x18220000;
+/// The error bits of the card status in the response to a command.
+const CMD_ERRORS_MASK: u32 = 0xfff9c004;
+
+const SR_CMD_INHIBIT: u32 = 0x01;
+const SR_DAT_INHIBIT: u32 = 0x02;
+
+const INT_CMD_DONE: u32 = 0x01;
+const INT_DATA_DONE: u32 = 0x02;
+const INT_WRITE_RDY: u32 = 0x10;
+const INT_CMD_TIMEOUT: u32 = 0x10000;
+const INT_DATA_TIMEOUT: u32 = 0x100000;
+const INT_ERROR_MASK: u32 = 0x017e8000;
+
+/// How long the controller may take to accept a command or finish a transfer.
+const EMMC_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// A handle to an SD card controller.
 #[derive(Debug)]
-pub struct Sd;
+pub struct Sd {
+    /// Whether the card is addressed by block (SDHC and SDXC) rather than by byte.
+    block_addressed: bool,
+}
 
 impl Sd {
     /// Initializes the SD card controller and returns a handle to it.
@@ -51,7 +85,7 @@ impl Sd {
     pub unsafe fn new() -> Result<Sd, io::Error> {
         match sd_init() {
             0i32 => {
-                return Ok(Sd{});
+                return Ok(Sd { block_addressed: Sd::probe_addressing()? });
             },
             -1i32 => {
                 return Err(io::Error::new(io::ErrorKind::TimedOut, "SD init time out"));
@@ -64,6 +98,74 @@ impl Sd {
             }
         }
     }
+
+    /// Returns `true` if the card is addressed by block. `libsd` keeps what the card
+    /// reported at initialization to itself, but passes the address it computed from
+    /// the sector number as the argument of the read command: reading sector 1 leaves
+    /// 1 in `ARG1` for a block-addressed card and 512 for a byte-addressed one.
+    unsafe fn probe_addressing() -> io::Result<bool> {
+        let mut buf = [0u32; 128];
+        if sd_readsector(1, buf.as_mut_ptr() as *mut u8) == 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "SD init probe read error"));
+        }
+        Ok(EMMC_ARG1.read_volatile() == 1)
+    }
+
+    /// Waits until none of the `mask` bits of the status register are set.
+    unsafe fn wait_status(mask: u32) -> io::Result<()> {
+        let deadline = current_time() + EMMC_TIMEOUT;
+        while EMMC_STATUS.read_volatile() & mask != 0 {
+            if EMMC_INTERRUPT.read_volatile() & INT_ERROR_MASK != 0 {
+                return Err(io::Error::new(io::ErrorKind::Other, "SD controller error"));
+            }
+            if current_time() > deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "SD controller busy"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the `mask` interrupt of the controller and acknowledges it.
+    unsafe fn wait_interrupt(mask: u32) -> io::Result<()> {
+        let deadline = current_time() + EMMC_TIMEOUT;
+        loop {
+            let interrupt = EMMC_INTERRUPT.read_volatile();
+            if interrupt & (INT_CMD_TIMEOUT | INT_DATA_TIMEOUT) != 0 || current_time() > deadline {
+                EMMC_INTERRUPT.write_volatile(interrupt);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "SD command time out"));
+            }
+            if interrupt & INT_ERROR_MASK != 0 {
+                EMMC_INTERRUPT.write_volatile(interrupt);
+                return Err(io::Error::new(io::ErrorKind::Other, "SD command error"));
+            }
+            if interrupt & mask != 0 {
+                EMMC_INTERRUPT.write_volatile(mask);
+                return Ok(());
+            }
+        }
+    }
+
+    /// Writes the 512 bytes of `buf` to sector `n` with a single block write, the
+    /// way `libsd` reads one.
+    unsafe fn write_block(&mut self, n: u32, buf: &[u8]) -> io::Result<()> {
+        Sd::wait_status(SR_DAT_INHIBIT)?;
+        EMMC_BLKSIZECNT.write_volatile((1 << 16) | 512);
+
+        Sd::wait_status(SR_CMD_INHIBIT)?;
+        EMMC_INTERRUPT.write_volatile(EMMC_INTERRUPT.read_volatile());
+        EMMC_ARG1.write_volatile(if self.block_addressed { n } else { n << 9 });
+        EMMC_CMDTM.write_volatile(CMD_WRITE_SINGLE);
+        Sd::wait_interrupt(INT_CMD_DONE)?;
+        if EMMC_RESP0.read_volatile() & CMD_ERRORS_MASK != 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "SD write_sector card error"));
+        }
+
+        Sd::wait_interrupt(INT_WRITE_RDY)?;
+        for word in buf[..512].chunks(4) {
+            EMMC_DATA.write_volatile(u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+        }
+        Sd::wait_interrupt(INT_DATA_DONE)
+    }
 }
 
 impl BlockDevice for Sd {
@@ -106,7 +208,27 @@ impl BlockDevice for Sd {
         }
     }
 
-    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
-        unimplemented!("SD card and file system are read only")
+    /// Writes the first 512 bytes of `buf` to sector `n` of the SD card. On success,
+    /// the number of bytes written is returned.
+    ///
+    /// # Errors
+    ///
+    /// An I/O error of kind `InvalidInput` is returned if `buf.len() < 512` or `n`
+    /// can't be addressed on the card.
+    ///
+    /// An error of kind `TimedOut` is returned if a timeout occurs while
+    /// writing to the SD card.
+    ///
+    /// An error of kind `Other` is returned for all other errors.
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() < 512 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "buf len < 512"));
+        }
+        let limit = if self.block_addressed { (1 << 32) - 1 } else { (1 << 23) - 1 };
+        if n > limit {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid n"));
+        }
+        unsafe { self.write_block(n as u32, buf)? };
+        Ok(512)
     }
 }
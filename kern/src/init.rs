@@ -6,7 +6,8 @@ use core::ptr::write_volatile;
 mod oom;
 mod panic;
 
-use crate::kmain;
+use crate::{kmain, SCHEDULER, VMM};
+use crate::process::mark_core_stack;
 use crate::param::*;
 global_asm!(include_str!("init/vectors.s"));
 
@@ -105,5 +106,46 @@ unsafe fn kinit() -> ! {
     zeros_bss();
     switch_to_el2();
     switch_to_el1();
+    mark_core_stack();
     kmain();
 }
+
+/// The entry point of the application cores, which the spin table makes them
+/// jump to. Each core gets its own kernel stack below the one of core 0.
+#[no_mangle]
+unsafe extern "C" fn start2() -> ! {
+    let core = MPIDR_EL1.get_value(MPIDR_EL1::Aff0) as usize;
+    SP.set(KERN_STACK_BASE - KERN_STACK_SIZE * core);
+    kinit2()
+}
+
+#[no_mangle]
+unsafe fn kinit2() -> ! {
+    switch_to_el2();
+    switch_to_el1();
+    mark_core_stack();
+    kmain2()
+}
+
+unsafe fn kmain2() -> ! {
+    // tell core 0 that this core left the spin table
+    let core = MPIDR_EL1.get_value(MPIDR_EL1::Aff0) as usize;
+    SPINNING_BASE.add(core).write_volatile(0);
+
+    VMM.wait();
+    SCHEDULER.start()
+}
+
+/// Wakes the application cores up from the spin table of the firmware, where each
+/// core waits for an address to jump to, and waits until every core has left it.
+///
+/// The MMU must still be off, so that the cores see the writes to the spin table.
+pub unsafe fn initialize_app_cores() {
+    for core in 1..NCORES {
+        SPINNING_BASE.add(core).write_volatile(start2 as usize);
+    }
+    sev();
+    for core in 1..NCORES {
+        while SPINNING_BASE.add(core).read_volatile() != 0 {}
+    }
+}
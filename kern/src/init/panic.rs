@@ -1,9 +1,14 @@
 use core::panic::PanicInfo;
 
-use crate::console::{kprintln};
+use crate::backtrace;
+use crate::console::{kprintln, CONSOLE};
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    // the panic may come from a console write on this core, which would wait for
+    // itself below otherwise
+    unsafe { CONSOLE.force_unlock() };
+
     kprintln!("     (");
     kprintln!("    (      )     )");
     kprintln!("      )   (    (");
@@ -26,6 +31,15 @@ fn panic(info: &PanicInfo) -> ! {
             kprintln!("panic occurred but can't get location information...");
         }
     }
+    kprintln!("");
+
+    let (pc, fp): (u64, u64);
+    unsafe {
+        asm!("adr $0, .
+              mov $1, x29"
+             : "=r"(pc), "=r"(fp) ::: "volatile");
+    }
+    backtrace::print_kernel(pc, fp);
 
     loop {}
 }
@@ -0,0 +1,252 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::str::FromStr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use aarch64::affinity;
+use pi::timer::current_time;
+
+use crate::console::kprintln;
+use crate::mutex::Mutex;
+use crate::param::LOG_BUFFER_SIZE;
+
+#[cfg(test)]
+mod tests;
+
+/// The severity of a log record, the most severe first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    /// Returns the level whose number is `n`, if any.
+    fn from_usize(n: usize) -> Option<Level> {
+        match n {
+            1 => Some(Level::Error),
+            2 => Some(Level::Warn),
+            3 => Some(Level::Info),
+            4 => Some(Level::Debug),
+            5 => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the level.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    /// Parses the name of a level, or its number from 1 for `Error` to 5 for
+    /// `Trace`.
+    fn from_str(s: &str) -> Result<Level, ()> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => usize::from_str(s).ok().and_then(Level::from_usize).ok_or(()),
+        }
+    }
+}
+
+/// The longest record kept in the log, in bytes. Longer ones are truncated.
+const MAX_RECORD: usize = 256;
+
+/// A ring buffer of log records, one per line, each starting with the number of
+/// its level. Once it is full, the oldest bytes are overwritten.
+struct LogBuffer {
+    data: [u8; LOG_BUFFER_SIZE],
+    /// Where the next byte goes.
+    head: usize,
+    /// How many bytes are stored.
+    len: usize,
+    /// Whether bytes were overwritten, so that the oldest record may be partial.
+    overwritten: bool,
+}
+
+impl LogBuffer {
+    const fn new() -> LogBuffer {
+        LogBuffer {
+            data: [0; LOG_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+            overwritten: false,
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.data[self.head] = byte;
+        self.head = (self.head + 1) % LOG_BUFFER_SIZE;
+        if self.len == LOG_BUFFER_SIZE {
+            self.overwritten = true;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    /// Returns the stored bytes, oldest first.
+    fn bytes<'a>(&'a self) -> impl Iterator<Item = u8> + 'a {
+        let start = (self.head + LOG_BUFFER_SIZE - self.len) % LOG_BUFFER_SIZE;
+        (0..self.len).map(move |i| self.data[(start + i) % LOG_BUFFER_SIZE])
+    }
+
+    /// Appends a record of `level` with the text `args`.
+    fn write_record(&mut self, level: Level, args: fmt::Arguments) {
+        self.push(b'0' + level as u8);
+        let mut writer = RecordWriter { buffer: self, written: 0 };
+        let _ = writer.write_fmt(args);
+        self.push(b'\n');
+    }
+
+    /// Returns the records of `level` or more severe, oldest first.
+    fn records(&self, level: Level) -> Vec<(Level, String)> {
+        let mut records = Vec::new();
+        let mut line = Vec::new();
+        // the first line may be the end of a record that was overwritten
+        let mut skip = self.overwritten;
+        for byte in self.bytes() {
+            if byte != b'\n' {
+                line.push(byte);
+                continue;
+            }
+            if !skip {
+                let record = line.split_first().and_then(|(&number, text)| {
+                    let level = Level::from_usize(number.wrapping_sub(b'0') as usize)?;
+                    Some((level, String::from_utf8_lossy(text).into_owned()))
+                });
+                match record {
+                    Some((record_level, text)) if record_level <= level => records.push((record_level, text)),
+                    _ => {},
+                }
+            }
+            skip = false;
+            line.clear();
+        }
+        records
+    }
+}
+
+/// Writes the text of a record to a `LogBuffer`, keeping it on one line of at
+/// most `MAX_RECORD` bytes.
+struct RecordWriter<'a> {
+    buffer: &'a mut LogBuffer,
+    written: usize,
+}
+
+impl<'a> fmt::Write for RecordWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if self.written == MAX_RECORD {
+                break;
+            }
+            self.buffer.push(if byte == b'\n' { b' ' } else { byte });
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+/// The kernel log: every record is kept in a ring buffer of `LOG_BUFFER_SIZE`
+/// bytes, which `dmesg` shows, and the records of the console level or more
+/// severe are printed to the console as well.
+pub struct Logger {
+    buffer: Mutex<LogBuffer>,
+    /// The number of the least severe level printed to the console.
+    console_level: AtomicUsize,
+}
+
+impl Logger {
+    const fn new() -> Logger {
+        Logger {
+            buffer: Mutex::new(LogBuffer::new()),
+            console_level: AtomicUsize::new(Level::Info as usize),
+        }
+    }
+
+    /// Logs the message `args` at `level`, stamped with the time and the core.
+    pub fn log(&self, level: Level, args: fmt::Arguments) {
+        let now = current_time();
+        self.buffer.lock().write_record(level, format_args!("[{:>5}.{:06}] core{}: {}",
+            now.as_secs(), now.subsec_micros(), affinity(), args));
+        // the buffer is released before printing, so that the other cores log
+        // meanwhile
+        if level <= self.console_level() {
+            kprintln!("[{:>5}.{:06}] {}: {}", now.as_secs(), now.subsec_micros(), level.name(), args);
+        }
+    }
+
+    /// Returns the least severe level printed to the console.
+    pub fn console_level(&self) -> Level {
+        Level::from_usize(self.console_level.load(Ordering::Relaxed)).unwrap_or(Level::Info)
+    }
+
+    /// Prints the records of `level` and the more severe levels to the console
+    /// from now on. The others are only kept in the buffer.
+    pub fn set_console_level(&self, level: Level) {
+        self.console_level.store(level as usize, Ordering::Relaxed);
+    }
+
+    /// Returns the records in the buffer of `level` or more severe, oldest first.
+    pub fn records(&self, level: Level) -> Vec<(Level, String)> {
+        self.buffer.lock().records(level)
+    }
+
+    /// Empties the buffer.
+    pub fn clear(&self) {
+        let mut buffer = self.buffer.lock();
+        buffer.head = 0;
+        buffer.len = 0;
+        buffer.overwritten = false;
+    }
+}
+
+/// The kernel log.
+pub static LOGGER: Logger = Logger::new();
+
+/// Internal function called by the logging macros.
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) {
+    LOGGER.log(level, args);
+}
+
+/// Logs a message at the `Error` level, like `kprintln!` takes it.
+pub macro error($($arg:tt)*) {
+    _log(Level::Error, format_args!($($arg)*))
+}
+
+/// Logs a message at the `Warn` level, like `kprintln!` takes it.
+pub macro warn($($arg:tt)*) {
+    _log(Level::Warn, format_args!($($arg)*))
+}
+
+/// Logs a message at the `Info` level, like `kprintln!` takes it.
+pub macro info($($arg:tt)*) {
+    _log(Level::Info, format_args!($($arg)*))
+}
+
+/// Logs a message at the `Debug` level, like `kprintln!` takes it.
+pub macro debug($($arg:tt)*) {
+    _log(Level::Debug, format_args!($($arg)*))
+}
+
+/// Logs a message at the `Trace` level, like `kprintln!` takes it.
+pub macro trace($($arg:tt)*) {
+    _log(Level::Trace, format_args!($($arg)*))
+}
@@ -0,0 +1,164 @@
+mod level {
+    use core::str::FromStr;
+
+    use crate::log::Level;
+
+    #[test]
+    fn test_from_str_names() {
+        assert_eq!(Level::from_str("error"), Ok(Level::Error));
+        assert_eq!(Level::from_str("warn"), Ok(Level::Warn));
+        assert_eq!(Level::from_str("info"), Ok(Level::Info));
+        assert_eq!(Level::from_str("debug"), Ok(Level::Debug));
+        assert_eq!(Level::from_str("trace"), Ok(Level::Trace));
+    }
+
+    #[test]
+    fn test_from_str_numbers() {
+        assert_eq!(Level::from_str("1"), Ok(Level::Error));
+        assert_eq!(Level::from_str("2"), Ok(Level::Warn));
+        assert_eq!(Level::from_str("3"), Ok(Level::Info));
+        assert_eq!(Level::from_str("4"), Ok(Level::Debug));
+        assert_eq!(Level::from_str("5"), Ok(Level::Trace));
+    }
+
+    #[test]
+    fn test_from_str_invalid() {
+        assert_eq!(Level::from_str("0"), Err(()));
+        assert_eq!(Level::from_str("6"), Err(()));
+        assert_eq!(Level::from_str("-1"), Err(()));
+        assert_eq!(Level::from_str(""), Err(()));
+        assert_eq!(Level::from_str("Error"), Err(()));
+        assert_eq!(Level::from_str("verbose"), Err(()));
+    }
+}
+
+mod log_buffer {
+    use alloc::vec::Vec;
+
+    use crate::log::{Level, LogBuffer, MAX_RECORD};
+    use crate::param::LOG_BUFFER_SIZE;
+
+    #[test]
+    fn test_bytes_before_wrap() {
+        let mut buffer = LogBuffer::new();
+        for byte in b"abc" {
+            buffer.push(*byte);
+        }
+        assert_eq!(buffer.bytes().collect::<Vec<u8>>(), b"abc".to_vec());
+        assert_eq!(buffer.len, 3);
+        assert!(!buffer.overwritten);
+    }
+
+    #[test]
+    fn test_bytes_after_wrap() {
+        let mut buffer = LogBuffer::new();
+        let extra = 100;
+        for i in 0..LOG_BUFFER_SIZE + extra {
+            buffer.push(i as u8);
+        }
+        assert_eq!(buffer.len, LOG_BUFFER_SIZE);
+        assert_eq!(buffer.head, extra);
+        assert!(buffer.overwritten);
+
+        let bytes: Vec<u8> = buffer.bytes().collect();
+        let expected: Vec<u8> = (extra..LOG_BUFFER_SIZE + extra).map(|i| i as u8).collect();
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn test_full_without_overwrite() {
+        let mut buffer = LogBuffer::new();
+        for _ in 0..LOG_BUFFER_SIZE {
+            buffer.push(b'x');
+        }
+        assert_eq!(buffer.len, LOG_BUFFER_SIZE);
+        assert_eq!(buffer.head, 0);
+        assert!(!buffer.overwritten);
+
+        buffer.push(b'y');
+        assert!(buffer.overwritten);
+        assert_eq!(buffer.bytes().last(), Some(b'y'));
+    }
+
+    #[test]
+    fn test_records() {
+        let mut buffer = LogBuffer::new();
+        buffer.write_record(Level::Error, format_args!("first {}", 1));
+        buffer.write_record(Level::Debug, format_args!("second"));
+        buffer.write_record(Level::Info, format_args!("third"));
+
+        assert_eq!(buffer.records(Level::Trace), vec![
+            (Level::Error, "first 1".to_string()),
+            (Level::Debug, "second".to_string()),
+            (Level::Info, "third".to_string()),
+        ]);
+        assert_eq!(buffer.records(Level::Info), vec![
+            (Level::Error, "first 1".to_string()),
+            (Level::Info, "third".to_string()),
+        ]);
+        assert_eq!(buffer.records(Level::Error), vec![(Level::Error, "first 1".to_string())]);
+    }
+
+    #[test]
+    fn test_record_on_one_line() {
+        let mut buffer = LogBuffer::new();
+        buffer.write_record(Level::Warn, format_args!("two\nlines"));
+        assert_eq!(buffer.records(Level::Trace), vec![(Level::Warn, "two lines".to_string())]);
+    }
+
+    #[test]
+    fn test_record_truncated() {
+        let mut buffer = LogBuffer::new();
+        let long = "x".repeat(MAX_RECORD * 2);
+        buffer.write_record(Level::Info, format_args!("{}", long));
+        buffer.write_record(Level::Info, format_args!("next"));
+
+        let records = buffer.records(Level::Trace);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].1, "x".repeat(MAX_RECORD));
+        assert_eq!(records[1].1, "next");
+    }
+
+    #[test]
+    fn test_records_after_eviction() {
+        // each record is 15 bytes: the level, 13 bytes of text and the newline,
+        // so the oldest record left in the buffer is cut
+        const RECORD: usize = 15;
+        const COUNT: usize = 2000;
+
+        let mut buffer = LogBuffer::new();
+        for i in 0..COUNT {
+            let level = if i % 2 == 0 { Level::Error } else { Level::Debug };
+            buffer.write_record(level, format_args!("record {:06}", i));
+        }
+        assert!(buffer.overwritten);
+        assert_ne!(LOG_BUFFER_SIZE % RECORD, 0);
+
+        let whole = LOG_BUFFER_SIZE / RECORD;
+        let records = buffer.records(Level::Trace);
+        assert_eq!(records.len(), whole);
+        for (record, i) in records.iter().zip(COUNT - whole..COUNT) {
+            let level = if i % 2 == 0 { Level::Error } else { Level::Debug };
+            assert_eq!(*record, (level, format!("record {:06}", i)));
+        }
+
+        let errors = buffer.records(Level::Error);
+        assert!(errors.iter().all(|(level, _)| *level == Level::Error));
+        assert_eq!(errors.last().map(|(_, text)| text.as_str()), Some("record 001998"));
+    }
+
+    #[test]
+    fn test_partial_record_skipped() {
+        let mut buffer = LogBuffer::new();
+        // 110 bytes, whose last 9 look like a record of their own
+        buffer.write_record(Level::Error, format_args!("{}2partial", "a".repeat(100)));
+        for _ in 0..LOG_BUFFER_SIZE - 15 {
+            buffer.push(b'\n');
+        }
+        // 6 bytes, which overwrite all of the first record but "2partial\n"
+        buffer.write_record(Level::Warn, format_args!("kept"));
+
+        assert!(buffer.bytes().take(9).eq(b"2partial\n".iter().cloned()));
+        assert_eq!(buffer.records(Level::Trace), vec![(Level::Warn, "kept".to_string())]);
+    }
+}
@@ -14,8 +14,10 @@ mod init;
 
 extern crate alloc;
 pub mod allocator;
+pub mod backtrace;
 pub mod console;
 pub mod fs;
+pub mod log;
 pub mod mutex;
 pub mod shell;
 pub mod elfparser;
@@ -81,7 +83,6 @@ use fs::FileSystem;
 use process::GlobalScheduler;
 use traps::irq::Irq;
 use vm::VMManager;
-use crate::shell::shell;
 use elfparser::{ELF, SectionTable, SymbolTable, DynamicSymbolTable, GnuVersionReq, GnuVersion, RelaTable, RelaPLT, DynamicTable};
 #[cfg_attr(not(test), global_allocator)]
 pub static ALLOCATOR: Allocator = Allocator::uninitialized();
@@ -93,13 +94,15 @@ use shim::path::Path;
 fn kmain() -> ! {
     unsafe {
         ALLOCATOR.initialize();
-        FILESYSTEM.initialize();
         IRQ.initialize();
         VMM.initialize();
-        shell(">");
+        init::initialize_app_cores();
+        VMM.wait();
 
-        // SCHEDULER.initialize();
-        // SCHEDULER.start();
+        // atomic operations need the MMU
+        FILESYSTEM.initialize();
+        backtrace::initialize();
+        SCHEDULER.initialize();
+        SCHEDULER.start();
     }
-    loop {}
 }
\ No newline at end of file
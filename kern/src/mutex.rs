@@ -3,11 +3,24 @@ use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use core::cell::UnsafeCell;
 use core::ops::{DerefMut, Deref, Drop};
 
+use aarch64::{affinity, is_mmu_ready, DAIF};
+
+use crate::param::NCORES;
+
+/// A spinlock shared by the cores.
+///
+/// The lock is not re-entrant: a core locking a mutex it already holds panics.
+/// IRQs are masked on the core while it holds any lock (see `push_off()`), so that
+/// an interrupt handler never spins on a lock its own core holds.
+///
+/// Exclusive memory accesses need the MMU: until it is enabled on the current core,
+/// only that core is running and the lock is taken with plain loads and stores.
 #[repr(align(32))]
 pub struct Mutex<T> {
     data: UnsafeCell<T>,
     lock: AtomicBool,
-    owner: AtomicUsize
+    /// The core holding the lock, to tell a deadlock from contention.
+    owner: AtomicUsize,
 }
 
 unsafe impl<T: Send> Send for Mutex<T> { }
@@ -31,34 +44,59 @@ impl<T> Mutex<T> {
 }
 
 impl<T> Mutex<T> {
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
-        let this = 0;
-        if !self.lock.load(Ordering::Relaxed) || self.owner.load(Ordering::Relaxed) == this {
+        push_off();
+
+        let acquired = if is_mmu_ready() {
+            self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok()
+        } else if !self.lock.load(Ordering::Relaxed) {
             self.lock.store(true, Ordering::Relaxed);
-            self.owner.store(this, Ordering::Relaxed);
+            true
+        } else {
+            false
+        };
+
+        if acquired {
+            self.owner.store(affinity(), Ordering::Relaxed);
             Some(MutexGuard { lock: &self })
         } else {
+            pop_off();
             None
         }
     }
 
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
     #[inline(never)]
     pub fn lock(&self) -> MutexGuard<T> {
         // Wait until we can "aquire" the lock, then "acquire" it.
         loop {
             match self.try_lock() {
                 Some(guard) => return guard,
-                None => continue
+                None if self.is_held() => panic!("core {} locked a mutex it holds", affinity()),
+                None => core::sync::atomic::spin_loop_hint()
             }
         }
     }
 
+    /// Returns `true` if the current core holds the lock.
+    pub fn is_held(&self) -> bool {
+        // only this core sets the owner to this core
+        self.lock.load(Ordering::Relaxed) && self.owner.load(Ordering::Relaxed) == affinity()
+    }
+
+    /// Releases the lock held by the current core without a guard, for the panic
+    /// handler to print on a core that panicked while holding the console.
+    ///
+    /// The guard, if it is ever dropped, releases the lock again, so the data
+    /// must not be used after the panic handler is done.
+    pub unsafe fn force_unlock(&self) {
+        if self.is_held() {
+            self.unlock();
+        }
+    }
+
     fn unlock(&self) {
-        self.lock.store(false, Ordering::Relaxed);
+        self.owner.store(usize::max_value(), Ordering::Relaxed);
+        self.lock.store(false, Ordering::Release);
     }
 }
 
@@ -78,7 +116,8 @@ impl<'a, T: 'a> DerefMut for MutexGuard<'a, T> {
 
 impl<'a, T: 'a> Drop for MutexGuard<'a, T> {
     fn drop(&mut self) {
-        self.lock.unlock()
+        self.lock.unlock();
+        pop_off();
     }
 }
 
@@ -90,3 +129,40 @@ impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
         }
     }
 }
+
+/// How deep a core is in `push_off()` calls, and whether IRQs were unmasked
+/// before the outermost one.
+#[derive(Clone, Copy)]
+struct IrqState {
+    depth: usize,
+    unmasked: bool,
+}
+
+/// The `IrqState` of each core. A core only touches its own, with IRQs masked.
+static mut IRQ_STATES: [IrqState; NCORES] = [IrqState { depth: 0, unmasked: false }; NCORES];
+
+/// Masks IRQs on the current core until the matching `pop_off()`. Calls nest:
+/// IRQs are unmasked again by the outermost `pop_off()`, and only if they were
+/// unmasked before the outermost `push_off()`.
+pub fn push_off() {
+    let unmasked = unsafe { DAIF.get_value(DAIF::I) } == 0;
+    unsafe { aarch64::cli() };
+
+    let state = unsafe { &mut IRQ_STATES[affinity()] };
+    if state.depth == 0 {
+        state.unmasked = unmasked;
+    }
+    state.depth += 1;
+}
+
+/// Undoes a `push_off()` on the current core.
+pub fn pop_off() {
+    assert!(unsafe { DAIF.get_value(DAIF::I) } != 0, "pop_off with IRQs unmasked");
+
+    let state = unsafe { &mut IRQ_STATES[affinity()] };
+    assert!(state.depth > 0, "pop_off without push_off");
+    state.depth -= 1;
+    if state.depth == 0 && state.unmasked {
+        unsafe { aarch64::sti() };
+    }
+}
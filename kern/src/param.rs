@@ -19,10 +19,42 @@ const_assert_eq!(
 );
 
 pub const USER_STACK_BASE: usize = core::usize::MAX & PAGE_MASK; 
+/// The maximum size a user stack may grow to. The page below it is the guard page.
+pub const USER_STACK_MAX_SIZE: usize = 16 * PAGE_SIZE;
 pub const USER_MAX_VM_SIZE: usize = 0x4000_0000;
+/// Anonymous `mmap` mappings are placed from here up; the heap grows up below it.
+pub const USER_MMAP_BASE: usize = USER_IMG_BASE + USER_MAX_VM_SIZE / 2;
+/// The size of the user stack of a thread created by `thread_spawn`.
+pub const THREAD_STACK_SIZE: usize = 4 * PAGE_SIZE;
+const_assert_eq!(USER_STACK_MAX_SIZE % PAGE_SIZE, 0);
 const_assert_eq!(USER_STACK_BASE.wrapping_add(PAGE_SIZE - 16) % 16, 0);
 const_assert_eq!(USER_IMG_BASE.wrapping_add(USER_MAX_VM_SIZE), 0);
 pub const KERN_STACK_BASE: usize = 0x80_000;
+/// The lowest address of the kernel stacks. The page below holds the ATAGS and the
+/// spin tables the application cores are started from.
+pub const KERN_STACK_LIMIT: usize = PAGE_SIZE;
+/// The size of the kernel stack of each core, which share the memory between
+/// `KERN_STACK_LIMIT` and `KERN_STACK_BASE`. The stack of core `n` starts at
+/// `KERN_STACK_BASE - n * KERN_STACK_SIZE`, right above the one of core `n + 1`;
+/// there is no guard page in between.
+pub const KERN_STACK_SIZE: usize = (KERN_STACK_BASE - KERN_STACK_LIMIT) / NCORES;
+const_assert_eq!(KERN_STACK_SIZE % 16, 0);
+/// The CPU affinity mask of all the cores: bit `n` stands for core `n`.
+pub const ALL_CORES: u64 = (1 << NCORES) - 1;
+
+/// The maximum number of file descriptors a process may have open at once.
+pub const MAX_FDS: usize = 64;
+/// The number of bytes a pipe holds before writers have to wait for a reader.
+pub const PIPE_CAPACITY: usize = 4096;
+
+/// The size of the ring buffer of the kernel log, in bytes.
+pub const LOG_BUFFER_SIZE: usize = 16 * 1024;
+
+/// How long the init process sleeps when it has no children to collect.
+pub const INIT_REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the kernel writes the file system cache back to the disk.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
 
 /// The `tick` time.
 // FIXME: When you're ready, change this to something more reasonable.
@@ -1,11 +1,21 @@
+mod fd;
+mod pipe;
+mod policy;
 mod process;
 mod scheduler;
+mod signal;
+mod space;
 mod stack;
 mod state;
 
-pub use self::process::{Id, Process};
-pub use self::scheduler::GlobalScheduler;
-pub use self::stack::Stack;
+pub use self::fd::{Descriptor, FdTable};
+pub use self::pipe::{pipe, PipeReader, PipeWriter};
+pub use self::policy::{Fair, Policy, Priority, SchedEntity};
+pub use self::process::{Id, Process, WaitTarget, INIT_ID};
+pub use self::scheduler::{GlobalScheduler, ProcessInfo};
+pub use self::signal::{Action, Delivery, SignalFrame, Signals};
+pub use self::space::AddressSpace;
+pub use self::stack::{check_core_stack, mark_core_stack, Stack};
 pub use self::state::State;
 pub use crate::param::TICK;
 
@@ -0,0 +1,179 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use shim::io::{Read, Seek, SeekFrom};
+
+use fat32::traits::File;
+use kernel_api::{FileKind, OsError, OsResult, Stat};
+
+use crate::console::CONSOLE;
+use crate::fs::PiVFatHandle;
+use crate::mutex::Mutex;
+use crate::param::MAX_FDS;
+use crate::process::pipe::{PipeReader, PipeWriter};
+
+/// The object a file descriptor refers to.
+#[derive(Debug)]
+pub enum Descriptor {
+    /// The console. Reads come from and writes go to the UART.
+    Console,
+    /// A regular file of the FAT32 file system, opened read-only.
+    File(fat32::vfat::File<PiVFatHandle>),
+    /// The reading end of a pipe.
+    PipeRead(PipeReader),
+    /// The writing end of a pipe.
+    PipeWrite(PipeWriter),
+}
+
+impl Descriptor {
+    /// Returns `true` if a `read()` can't make progress yet: the descriptor is the
+    /// reading end of an empty pipe that still has writers, or the console and no
+    /// byte is available. Waiting for input with the console locked would hold up
+    /// the output of the other cores.
+    pub fn read_would_block(&self) -> bool {
+        match *self {
+            Descriptor::Console => !CONSOLE.lock().has_byte(),
+            Descriptor::PipeRead(ref pipe) => pipe.would_block(),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if a `write()` can't make progress yet: the descriptor is the
+    /// writing end of a full pipe that still has readers.
+    pub fn write_would_block(&self) -> bool {
+        match *self {
+            Descriptor::PipeWrite(ref pipe) => pipe.would_block(),
+            _ => false,
+        }
+    }
+
+    /// Reads bytes into `buf` and returns the number of bytes read, 0 at the end
+    /// of a file or of a pipe without writers. Reading from the console blocks until
+    /// a byte is available. The writing end of a pipe returns `NoAccess`.
+    pub fn read(&mut self, buf: &mut [u8]) -> OsResult<usize> {
+        match *self {
+            Descriptor::Console => {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+                buf[0] = CONSOLE.lock().read_byte();
+                Ok(1)
+            },
+            Descriptor::File(ref mut file) => Ok(file.read(buf)?),
+            Descriptor::PipeRead(ref mut pipe) => Ok(pipe.read(buf)),
+            Descriptor::PipeWrite(_) => Err(OsError::NoAccess),
+        }
+    }
+
+    /// Writes the bytes of `buf` and returns the number of bytes written. The console
+    /// stays locked for the whole buffer so that it isn't interleaved with other
+    /// output. Writes to a pipe may be short when it fills up. Files and the reading
+    /// end of a pipe return `NoAccess`.
+    pub fn write(&mut self, buf: &[u8]) -> OsResult<usize> {
+        match *self {
+            Descriptor::Console => {
+                let mut console = CONSOLE.lock();
+                for byte in buf.iter() {
+                    console.write_byte(*byte);
+                }
+                Ok(buf.len())
+            },
+            Descriptor::PipeWrite(ref mut pipe) => pipe.write(buf),
+            Descriptor::File(_) | Descriptor::PipeRead(_) => Err(OsError::NoAccess),
+        }
+    }
+
+    /// Moves the offset of a file to `pos` and returns the new offset. The console
+    /// and pipes can't seek and return `InvalidArgument`.
+    pub fn seek(&mut self, pos: SeekFrom) -> OsResult<u64> {
+        match *self {
+            Descriptor::File(ref mut file) => Ok(file.seek(pos)?),
+            _ => Err(OsError::InvalidArgument),
+        }
+    }
+
+    /// Returns the status of the object.
+    pub fn stat(&self) -> Stat {
+        match *self {
+            Descriptor::Console => Stat { size: 0, kind: FileKind::Console },
+            Descriptor::File(ref file) => Stat { size: file.size(), kind: FileKind::File },
+            Descriptor::PipeRead(ref pipe) => Stat { size: pipe.len() as u64, kind: FileKind::Pipe },
+            Descriptor::PipeWrite(ref pipe) => Stat { size: pipe.len() as u64, kind: FileKind::Pipe },
+        }
+    }
+}
+
+/// The open file descriptors of a process, indexed by descriptor number.
+/// Descriptors 0, 1 and 2 start out referring to the console. A descriptor is
+/// shared with the children created by `fork()`, including its file offset.
+#[derive(Debug, Clone)]
+pub struct FdTable {
+    entries: Vec<Option<Arc<Mutex<Descriptor>>>>,
+}
+
+impl FdTable {
+    /// Returns a new `FdTable` with standard input, output and error on the console.
+    pub fn new() -> FdTable {
+        let console = Arc::new(Mutex::new(Descriptor::Console));
+        let mut entries = Vec::new();
+        entries.push(Some(console.clone()));
+        entries.push(Some(console.clone()));
+        entries.push(Some(console));
+        FdTable { entries: entries }
+    }
+
+    /// Installs `descriptor` at the lowest free descriptor number and returns that
+    /// number. Returns `NoMemory` if the process already has `MAX_FDS` open descriptors.
+    pub fn insert(&mut self, descriptor: Descriptor) -> OsResult<usize> {
+        let descriptor = Some(Arc::new(Mutex::new(descriptor)));
+        match self.entries.iter().position(|entry| entry.is_none()) {
+            Some(fd) => {
+                self.entries[fd] = descriptor;
+                Ok(fd)
+            },
+            None if self.entries.len() < MAX_FDS => {
+                self.entries.push(descriptor);
+                Ok(self.entries.len() - 1)
+            },
+            None => Err(OsError::NoMemory),
+        }
+    }
+
+    /// Returns the object `fd` refers to, or `InvalidArgument` if `fd` is not open.
+    pub fn get(&self, fd: usize) -> OsResult<Arc<Mutex<Descriptor>>> {
+        match self.entries.get(fd) {
+            Some(Some(descriptor)) => Ok(descriptor.clone()),
+            _ => Err(OsError::InvalidArgument),
+        }
+    }
+
+    /// Makes `new_fd` refer to the same object as `old_fd`, closing `new_fd` first if
+    /// it is open, and returns `new_fd`. Returns `InvalidArgument` if `old_fd` is not
+    /// open or `new_fd` is not below `MAX_FDS`.
+    pub fn dup2(&mut self, old_fd: usize, new_fd: usize) -> OsResult<usize> {
+        let descriptor = self.get(old_fd)?;
+        if new_fd >= MAX_FDS {
+            return Err(OsError::InvalidArgument);
+        }
+        if new_fd >= self.entries.len() {
+            self.entries.resize(new_fd + 1, None);
+        }
+        self.entries[new_fd] = Some(descriptor);
+        Ok(new_fd)
+    }
+
+    /// Closes every descriptor, as when the process exits.
+    pub fn close_all(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Closes `fd`. Returns `InvalidArgument` if `fd` is not open.
+    pub fn close(&mut self, fd: usize) -> OsResult<()> {
+        match self.entries.get_mut(fd) {
+            Some(entry) if entry.is_some() => {
+                *entry = None;
+                Ok(())
+            },
+            _ => Err(OsError::InvalidArgument),
+        }
+    }
+}
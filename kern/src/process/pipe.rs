@@ -0,0 +1,110 @@
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::Arc;
+use core::fmt;
+
+use kernel_api::{OsError, OsResult};
+
+use crate::mutex::Mutex;
+use crate::param::PIPE_CAPACITY;
+
+/// A bounded queue of bytes written to by `PipeWriter`s and read from by
+/// `PipeReader`s. It counts the ends that are still open so that readers see
+/// the end of the stream once every writer is gone.
+struct Pipe {
+    buffer: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+}
+
+impl fmt::Debug for Pipe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pipe")
+            .field("len", &self.buffer.len())
+            .field("readers", &self.readers)
+            .field("writers", &self.writers)
+            .finish()
+    }
+}
+
+/// Returns the two ends of a new, empty pipe.
+pub fn pipe() -> (PipeReader, PipeWriter) {
+    let pipe = Arc::new(Mutex::new(Pipe {
+        buffer: VecDeque::with_capacity(PIPE_CAPACITY),
+        readers: 1,
+        writers: 1,
+    }));
+    (PipeReader(pipe.clone()), PipeWriter(pipe))
+}
+
+/// The reading end of a pipe.
+#[derive(Debug)]
+pub struct PipeReader(Arc<Mutex<Pipe>>);
+
+impl PipeReader {
+    /// Returns `true` if a read has to wait: the pipe is empty but may still be
+    /// written to.
+    pub fn would_block(&self) -> bool {
+        let pipe = self.0.lock();
+        pipe.buffer.is_empty() && pipe.writers > 0
+    }
+
+    /// Moves up to `buf.len()` bytes out of the pipe into `buf` and returns the
+    /// number of bytes read. Returns 0 if the pipe is empty.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut pipe = self.0.lock();
+        let count = core::cmp::min(buf.len(), pipe.buffer.len());
+        for (byte, data) in buf.iter_mut().zip(pipe.buffer.drain(..count)) {
+            *byte = data;
+        }
+        count
+    }
+
+    /// Returns the number of bytes waiting in the pipe.
+    pub fn len(&self) -> usize {
+        self.0.lock().buffer.len()
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        self.0.lock().readers -= 1;
+    }
+}
+
+/// The writing end of a pipe.
+#[derive(Debug)]
+pub struct PipeWriter(Arc<Mutex<Pipe>>);
+
+impl PipeWriter {
+    /// Returns `true` if a write has to wait: the pipe is full but may still be
+    /// read from.
+    pub fn would_block(&self) -> bool {
+        let pipe = self.0.lock();
+        pipe.buffer.len() == PIPE_CAPACITY && pipe.readers > 0
+    }
+
+    /// Appends as many bytes of `buf` as there is room for to the pipe and returns
+    /// the number of bytes written.
+    ///
+    /// Returns `BrokenPipe` if the reading end is closed.
+    pub fn write(&mut self, buf: &[u8]) -> OsResult<usize> {
+        let mut pipe = self.0.lock();
+        if pipe.readers == 0 {
+            return Err(OsError::BrokenPipe);
+        }
+        let count = core::cmp::min(buf.len(), PIPE_CAPACITY - pipe.buffer.len());
+        pipe.buffer.extend(buf[..count].iter());
+        Ok(count)
+    }
+
+    /// Returns the number of bytes waiting in the pipe.
+    pub fn len(&self) -> usize {
+        self.0.lock().buffer.len()
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.0.lock().writers -= 1;
+    }
+}
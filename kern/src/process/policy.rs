@@ -0,0 +1,177 @@
+use alloc::boxed::Box;
+use core::fmt;
+use core::time::Duration;
+
+use kernel_api::PRIO_MIN;
+
+use crate::process::Process;
+
+#[cfg(test)]
+mod tests;
+
+/// The scheduling bookkeeping of a process, maintained by the scheduler and its
+/// policy.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SchedEntity {
+    /// When the process was last switched in.
+    pub started: Duration,
+    /// The total time the process has run.
+    pub runtime: Duration,
+    /// The weighted run time of the process in nanoseconds, see `Fair`.
+    pub vruntime: u64,
+    /// When the process was added to the scheduler.
+    pub created: Duration,
+    /// How many times the process left the CPU because it blocked, slept or exited.
+    pub voluntary_switches: u64,
+    /// How many times the process left the CPU while it was still ready to run.
+    pub involuntary_switches: u64,
+}
+
+/// A scheduling policy: which of the ready processes runs next. Each run queue
+/// has its own policy; the scheduler keeps the queue, tells the policy about the
+/// processes added to it and how long each process ran, and lets `pick()` rank
+/// the processes that are ready.
+pub trait Policy: fmt::Debug + Send {
+    /// The name of the policy, for diagnostics.
+    fn name(&self) -> &'static str;
+
+    /// Prepares the bookkeeping of `process`, which was just added to the queue.
+    fn enqueue(&mut self, process: &mut Process);
+
+    /// Accounts for `process` having run for `ran` until it was switched out.
+    fn charge(&mut self, process: &mut Process, ran: Duration);
+
+    /// Returns the rank of `process`, which is ready to run. The process of the
+    /// least rank runs next, the first one in queue order among equal ranks.
+    fn rank(&mut self, process: &mut Process) -> u64;
+
+    /// Accounts for `process` having been picked to run next.
+    fn picked(&mut self, _process: &Process) {}
+}
+
+/// Picks the process `policy` runs next among the `ready` processes, given in
+/// queue order with their index in the queue. Returns the index of the process
+/// and the union of the affinities of the other ready processes, or `None` if
+/// `ready` is empty. Nothing is allocated.
+pub fn pick<'a, I>(policy: &mut dyn Policy, ready: I) -> Option<(usize, u64)>
+where
+    I: Iterator<Item = (usize, &'a mut Process)>,
+{
+    let mut next: Option<(usize, u64, &'a mut Process)> = None;
+    let mut others = 0;
+    for (index, process) in ready {
+        let rank = policy.rank(process);
+        match next {
+            Some((_, least, _)) if least <= rank => others |= process.affinity,
+            _ => {
+                if let Some((_, _, previous)) = next.replace((index, rank, process)) {
+                    others |= previous.affinity;
+                }
+            },
+        }
+    }
+    let (index, _, process) = next?;
+    policy.picked(process);
+    Some((index, others))
+}
+
+/// Returns the policy selected at build time: `Fair` with the `cfs` feature,
+/// `Priority` otherwise.
+pub fn default_policy() -> Box<dyn Policy> {
+    if cfg!(feature = "cfs") {
+        Box::new(Fair::new())
+    } else {
+        Box::new(Priority)
+    }
+}
+
+/// Strict priorities: the ready process with the lowest nice value runs, and
+/// processes of the same nice value take turns in queue order. Processes of a
+/// lower priority only run when no process of a higher one is ready.
+#[derive(Debug)]
+pub struct Priority;
+
+impl Policy for Priority {
+    fn name(&self) -> &'static str {
+        "priority"
+    }
+
+    fn enqueue(&mut self, _process: &mut Process) {}
+
+    fn charge(&mut self, _process: &mut Process, _ran: Duration) {}
+
+    fn rank(&mut self, process: &mut Process) -> u64 {
+        (process.nice as i64 - PRIO_MIN) as u64
+    }
+}
+
+/// The weight of a process of nice 0. A process of weight `w` accumulates virtual
+/// run time `NICE_0_WEIGHT / w` times as fast as its real run time.
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// The weights of nice values -20 to 19, each about 1.25 times the next one, so
+/// that one nice level is about a 10% difference in CPU share.
+const NICE_WEIGHTS: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291,
+    29154, 23254, 18705, 14949, 11916,
+    9548, 7620, 6100, 4904, 3906,
+    3121, 2501, 1991, 1586, 1277,
+    1024, 820, 655, 526, 423,
+    335, 272, 215, 172, 137,
+    110, 87, 70, 56, 45,
+    36, 29, 23, 18, 15,
+];
+
+/// How far behind the least virtual run time of the runnable processes a waking
+/// process may start, in nanoseconds, so that a long sleep doesn't earn it the CPU
+/// for as long.
+const SLEEPER_CREDIT: u64 = 20_000_000;
+
+/// Returns the weight of the nice value `nice`.
+fn weight(nice: i32) -> u64 {
+    let index = core::cmp::min(core::cmp::max(nice + 20, 0), 39);
+    NICE_WEIGHTS[index as usize]
+}
+
+/// A completely fair scheduler: the ready process with the least virtual run time
+/// runs. Virtual run time is real run time, measured with the system timer, scaled
+/// down by the weight of the nice value of the process, so that CPU time is shared
+/// in proportion to the weights.
+#[derive(Debug)]
+pub struct Fair {
+    /// The least virtual run time of the processes picked so far, which never
+    /// decreases. New processes start there.
+    min_vruntime: u64,
+}
+
+impl Fair {
+    /// Returns a new `Fair` policy.
+    pub fn new() -> Fair {
+        Fair { min_vruntime: 0 }
+    }
+}
+
+impl Policy for Fair {
+    fn name(&self) -> &'static str {
+        "cfs"
+    }
+
+    fn enqueue(&mut self, process: &mut Process) {
+        process.sched.vruntime = core::cmp::max(process.sched.vruntime, self.min_vruntime);
+    }
+
+    fn charge(&mut self, process: &mut Process, ran: Duration) {
+        let ns = ran.as_nanos() as u64;
+        process.sched.vruntime += ns.saturating_mul(NICE_0_WEIGHT) / weight(process.nice);
+    }
+
+    fn rank(&mut self, process: &mut Process) -> u64 {
+        let floor = self.min_vruntime.saturating_sub(SLEEPER_CREDIT);
+        process.sched.vruntime = core::cmp::max(process.sched.vruntime, floor);
+        process.sched.vruntime
+    }
+
+    fn picked(&mut self, process: &Process) {
+        self.min_vruntime = core::cmp::max(self.min_vruntime, process.sched.vruntime);
+    }
+}
@@ -0,0 +1,146 @@
+mod priority {
+    use crate::process::policy::{pick, Policy, Priority};
+    use crate::process::Process;
+
+    fn process(nice: i32) -> Process {
+        let mut process = Process::new().expect("process");
+        process.nice = nice;
+        process
+    }
+
+    #[test]
+    fn test_pick_lowest_nice() {
+        let mut queue = [process(0), process(5), process(-3), process(10)];
+        assert_eq!(pick(&mut Priority, queue.iter_mut().enumerate()).map(|(index, _)| index), Some(2));
+    }
+
+    #[test]
+    fn test_pick_queue_order_among_equals() {
+        let mut queue = [process(1), process(-2), process(-2), process(1)];
+        assert_eq!(pick(&mut Priority, queue.iter_mut().enumerate()).map(|(index, _)| index), Some(1));
+    }
+
+    #[test]
+    fn test_pick_others_affinity() {
+        let mut queue = [process(0), process(-1), process(0)];
+        queue[0].affinity = 0b0001;
+        queue[1].affinity = 0b0010;
+        queue[2].affinity = 0b0100;
+        assert_eq!(pick(&mut Priority, queue.iter_mut().enumerate()), Some((1, 0b0101)));
+    }
+
+    #[test]
+    fn test_pick_empty() {
+        let mut queue: [Process; 0] = [];
+        assert_eq!(pick(&mut Priority, queue.iter_mut().enumerate()), None);
+    }
+
+    #[test]
+    fn test_charge_keeps_order() {
+        let mut policy = Priority;
+        let mut queue = [process(0), process(0)];
+        policy.charge(&mut queue[0], core::time::Duration::from_secs(10));
+        assert_eq!(pick(&mut policy, queue.iter_mut().enumerate()).map(|(index, _)| index), Some(0));
+    }
+}
+
+mod fair {
+    use core::time::Duration;
+
+    use crate::process::policy::{pick, Fair, Policy, SLEEPER_CREDIT};
+    use crate::process::Process;
+
+    const MS: u64 = 1_000_000;
+
+    fn process(nice: i32, vruntime: u64) -> Process {
+        let mut process = Process::new().expect("process");
+        process.nice = nice;
+        process.sched.vruntime = vruntime;
+        process
+    }
+
+    #[test]
+    fn test_pick_least_vruntime() {
+        let mut policy = Fair::new();
+        let mut queue = [process(0, 30 * MS), process(0, 10 * MS), process(0, 20 * MS)];
+        assert_eq!(pick(&mut policy, queue.iter_mut().enumerate()).map(|(index, _)| index), Some(1));
+        assert_eq!(policy.min_vruntime, 10 * MS);
+    }
+
+    #[test]
+    fn test_pick_queue_order_among_equals() {
+        let mut policy = Fair::new();
+        let mut queue = [process(0, 20 * MS), process(0, 10 * MS), process(-5, 10 * MS)];
+        assert_eq!(pick(&mut policy, queue.iter_mut().enumerate()).map(|(index, _)| index), Some(1));
+    }
+
+    #[test]
+    fn test_charge_nice_0() {
+        let mut policy = Fair::new();
+        let mut process = process(0, 0);
+        policy.charge(&mut process, Duration::from_millis(3));
+        assert_eq!(process.sched.vruntime, 3 * MS);
+    }
+
+    #[test]
+    fn test_charge_weighted() {
+        let mut policy = Fair::new();
+        let mut high = process(-5, 0);
+        let mut low = process(5, 0);
+        policy.charge(&mut high, Duration::from_millis(10));
+        policy.charge(&mut low, Duration::from_millis(10));
+        assert_eq!(high.sched.vruntime, 10 * MS * 1024 / 3121);
+        assert_eq!(low.sched.vruntime, 10 * MS * 1024 / 335);
+    }
+
+    #[test]
+    fn test_charge_then_pick_other() {
+        let mut policy = Fair::new();
+        let mut queue = [process(0, 0), process(0, 0)];
+        assert_eq!(pick(&mut policy, queue.iter_mut().enumerate()).map(|(index, _)| index), Some(0));
+        policy.charge(&mut queue[0], Duration::from_millis(1));
+        assert_eq!(pick(&mut policy, queue.iter_mut().enumerate()).map(|(index, _)| index), Some(1));
+    }
+
+    #[test]
+    fn test_enqueue_starts_at_min_vruntime() {
+        let mut policy = Fair::new();
+        let mut queue = [process(0, 100 * MS)];
+        pick(&mut policy, queue.iter_mut().enumerate());
+
+        let mut new = process(0, 0);
+        policy.enqueue(&mut new);
+        assert_eq!(new.sched.vruntime, 100 * MS);
+
+        let mut ahead = process(0, 200 * MS);
+        policy.enqueue(&mut ahead);
+        assert_eq!(ahead.sched.vruntime, 200 * MS);
+    }
+
+    #[test]
+    fn test_woken_process_clamped() {
+        let mut policy = Fair::new();
+        let mut queue = [process(0, 100 * MS)];
+        pick(&mut policy, queue.iter_mut().enumerate());
+
+        // slept for long: starts no further than SLEEPER_CREDIT behind
+        let mut queue = [process(0, 100 * MS), process(0, 0)];
+        assert_eq!(pick(&mut policy, queue.iter_mut().enumerate()).map(|(index, _)| index), Some(1));
+        assert_eq!(queue[1].sched.vruntime, 100 * MS - SLEEPER_CREDIT);
+
+        // slept briefly: keeps its own virtual run time
+        let mut queue = [process(0, 100 * MS), process(0, 95 * MS)];
+        pick(&mut policy, queue.iter_mut().enumerate());
+        assert_eq!(queue[1].sched.vruntime, 95 * MS);
+    }
+
+    #[test]
+    fn test_min_vruntime_never_decreases() {
+        let mut policy = Fair::new();
+        let mut queue = [process(0, 50 * MS)];
+        pick(&mut policy, queue.iter_mut().enumerate());
+        let mut queue = [process(0, 40 * MS)];
+        pick(&mut policy, queue.iter_mut().enumerate());
+        assert_eq!(policy.min_vruntime, 50 * MS);
+    }
+}
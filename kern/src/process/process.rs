@@ -1,31 +1,96 @@
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use shim::path::{Path, PathBuf};
 
 use crate::FILESYSTEM;
+use crate::backtrace::Symbolizer;
+use crate::fs::PiVFatHandle;
+use crate::mutex::Mutex;
 use fat32::traits::FileSystem;
 use fat32::traits::Entry;
 use crate::param::*;
-use crate::process::{State};
+use crate::process::{AddressSpace, FdTable, SchedEntity, Stack, State};
+use crate::process::signal::{Delivery, SignalFrame, Signals};
 use crate::traps::TrapFrame;
 use crate::vm::*;
-use kernel_api::{OsResult};
-use crate::console::{kprintln};
-use crate::elfparser::{ELF};
+use kernel_api::{OsError, OsResult, Rusage, SIGCONT, SIGKILL, SIGSEGV};
+use core::time::Duration;
+use crate::log::{debug, error, warn};
+use crate::elfparser::{ELF, ELFHeader, ProgHeader64};
+use crate::elfparser::{FileHeaderClass, FileHeaderMachine, FileHeaderType, ProgHeaderType, ProgHeaderFlag};
+
+/// The condition flags (`NZCV`) of `SPSR_EL1`, the only bits user code may change.
+const SPSR_FLAGS: u64 = 0xf000_0000;
+
+/// The `SPSR_EL1` of a kernel thread: EL1 using `SP_EL0`, so that its stack is saved
+/// and restored with its trap frame, with IRQs unmasked so that it is preempted.
+/// A kernel lock masks them while the thread holds it.
+const SPSR_EL1T: u64 = 0b1101000100;
 
 /// Type alias for the type of a process ID.
 pub type Id = u64;
 
+/// The ID of the init process, a kernel thread which adopts the children of exiting
+/// processes and collects them once they exit.
+pub const INIT_ID: Id = 1;
+
+/// The children a process blocked in `wait` is waiting for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WaitTarget {
+    /// Any child of the process.
+    Any,
+    /// The child with the given ID.
+    Child(Id),
+}
+
 /// A structure that represents the complete state of a process.
 #[derive(Debug)]
 pub struct Process {
+    /// The name of the process, the file name of the program it runs.
+    pub name: String,
+    /// The symbols of the program the process runs, read when it is loaded to
+    /// name the functions of its backtraces. `None` for kernel threads and
+    /// stripped programs.
+    pub symbols: Option<Arc<Symbolizer>>,
     /// The saved trap frame of a process.
     pub context: Box<TrapFrame>,
-    /// The memory allocation used for the process's stack.
-    //pub stack: Stack,
-    /// The page table describing the Virtual Memory of the process
-    pub vmap: Box<UserPageTable>,
+    /// The memory allocation used for the stack of a kernel thread.
+    pub stack: Option<Stack>,
+    /// The user address space, shared with the other threads of the process.
+    pub space: Arc<Mutex<AddressSpace>>,
     /// The scheduling state of the process.
     pub state: State,
+    /// The ID of the parent process, `None` if the process was started by the kernel.
+    pub parent: Option<Id>,
+    /// The IDs of the children of the process that haven't been collected yet.
+    pub children: Vec<Id>,
+    /// The children the process is blocked in `wait` for, if any.
+    pub wait_target: Option<WaitTarget>,
+    /// The open file descriptors of the process, shared with its other threads.
+    pub files: Arc<Mutex<FdTable>>,
+    /// The signal actions, pending and blocked signals of the process.
+    pub signals: Signals,
+    /// Whether the process was stopped by a signal. It is not scheduled until it
+    /// receives `SIGCONT`.
+    pub stopped: bool,
+    /// The ID of the main thread of the process if this is another of its threads,
+    /// `None` for the main thread, whose ID is the process ID.
+    pub leader: Option<Id>,
+    /// The thread this thread is blocked in `thread_join` for, if any.
+    pub join_target: Option<Id>,
+    /// The address and the size of the user stack of a thread other than the main one.
+    pub thread_stack: Option<(usize, usize)>,
+    /// The user address of the futex the thread is parked at in `futex_wait`, if any.
+    pub futex: Option<usize>,
+    /// The nice value of the process, from `PRIO_MIN` to `PRIO_MAX`. The scheduler
+    /// runs the ready process with the lowest one.
+    pub nice: i32,
+    /// The cores the process may run on, bit `n` standing for core `n`.
+    pub affinity: u64,
+    /// The scheduling bookkeeping of the process.
+    pub sched: SchedEntity,
 }
 
 impl Process {
@@ -42,19 +107,33 @@ impl Process {
         //         return Err(OsError::from(20)); // no memory errror
         //     }
         // };
-        let vmap = Box::new(UserPageTable::new());
         return Ok(Process {
+            name : String::new(),
+            symbols : None,
             context : tf,
-            //stack : stack,
-            vmap : vmap,
-            state : State::Ready
+            stack : None,
+            space : Arc::new(Mutex::new(AddressSpace::new())),
+            state : State::Ready,
+            parent : None,
+            children : Vec::new(),
+            wait_target : None,
+            files : Arc::new(Mutex::new(FdTable::new())),
+            signals : Signals::new(),
+            stopped : false,
+            leader : None,
+            join_target : None,
+            thread_stack : None,
+            futex : None,
+            nice : 0,
+            affinity : ALL_CORES,
+            sched : SchedEntity::default(),
         });
     }
 
-    /// Load a program stored in the given path by calling `do_load()` method.
+    /// Load a program stored in the given path by calling `load_elf()` method.
     /// Set trapframe `context` corresponding to the its page table.
     /// `sp` - the address of stack top
-    /// `elr` - the address of image base.
+    /// `elr` - the entry point of the image, set by `load_elf()`.
     /// `ttbr0` - the base address of kernel page table
     /// `ttbr1` - the base address of user page table
     /// `spsr` - `F`, `A`, `D` bit should be set.
@@ -64,11 +143,12 @@ impl Process {
         use crate::VMM;
 
         //let mut p = Process::do_load(pn)?;
+        let name: String = pn.as_ref().file_name().and_then(|name| name.to_str()).unwrap_or("?").into();
         let mut p = Process::load_elf(pn)?;
+        p.name = name;
         p.context.sp = Process::get_stack_top().as_u64();
-        p.context.elr = (USER_IMG_BASE) as u64;
         p.context.ttbr0 = VMM.get_baddr().as_u64();
-        p.context.ttbr1 = p.vmap.get_baddr().as_u64();
+        p.context.ttbr1 = p.space.lock().vmap.get_baddr().as_u64();
         p.context.spsr = 0b1101000000;
         Ok(p)
     }
@@ -78,15 +158,16 @@ impl Process {
     /// permission to load file's contents.
     fn do_load<P: AsRef<Path>>(pn: P) -> OsResult<Process> {
         let mut process = Process::new().unwrap();
-        let user_stack = process.vmap.alloc(VirtualAddr::from(USER_STACK_BASE), PagePerm::RW);
+        let mut space = process.space.lock();
+        let user_stack = space.vmap.alloc(VirtualAddr::from(USER_STACK_BASE), PagePerm::RW)?;
         
         let mut working_dir = PathBuf::from("/");
-        let mut page = process.vmap.alloc(VirtualAddr::from(USER_IMG_BASE as u64), PagePerm::RWX);
+        let mut page = space.vmap.alloc(VirtualAddr::from(USER_IMG_BASE as u64), PagePerm::RWX)?;
         let mut dir = working_dir.clone();
         dir.push(pn);
         let entry = FILESYSTEM.open(dir.as_path());
         if entry.is_err() {
-            kprintln!("Path not found");
+            error!("Path not found");
             panic!("Path not found");
         }
 
@@ -101,40 +182,459 @@ impl Process {
                     length
                 },
                 Err(error)=> {
-                    kprintln!("Can't read file {:?}", error);
+                    error!("Can't read file {:?}", error);
                     0usize
                 }
             };
             page.copy_from_slice(&buffer[..PAGE_SIZE]);
-            kprintln!("done! Copied {}", length);
+            debug!("done! Copied {}", length);
             file_length = length;
         }
-        kprintln!("file length {} vs PAGESIZE: {}", file_length, PAGE_SIZE);
-        let mut page2 = process.vmap.alloc(VirtualAddr::from((USER_IMG_BASE + PAGE_SIZE) as u64), PagePerm::RWX);
+        debug!("file length {} vs PAGESIZE: {}", file_length, PAGE_SIZE);
+        let mut page2 = space.vmap.alloc(VirtualAddr::from((USER_IMG_BASE + PAGE_SIZE) as u64), PagePerm::RWX)?;
         page2.copy_from_slice(&buffer[PAGE_SIZE..file_length]);
+        drop(space);
         Ok(process)  
     }
 
-    // Load ELF, use this instead of do_load if we are working with elf files
+    /// Creates a process and loads the ELF executable stored at the given path into it.
+    ///
+    /// Every `PT_LOAD` segment becomes a region at its requested `p_vaddr` spanning
+    /// `p_memsz` bytes, whose first `p_filesz` bytes come from the file and the rest
+    /// (.bss) is zero-filled. The stack starts as a single page and grows down up to
+    /// `USER_STACK_MAX_SIZE`. Pages of both are mapped on first touch by `handle_fault()`.
+    /// The `elr` of the process is set to `e_entry`.
+    ///
+    /// Returns `NoEntry` if the file can't be read, `IoErrorInvalidData` if it is not an
+    /// AArch64 executable or one of its headers is malformed, and `NoVmSpace` if a
+    /// segment does not fit in the user address space.
     pub fn load_elf<P: AsRef<Path>>(pn: P) -> OsResult<Process> {
         let mut elf = ELF::new();
-        elf.initialize_to_execute(pn);
-        
-        // Extract binary code into a u8 array
-        let binary = elf.binary();
-        let b = &binary; // b: &Vec<u8>
-        let buffer: &[u8] = &b; // c: &[u8]
+        if !elf.initialize_to_execute(pn) {
+            if elf.raw.is_empty() {
+                return Err(OsError::NoEntry);
+            }
+            return Err(OsError::IoErrorInvalidData);
+        }
+        Process::check_header(&elf.header)?;
 
         // create process
-        let mut process = Process::new().unwrap();
-        let user_stack = process.vmap.alloc(VirtualAddr::from(USER_STACK_BASE), PagePerm::RW);
+        let mut process = Process::new()?;
+        process.symbols = Symbolizer::read(&elf.raw).map(Arc::new);
+        let mut space = process.space.lock();
+        space.regions.push(Region::new(USER_STACK_BASE, PAGE_SIZE, PagePerm::RW, Backing::Stack));
 
-        // allocate page and copy the executable binary into the page
-        let mut page = process.vmap.alloc(VirtualAddr::from((USER_IMG_BASE) as u64), PagePerm::RWX);
-        page[..binary.capacity()].copy_from_slice(&buffer[..binary.capacity()]);
+        let data = Arc::new(core::mem::replace(&mut elf.raw.raw, Vec::new()));
+        let entry = elf.header.e_entry as usize;
+        let mut entry_loaded = false;
+        for program_header in elf.header_table.iter() {
+            if program_header.p_type != ProgHeaderType::LOAD || program_header.p_memsz == 0 {
+                continue;
+            }
+            let region = Process::segment_region(&data, program_header)?;
+            if region.contains(entry) && region.perm.is_executable() {
+                entry_loaded = true;
+            }
+            space.regions.push(region);
+        }
+        if !entry_loaded {
+            warn!("Entry point 0x{:x} is not inside an executable segment", entry);
+            return Err(OsError::IoErrorInvalidData);
+        }
+
+        // the heap starts empty on the page after the image
+        let image_end = space.regions.iter()
+            .filter(|region| match region.backing { Backing::Elf { .. } => true, _ => false })
+            .map(|region| region.start + region.size)
+            .max()
+            .unwrap_or(USER_IMG_BASE);
+        space.heap_start = (image_end + (PAGE_SIZE - 1)) & PAGE_MASK;
+        space.brk = space.heap_start;
+        let heap_start = space.heap_start;
+        space.regions.push(Region::new(heap_start, 0, PagePerm::RW, Backing::Heap));
+        drop(space);
+
+        process.context.elr = entry as u64;
         Ok(process)
     }
 
+    /// Checks that `header` describes a 64-bit AArch64 executable.
+    fn check_header(header: &ELFHeader) -> OsResult<()> {
+        if header.ei_mag != [0x7f, b'E', b'L', b'F']
+            || header.ei_class != FileHeaderClass::ELF64
+            || header.e_machine != FileHeaderMachine::AArch64
+            || header.e_type != FileHeaderType::EXEC {
+            return Err(OsError::IoErrorInvalidData);
+        }
+        Ok(())
+    }
+
+    /// Validates the `PT_LOAD` segment described by `program_header` against the raw
+    /// ELF file `data` and returns the region it should be loaded into.
+    fn segment_region(data: &Arc<Vec<u8>>, program_header: &ProgHeader64) -> OsResult<Region> {
+        let file_start = program_header.p_offset as usize;
+        let file_size = program_header.p_filesz as usize;
+        if program_header.p_filesz > program_header.p_memsz
+            || file_start.checked_add(file_size).map_or(true, |end| end > data.len()) {
+            return Err(OsError::IoErrorInvalidData);
+        }
+
+        let start = program_header.p_vaddr as usize;
+        let size = program_header.p_memsz as usize;
+        match start.checked_add(size) {
+            Some(end) if start >= USER_IMG_BASE && end <= Process::get_stack_guard().as_usize() => {},
+            _ => return Err(OsError::NoVmSpace),
+        }
+
+        let backing = Backing::Elf {
+            data: data.clone(),
+            offset: file_start,
+            file_size: file_size,
+        };
+        Ok(Region::new(start, size, Process::segment_perm(program_header.p_flags), backing))
+    }
+
+    /// Returns a copy of this process for `fork()`, whose trap frame is `tf`. The
+    /// pages of the child are shared copy-on-write with this process. The child
+    /// returns 0 from the system call; its ID is assigned when it is added to the
+    /// scheduler, after which the caller records it in `children`.
+    pub fn fork(&mut self, tf: &TrapFrame) -> OsResult<Process> {
+        let mut child = Process::new()?;
+        child.name = self.name.clone();
+        child.symbols = self.symbols.clone();
+        child.parent = Some(self.context.tpidr);
+        child.space = Arc::new(Mutex::new(self.space.lock().fork()));
+        child.files = Arc::new(Mutex::new(self.files.lock().clone()));
+        child.signals = self.signals.fork();
+        child.nice = self.nice;
+        child.affinity = self.affinity;
+        *child.context = *tf;
+        child.context.ttbr1 = child.space.lock().vmap.get_baddr().as_u64();
+        child.context.x0 = 0;
+        child.context.x7 = 1;
+        Ok(child)
+    }
+
+    /// Returns a new thread of this process, whose trap frame is `tf`, that starts at
+    /// the user address `entry` with `arg` in `x0`, on a new stack of
+    /// `THREAD_STACK_SIZE` bytes mapped in the address space. The thread shares the
+    /// address space and the file descriptors of this process and inherits its signal
+    /// actions, its nice value and its affinity. When `entry` returns, the thread
+    /// jumps to `exit`, which is expected to issue `thread_exit`. Its ID is assigned
+    /// when it is added to the scheduler.
+    ///
+    /// Returns `NoVmSpace` if there is no room for the stack.
+    pub fn spawn_thread(&mut self, tf: &TrapFrame, entry: u64, arg: u64, exit: u64) -> OsResult<Process> {
+        let stack = self.space.lock().mmap(0, THREAD_STACK_SIZE, PagePerm::RW)?;
+        let mut thread = Process {
+            name : self.name.clone(),
+            symbols : self.symbols.clone(),
+            context : Box::new(TrapFrame::default()),
+            stack : None,
+            space : self.space.clone(),
+            state : State::Ready,
+            parent : None,
+            children : Vec::new(),
+            wait_target : None,
+            files : self.files.clone(),
+            signals : self.signals.fork(),
+            stopped : false,
+            leader : Some(self.pid()),
+            join_target : None,
+            thread_stack : Some((stack, THREAD_STACK_SIZE)),
+            futex : None,
+            nice : self.nice,
+            affinity : self.affinity,
+            sched : SchedEntity::default(),
+        };
+        thread.context.elr = entry;
+        thread.context.x0 = arg;
+        thread.context.x30lr = exit;
+        thread.context.sp = (stack + THREAD_STACK_SIZE) as u64;
+        thread.context.ttbr0 = tf.ttbr0;
+        thread.context.ttbr1 = tf.ttbr1;
+        thread.context.spsr = tf.spsr & !SPSR_FLAGS;
+        Ok(thread)
+    }
+
+    /// Returns a kernel thread running `f` at EL1 on a stack of its own, with an empty
+    /// user address space. It exits when `f` returns. Its ID is assigned when it is
+    /// added to the scheduler.
+    ///
+    /// Returns `NoMemory` if the stack can't be allocated.
+    pub fn kernel_thread(name: &str, f: Box<dyn FnOnce() + Send>) -> OsResult<Process> {
+        use crate::VMM;
+
+        let stack = Stack::new().ok_or(OsError::NoMemory)?;
+        let mut thread = Process::new()?;
+        thread.name = String::from(name);
+        thread.context.elr = kernel_thread_start as usize as u64;
+        thread.context.x0 = Box::into_raw(Box::new(f)) as u64;
+        thread.context.sp = stack.top().as_u64();
+        thread.context.ttbr0 = VMM.get_baddr().as_u64();
+        thread.context.ttbr1 = thread.space.lock().vmap.get_baddr().as_u64();
+        thread.context.spsr = SPSR_EL1T;
+        thread.stack = Some(stack);
+        Ok(thread)
+    }
+
+    /// Returns `true` if this is a kernel thread, which runs at EL1 and never takes
+    /// signals, since they are delivered on the way back to user space.
+    pub fn is_kernel_thread(&self) -> bool {
+        self.stack.is_some()
+    }
+
+    /// Returns `true` if the affinity of this process allows it to run on `core`.
+    pub fn may_run_on(&self, core: usize) -> bool {
+        self.affinity & (1 << core) != 0
+    }
+
+    /// Returns the CPU time this process has used until `now`, including its
+    /// current run if it is running.
+    pub fn cpu_time(&self, now: Duration) -> Duration {
+        match self.state {
+            State::Running => self.sched.runtime + (now - self.sched.started),
+            _ => self.sched.runtime,
+        }
+    }
+
+    /// Returns the resource usage of this process at `now`.
+    pub fn usage(&self, now: Duration) -> Rusage {
+        Rusage {
+            cpu_time: self.cpu_time(now).as_micros() as u64,
+            start_time: self.sched.created.as_micros() as u64,
+            voluntary_switches: self.sched.voluntary_switches,
+            involuntary_switches: self.sched.involuntary_switches,
+            pages: self.space.lock().vmap.mapped_pages() as u64,
+        }
+    }
+
+    /// Returns a short name of the state of this process, for listings.
+    pub fn state_name(&self) -> &'static str {
+        match self.state {
+            State::Zombie(_) => "zombie",
+            State::Dead => "dead",
+            _ if self.stopped => "stopped",
+            State::Ready => "ready",
+            State::Running => "run",
+            State::Waiting(_) | State::Parked(_) => "wait",
+            State::Sleeping(_) => "sleep",
+        }
+    }
+
+    /// Returns the process ID of this thread, the ID of the main thread of its process.
+    pub fn pid(&self) -> Id {
+        self.leader.unwrap_or(self.context.tpidr)
+    }
+
+    /// Completes the `thread_join` of this thread with the thread it joins, which
+    /// exited with status `code`: the system call returns the status.
+    pub fn collect_thread(&mut self, code: i32) {
+        self.join_target = None;
+        self.context.x0 = code as u64;
+        self.context.x7 = 1;
+    }
+
+    /// Returns `true` if this process is blocked in `wait` for its child `id`.
+    pub fn waits_for(&self, id: Id) -> bool {
+        match self.wait_target {
+            Some(WaitTarget::Any) => true,
+            Some(WaitTarget::Child(child)) => child == id,
+            None => false,
+        }
+    }
+
+    /// Completes the `wait` of this process with the child `id` that exited with
+    /// status `code`: the system call returns the ID of the child and its status.
+    pub fn collect_child(&mut self, id: Id, code: i32) {
+        self.wait_target = None;
+        self.children.retain(|child| *child != id);
+        self.context.x0 = id;
+        self.context.x1 = code as u64;
+        self.context.x7 = 1;
+    }
+
+    /// Replaces the image of this process with `image`, a process freshly loaded by
+    /// `load()`, keeping the ID of this process. Returns the old address space, for
+    /// the caller to drop once it holds no lock: dropping it releases its pages and
+    /// writes its shared file mappings back.
+    pub fn exec(&mut self, image: Process) -> Arc<Mutex<AddressSpace>> {
+        let id = self.context.tpidr;
+        self.name = image.name;
+        self.symbols = image.symbols;
+        let space = core::mem::replace(&mut self.space, image.space);
+        self.signals.reset_handlers();
+        *self.context = *image.context;
+        self.context.tpidr = id;
+        space
+    }
+
+    /// Sends the signal `sig` to this process. `SIGKILL` and `SIGCONT` resume a
+    /// stopped process right away; other signals are delivered by `deliver_signal()`
+    /// when the process next returns to user space.
+    pub fn send_signal(&mut self, sig: u64) {
+        if sig == SIGKILL || sig == SIGCONT {
+            self.stopped = false;
+        }
+        self.signals.raise(sig);
+    }
+
+    /// Delivers the pending signals of this process, which is about to return to user
+    /// space with the trap frame `tf`. A caught signal saves `tf` in a `SignalFrame`
+    /// on the user stack and makes `tf` enter the handler with the signal number in
+    /// `x0`, the signal blocked and `restorer` as the return address. Signals whose
+    /// action is to terminate or stop the process are returned to the caller, with
+    /// `stopped` already set for the latter.
+    ///
+    /// If the signal frame can't be written, the process is terminated as by `SIGSEGV`.
+    pub fn deliver_signal(&mut self, tf: &mut TrapFrame) -> Option<Delivery> {
+        match self.signals.take()? {
+            Delivery::Handle { sig, handler, restorer } => {
+                let frame = SignalFrame {
+                    context: *tf,
+                    blocked: self.signals.blocked(),
+                    sig: sig,
+                };
+                let size = core::mem::size_of::<SignalFrame>() as u64;
+                let sp = match tf.sp.checked_sub(size) {
+                    Some(sp) => sp & !0b1111,
+                    None => return Some(Delivery::Terminate(SIGSEGV)),
+                };
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(&frame as *const SignalFrame as *const u8, size as usize)
+                };
+                if self.copy_to_user(sp as usize, bytes).is_err() {
+                    return Some(Delivery::Terminate(SIGSEGV));
+                }
+                let blocked = self.signals.blocked() | (1 << sig);
+                self.signals.set_blocked(blocked);
+                tf.sp = sp;
+                tf.elr = handler;
+                tf.x30lr = restorer;
+                tf.x0 = sig;
+                None
+            },
+            Delivery::Stop(sig) => {
+                self.stopped = true;
+                Some(Delivery::Stop(sig))
+            },
+            delivery => Some(delivery),
+        }
+    }
+
+    /// Returns from a signal handler of this process: restores the `SignalFrame` at
+    /// the stack pointer of `tf` into `tf` and the blocked signals. The page tables,
+    /// the ID and the exception level of `tf` are kept; only the condition flags of
+    /// the saved `spsr` are restored.
+    ///
+    /// Returns `BadAddress` if the frame can't be read.
+    pub fn sigreturn(&mut self, tf: &mut TrapFrame) -> OsResult<()> {
+        let mut frame = SignalFrame {
+            context: TrapFrame::default(),
+            blocked: 0,
+            sig: 0,
+        };
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(&mut frame as *mut SignalFrame as *mut u8, core::mem::size_of::<SignalFrame>())
+        };
+        self.copy_from_user(tf.sp as usize, bytes)?;
+
+        let mut context = frame.context;
+        context.ttbr0 = tf.ttbr0;
+        context.ttbr1 = tf.ttbr1;
+        context.tpidr = tf.tpidr;
+        context.spsr = (frame.context.spsr & SPSR_FLAGS) | (tf.spsr & !SPSR_FLAGS);
+        *tf = context;
+        self.signals.set_blocked(frame.blocked);
+        Ok(())
+    }
+
+    /// Copies `args` to the top of the stack of this freshly loaded process and passes
+    /// them to its entry point: `x0` holds the number of arguments and `x1` points to
+    /// an array of `[ptr, len]` pairs, one for each argument.
+    ///
+    /// Returns `InvalidArgument` if the arguments take more than half of a page.
+    pub fn push_args(&mut self, args: &[String]) -> OsResult<()> {
+        let strings_size: usize = args.iter().map(|arg| arg.len()).sum();
+        let size = ((strings_size + 15) & !15) + args.len() * 16;
+        if size > PAGE_SIZE / 2 {
+            return Err(OsError::InvalidArgument);
+        }
+
+        let stack_base = Process::get_stack_base();
+        let mut space = self.space.lock();
+        space.handle_fault(stack_base, Access::Write)?;
+        let page = space.vmap.get_page(stack_base).ok_or(OsError::NoMemory)?;
+        let argv = Process::get_stack_top().as_usize() - size;
+        let mut string = argv + args.len() * 16;
+        for (index, arg) in args.iter().enumerate() {
+            let pair = argv - USER_STACK_BASE + index * 16;
+            page[pair..(pair + 8)].copy_from_slice(&(string as u64).to_le_bytes());
+            page[(pair + 8)..(pair + 16)].copy_from_slice(&(arg.len() as u64).to_le_bytes());
+            let offset = string - USER_STACK_BASE;
+            page[offset..(offset + arg.len())].copy_from_slice(arg.as_bytes());
+            string += arg.len();
+        }
+
+        self.context.sp = argv as u64;
+        self.context.x0 = args.len() as u64;
+        self.context.x1 = argv as u64;
+        Ok(())
+    }
+
+    /// Resolves a translation fault on `va` caused by an `access` of this process.
+    /// See `AddressSpace::handle_fault()`.
+    pub fn handle_fault(&mut self, va: VirtualAddr, access: Access) -> OsResult<()> {
+        self.space.lock().handle_fault(va, access)
+    }
+
+    /// Resolves a permission fault on `va` caused by a write of this process to a
+    /// copy-on-write page or a clean page of a shared file mapping. See
+    /// `AddressSpace::handle_cow_fault()`.
+    pub fn handle_cow_fault(&mut self, va: VirtualAddr) -> OsResult<()> {
+        self.space.lock().handle_cow_fault(va)
+    }
+
+    /// Copies `buf.len()` bytes of the memory of this process at the user address
+    /// `va` into `buf`. Returns `BadAddress` if the process may not read the range.
+    pub fn copy_from_user(&mut self, va: usize, buf: &mut [u8]) -> OsResult<()> {
+        self.space.lock().copy_from_user(va, buf)
+    }
+
+    /// Copies `buf` into the memory of this process at the user address `va`.
+    /// Returns `BadAddress` if the process may not write the range.
+    pub fn copy_to_user(&mut self, va: usize, buf: &[u8]) -> OsResult<()> {
+        self.space.lock().copy_to_user(va, buf)
+    }
+
+    /// Reads the UTF-8 string of `len` bytes at the user address `va` of this process.
+    pub fn string_from_user(&mut self, va: usize, len: usize) -> OsResult<String> {
+        self.space.lock().string_from_user(va, len)
+    }
+
+    /// Returns `true` if a fault on `va` while the user stack pointer is `sp` means
+    /// the stack overflowed: `va` is on the guard page, or `sp` itself was moved below
+    /// the stack limit and `va` is at or above it.
+    pub fn is_stack_overflow(&self, va: VirtualAddr, sp: u64) -> bool {
+        let va = va.as_usize();
+        let guard = Process::get_stack_guard().as_usize();
+        let limit = Process::get_stack_limit().as_usize();
+        let sp = sp as usize;
+        (va >= guard && va < limit) || (sp < limit && va >= sp && va < limit)
+    }
+
+    /// Returns the page permission matching the `p_flags` of a program header.
+    fn segment_perm(flags: u32) -> PagePerm {
+        let writable = flags & ProgHeaderFlag::W != 0;
+        let executable = flags & ProgHeaderFlag::X != 0;
+        match (writable, executable) {
+            (true, true) => PagePerm::RWX,
+            (false, true) => PagePerm::RX,
+            (true, false) => PagePerm::RW,
+            (false, false) => PagePerm::RO,
+        }
+    }
+
     /// Returns the highest `VirtualAddr` that is supported by this system.
     pub fn get_max_va() -> VirtualAddr {
         // 65536 * 16382
@@ -153,6 +653,18 @@ impl Process {
         VirtualAddr::from(USER_STACK_BASE) // last space in virtual addr
     }
 
+    /// Returns the `VirtualAddr` represents the lowest address the user
+    /// process's stack may grow to.
+    pub fn get_stack_limit() -> VirtualAddr {
+        VirtualAddr::from(USER_STACK_BASE - (USER_STACK_MAX_SIZE - PAGE_SIZE))
+    }
+
+    /// Returns the `VirtualAddr` represents the base address of the guard page
+    /// below the user process's stack, which is never mapped.
+    pub fn get_stack_guard() -> VirtualAddr {
+        VirtualAddr::from(Process::get_stack_limit().as_usize() - PAGE_SIZE)
+    }
+
     /// Returns the `VirtualAddr` represents the top of the user process's
     /// stack.
     pub fn get_stack_top() -> VirtualAddr {
@@ -172,23 +684,43 @@ impl Process {
     ///     occured. If it has, the state is switched to `Ready` and this
     ///     function returns `true`.
     ///
-    /// Returns `false` in all other cases.
+    ///   * The process is waiting or sleeping and a signal can be delivered to it.
+    ///     The system call it is blocked in returns `Interrupted`.
+    ///
+    /// Returns `false` in all other cases, and always while the process is stopped.
+    /// In particular, sleeping and parked processes are woken by the scheduler, not
+    /// polled.
     pub fn is_ready(&mut self) -> bool {
+        if self.stopped {
+            return false;
+        }
         let state = State::Ready;
         let original_state : State = core::mem::replace(&mut self.state, state);
         match original_state {
             State::Ready => true,
             State::Waiting(mut function) => {
+                if self.signals.has_deliverable() {
+                    // the blocking system call fails so that the signal can be delivered
+                    self.wait_target = None;
+                    self.join_target = None;
+                    self.context.x7 = OsError::Interrupted as u64;
+                    self.state = State::Ready;
+                    return true;
+                }
                 if function(self) {
                     self.state = State::Ready;
-                    kprintln!("Process {} ready", self.context.tpidr);
                     return true;
                 } else {
                     self.state = State::Waiting(function);
-                    kprintln!("Process {} not ready", self.context.tpidr);
                     return false;
                 }
             },
+            State::Sleeping(_) if self.signals.has_deliverable() => {
+                // the scheduler skips the stale entry in its sleep queue
+                self.context.x7 = OsError::Interrupted as u64;
+                self.state = State::Ready;
+                true
+            },
             _ => {
                 self.state = original_state;
                 return false;
@@ -196,3 +728,11 @@ impl Process {
         }
     }
 }
+
+/// The entry point of kernel threads, called at EL1 with the closure the thread
+/// runs. The thread exits once it returns.
+extern "C" fn kernel_thread_start(f: *mut Box<dyn FnOnce() + Send>) -> ! {
+    let f = unsafe { Box::from_raw(f) };
+    (*f)();
+    kernel_api::syscall::exit(0)
+}
@@ -1,116 +1,666 @@
 use alloc::boxed::Box;
+use alloc::collections::btree_map::BTreeMap;
 use alloc::collections::vec_deque::VecDeque;
+use alloc::format;
+use alloc::string::String;
 
-use crate::console::{kprintln};
+use crate::log::{error, info};
 use crate::mutex::Mutex;
-use crate::param::{PAGE_SIZE, TICK, USER_IMG_BASE};
-use crate::process::{Id, Process, State};
+use crate::param::{ALL_CORES, FLUSH_INTERVAL, INIT_REAP_INTERVAL, NCORES, TICK, USER_IMG_BASE};
+use alloc::vec::Vec;
+use crate::process::{Id, Process, State, INIT_ID};
+use crate::process::policy::{default_policy, pick, Policy};
 use crate::traps::TrapFrame;
-use pi::timer::tick_in;
-use pi::interrupt::{Interrupt};
-use crate::IRQ;
-use pi::interrupt::Controller;
+use pi::timer::current_time;
+use pi::local_interrupt::LocalController;
+use aarch64::affinity;
+use core::cmp::Reverse;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
 use crate::SCHEDULER;
+use crate::FILESYSTEM;
 use crate::vm::{VirtualAddr, PagePerm};
 use shim::path::Path;
-/// Process scheduler for the entire machine.
+use kernel_api::{OsError, OsResult, Rusage, SIGCHLD};
+/// Process scheduler for the entire machine: a run queue per core, each behind
+/// its own lock, and the state the cores share behind another one.
+///
+/// A core schedules with only its own queue locked. It locks the queue of another
+/// core to steal a process from it or to move one there, and to reach a process of
+/// that queue, as a wake-up or a signal from another core does. Two queue locks
+/// are taken in core order, and the shared lock is always taken last.
 #[derive(Debug)]
-pub struct GlobalScheduler(Mutex<Option<Scheduler>>);
+pub struct GlobalScheduler {
+    /// The run queue of each core. Every process is in exactly one of them, that
+    /// of the core it runs on or last ran on.
+    queues: [Mutex<Option<RunQueue>>; NCORES],
+    shared: Mutex<Option<Shared>>,
+    /// The ID of the process each core runs, 0 while it switches.
+    running: [AtomicU64; NCORES],
+    /// The number of processes of each queue that are ready or running, not
+    /// counting the idle task, as of the last change to the queue.
+    loads: [AtomicUsize; NCORES],
+    /// The cores running their idle task, bit `n` standing for core `n`.
+    idle_cores: AtomicU64,
+    /// The cores running a process without a time slice.
+    unsliced_cores: AtomicU64,
+}
 
 impl GlobalScheduler {
-    /// Returns an uninitialized wrapper around a local scheduler.
+    /// Returns an uninitialized scheduler.
     pub const fn uninitialized() -> GlobalScheduler {
-        GlobalScheduler(Mutex::new(None))
+        // one entry per core
+        GlobalScheduler {
+            queues: [Mutex::new(None), Mutex::new(None), Mutex::new(None), Mutex::new(None)],
+            shared: Mutex::new(None),
+            running: [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)],
+            loads: [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)],
+            idle_cores: AtomicU64::new(0),
+            unsliced_cores: AtomicU64::new(0),
+        }
     }
 
-    /// Enter a critical region and execute the provided closure with the
-    /// internal scheduler.
-    pub fn critical<F, R>(&self, f: F) -> R
+    /// Executes the provided closure with the run queue of `core` locked.
+    fn queue<F, R>(&self, core: usize, f: F) -> R
     where
-        F: FnOnce(&mut Scheduler) -> R,
+        F: FnOnce(&mut RunQueue) -> R,
     {
-        let mut guard = self.0.lock();
+        let mut guard = self.queues[core].lock();
         f(guard.as_mut().expect("scheduler uninitialized"))
     }
 
+    /// Executes the provided closure with the run queues of the distinct cores `a`
+    /// and `b` locked, in core order.
+    fn queue_pair<F, R>(&self, a: usize, b: usize, f: F) -> R
+    where
+        F: FnOnce(&mut RunQueue, &mut RunQueue) -> R,
+    {
+        assert!(a != b, "core {} paired with itself", a);
+        let mut low = self.queues[core::cmp::min(a, b)].lock();
+        let mut high = self.queues[core::cmp::max(a, b)].lock();
+        let low = low.as_mut().expect("scheduler uninitialized");
+        let high = high.as_mut().expect("scheduler uninitialized");
+        if a < b {
+            f(low, high)
+        } else {
+            f(high, low)
+        }
+    }
+
+    /// Executes the provided closure with the shared state locked.
+    fn shared<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Shared) -> R,
+    {
+        let mut guard = self.shared.lock();
+        f(guard.as_mut().expect("scheduler uninitialized"))
+    }
+
+    /// Returns where the process `id` is, or `None` if there is no such process.
+    fn place(&self, id: Id) -> Option<Place> {
+        self.shared(|shared| shared.places.get(&id).cloned())
+    }
+
+    /// Returns the IDs of the threads of the process `pid`.
+    fn threads(&self, pid: Id) -> Vec<Id> {
+        self.shared(|shared| {
+            shared.places.iter()
+                .filter(|(_, place)| place.pid == pid)
+                .map(|(&id, _)| id)
+                .collect()
+        })
+    }
+
+    /// Executes the provided closure with the run queue holding the process `id`
+    /// locked and the index of the process in it. Returns `NoEntry` if there is no
+    /// such process.
+    fn with_queue_of<F, R>(&self, id: Id, f: F) -> OsResult<R>
+    where
+        F: FnOnce(&mut RunQueue, usize) -> R,
+    {
+        let mut core = self.place(id).ok_or(OsError::NoEntry)?.core;
+        loop {
+            let mut guard = self.queues[core].lock();
+            let queue = guard.as_mut().expect("scheduler uninitialized");
+            if let Some(index) = queue.position(id) {
+                return Ok(f(queue, index));
+            }
+            // the process moved to another queue or was removed before the lock was
+            // taken: both update its place with the queue locked
+            match self.place(id) {
+                Some(place) if place.core != core => core = place.core,
+                _ => return Err(OsError::NoEntry),
+            }
+        }
+    }
+
+    /// Executes the provided closure on the process `id`, with the run queue
+    /// holding it locked, and returns its result. Returns `NoEntry` if there is no
+    /// such process. The closure must not call the scheduler.
+    pub fn with_process<F, R>(&self, id: Id, f: F) -> OsResult<R>
+    where
+        F: FnOnce(&mut Process) -> OsResult<R>,
+    {
+        self.with_queue_of(id, |queue, index| f(&mut queue.processes[index]))?
+    }
 
-    /// Adds a process to the scheduler's queue and returns that process's ID.
-    /// For more details, see the documentation on `Scheduler::add()`.
-    pub fn add(&self, process: Process) -> Option<Id> {
-        self.critical(move |scheduler| scheduler.add(process))
+    /// Adds a process to the scheduler and returns that process's ID if a new
+    /// process can be scheduled. The process ID is newly allocated for the process
+    /// and saved in its `trap_frame`. If no further processes can be scheduled,
+    /// returns `None`.
+    ///
+    /// The process goes to the queue of the least loaded core its affinity allows,
+    /// which is kicked (see `kick()`).
+    ///
+    /// It is the caller's responsibility to ensure that the first time `switch`
+    /// is called, that process is executing on the CPU.
+    pub fn add(&self, mut process: Process) -> Option<Id> {
+        let id = self.shared(|shared| {
+            let id = match shared.last_id {
+                Some(id) => id.checked_add(1)?,
+                None => 1, // no process yet, process ID 1
+            };
+            shared.last_id = Some(id);
+            Some(id)
+        })?;
+        process.context.tpidr = id;
+        process.sched.created = current_time();
+        let pid = process.pid();
+        let core = self.least_loaded(process.affinity);
+        self.queue(core, |queue| {
+            queue.policy.enqueue(&mut process);
+            queue.processes.push_back(process); // add to the back of the queue
+            self.shared(|shared| shared.places.insert(id, Place { core: core, pid: pid }));
+            self.kick(queue);
+        });
+        Some(id)
     }
 
     /// Performs a context switch using `tf` by setting the state of the current
     /// process to `new_state`, saving `tf` into the current process, and
     /// restoring the next process's trap frame into `tf`. For more details, see
-    /// the documentation on `Scheduler::schedule_out()` and `Scheduler::switch_to()`.
+    /// the documentation on `schedule_out()` and `switch_to()`.
     pub fn switch(&self, new_state: State, tf: &mut TrapFrame) -> Id {
-        self.critical(|scheduler| scheduler.schedule_out(new_state, tf));
+        self.schedule_out(new_state, tf);
         self.switch_to(tf)
     }
 
+    /// Switches the current core to the next process, restoring its trap frame into
+    /// `tf`, and returns its ID. The dead processes of the queue of the core are
+    /// dropped and its zombies collected first, see `reap()`.
+    ///
+    /// The scheduling policy of the queue picks the next process among its ready
+    /// ones. If none is ready, the core steals one from the queue of another core,
+    /// and runs its idle task if there is none either.
     pub fn switch_to(&self, tf: &mut TrapFrame) -> Id {
+        let core = affinity();
+        // once the idle task exists, there always is a process to switch to
         loop {
-            let rtn = self.critical(|scheduler| scheduler.switch_to(tf));
-            if let Some(id) = rtn {
+            self.reap(core);
+            if let Some(id) = self.queue(core, |queue| self.run_next(queue, tf, false)) {
+                return id;
+            }
+            self.steal(core);
+            if let Some(id) = self.queue(core, |queue| self.run_next(queue, tf, true)) {
                 return id;
             }
             aarch64::wfe();
         }
     }
 
-    /// Kills currently running process and returns that process's ID.
-    /// For more details, see the documentaion on `Scheduler::kill()`.
+    /// Kills currently running process by terminating it with the exit status -1.
+    /// See `exit()`. Returns the killed process's process ID.
     #[must_use]
     pub fn kill(&self, tf: &mut TrapFrame) -> Option<Id> {
-        self.critical(|scheduler| scheduler.kill(tf))
+        self.exit(-1, tf)
+    }
+
+    /// Terminates the process of the currently running thread with exit status `code`.
+    /// The main thread of the process is scheduled out as a `Zombie` holding `code`
+    /// until its parent collects it with `wait`, or as `Dead` if it has no parent,
+    /// which is sent `SIGCHLD`. The other threads are `Dead`. The file descriptors
+    /// are closed and the children of every thread are re-parented to the init process.
+    ///
+    /// If there is no current process, returns `None`. Otherwise, returns `Some` of
+    /// the ID of the terminated thread.
+    #[must_use]
+    pub fn exit(&self, code: i32, tf: &mut TrapFrame) -> Option<Id> {
+        let id = tf.tpidr;
+        let pid = self.with_process(id, |process| {
+            // close the descriptors now so that pipe readers see the end of the stream
+            process.files.lock().close_all();
+            Ok(process.pid())
+        }).ok()?;
+
+        // nobody is left to wake the futexes of the process
+        self.drop_futexes(pid);
+
+        for thread in self.threads(pid).into_iter().filter(|&thread| thread != id) {
+            self.release(thread);
+            let _ = self.with_process(thread, |process| {
+                process.state = match process.parent {
+                    Some(_) if thread == pid => State::Zombie(code),
+                    _ => State::Dead,
+                };
+                Ok(())
+            });
+            self.interrupt(thread);
+        }
+
+        let state = match self.release(id) {
+            Some(_) if id == pid => State::Zombie(code),
+            _ => State::Dead,
+        };
+        if self.schedule_out(state, tf) {
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    /// Terminates the currently running thread with exit status `code`, which the
+    /// thread joining it collects. Its user stack is unmapped. If it is the main
+    /// thread, the whole process terminates as with `exit()`.
+    ///
+    /// If there is no current thread, returns `None`. Otherwise, returns `Some` of
+    /// the ID of the terminated thread.
+    #[must_use]
+    pub fn exit_thread(&self, code: i32, tf: &mut TrapFrame) -> Option<Id> {
+        let id = tf.tpidr;
+        let is_leader = self.with_process(id, |process| {
+            if let Some((start, size)) = process.thread_stack.take() {
+                let _ = process.space.lock().munmap(start, size);
+            }
+            Ok(process.leader.is_none())
+        }).ok()?;
+        if is_leader {
+            return self.exit(code, tf);
+        }
+
+        self.release(id);
+        if self.schedule_out(State::Zombie(code), tf) {
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    /// Adds a kernel thread named `name` running `f` to the scheduler and returns
+    /// its ID. See `Process::kernel_thread()`.
+    pub fn spawn_kernel<F>(&self, name: &str, f: F) -> Option<Id>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let thread = Process::kernel_thread(name, Box::new(f)).ok()?;
+        self.add(thread)
+    }
+
+    /// Switches the current process out as `Sleeping` until `deadline`, inserts it
+    /// in the sleep queue of its run queue and switches to the next process. It is
+    /// woken by `wake_sleepers()`, and its `sleep` returns the time it slept in
+    /// milliseconds.
+    pub fn sleep(&self, deadline: Duration, tf: &mut TrapFrame) -> Id {
+        let id = tf.tpidr;
+        let allowed = self.queue(affinity(), |queue| {
+            let allowed = self.schedule_out_locked(queue, State::Sleeping(deadline), tf);
+            if allowed.is_some() {
+                queue.add_sleeper(id, deadline);
+            }
+            allowed
+        });
+        if allowed == Some(false) {
+            self.migrate(id);
+        }
+        self.switch_to(tf)
+    }
+
+    /// Parks the current thread in the wait queue of the futex at the user address
+    /// `addr` of its process if the 32-bit word there still holds `expected`, and
+    /// switches to the next process until `futex_wake()` or, if any, `deadline`,
+    /// when its `futex_wait` returns `IoErrorTimedOut`.
+    ///
+    /// Returns `WouldBlock` if the word holds another value, and `InvalidArgument`
+    /// if `addr` is not aligned to 4 bytes.
+    pub fn futex_wait(&self, addr: usize, expected: u32, deadline: Option<Duration>, tf: &mut TrapFrame) -> OsResult<Id> {
+        if addr % 4 != 0 {
+            return Err(OsError::InvalidArgument);
+        }
+        let id = tf.tpidr;
+        let allowed = self.queue(affinity(), |queue| {
+            let index = queue.position(id).ok_or(OsError::NoEntry)?;
+            // the futex wait queues stay locked from the check of the word until the
+            // thread is parked, so that a `futex_wake()` comes before or after both
+            let allowed = self.shared(|shared| {
+                let process = &mut queue.processes[index];
+                let mut word = [0u8; 4];
+                process.copy_from_user(addr, &mut word)?;
+                if u32::from_le_bytes(word) != expected {
+                    return Err(OsError::WouldBlock);
+                }
+                process.futex = Some(addr);
+                let key = (process.pid(), addr);
+                let allowed = self.schedule_out_locked(queue, State::Parked(deadline), tf)
+                    .ok_or(OsError::NoEntry)?;
+                shared.futexes.entry(key).or_insert_with(VecDeque::new).push_back(id);
+                Ok(allowed)
+            })?;
+            if let Some(deadline) = deadline {
+                queue.add_sleeper(id, deadline);
+            }
+            Ok(allowed)
+        })?;
+        if !allowed {
+            self.migrate(id);
+        }
+        Ok(self.switch_to(tf))
+    }
+
+    /// Wakes up to `count` threads parked at the futex at the user address `addr`
+    /// of the process of the thread `id`, the longest waiting first, and returns the
+    /// number of threads woken. Their `futex_wait` returns successfully, once an
+    /// idle core is interrupted to run them.
+    pub fn futex_wake(&self, id: Id, addr: usize, count: usize) -> OsResult<usize> {
+        let key = (self.place(id).ok_or(OsError::NoEntry)?.pid, addr);
+        let mut woken = 0;
+        while woken < count {
+            let waiter = self.shared(|shared| {
+                let waiters = shared.futexes.get_mut(&key)?;
+                let waiter = waiters.pop_front();
+                if waiters.is_empty() {
+                    shared.futexes.remove(&key);
+                }
+                waiter
+            });
+            let waiter = match waiter {
+                Some(waiter) => waiter,
+                None => break,
+            };
+            // a timeout or a signal may have unparked the waiter meanwhile
+            let unparked = self.with_queue_of(waiter, |queue, index| {
+                let process = &mut queue.processes[index];
+                if process.futex.take().is_none() {
+                    return false;
+                }
+                process.state = State::Ready;
+                process.context.x0 = 0;
+                process.context.x7 = 1;
+                self.wake(queue, index);
+                true
+            });
+            if unparked.unwrap_or(false) {
+                woken += 1;
+            }
+        }
+        Ok(woken)
     }
 
+    /// Sends the signal `sig` to the process `id` and lets it run to take it. A thread
+    /// parked at a futex that can take the signal is unparked, its `futex_wait`
+    /// returning `Interrupted`. Kernel threads never take signals: `sig` is dropped.
+    pub fn signal(&self, id: Id, sig: u64) {
+        let _ = self.with_queue_of(id, |queue, index| {
+            let process = &mut queue.processes[index];
+            if process.is_kernel_thread() {
+                return;
+            }
+            process.send_signal(sig);
+            if process.futex.is_some() && process.signals.has_deliverable() {
+                self.unpark(queue, index, OsError::Interrupted);
+            }
+            // a running process takes the signal on its next exception, another one
+            // when it is polled or runs again
+            if queue.current == Some(id) {
+                self.interrupt(id);
+            } else {
+                self.wake(queue, index);
+            }
+        });
+    }
+
+    /// Sets the nice value of every thread of the process of the thread `id` to
+    /// `nice`. As for an unprivileged caller on POSIX, a process may only be given a
+    /// lower priority, that is a higher nice value, even by itself: otherwise a few
+    /// busy threads at `PRIO_MIN` would starve every other process.
+    ///
+    /// Returns `NoAccess` if `nice` is below the current nice value of the process.
+    pub fn set_nice(&self, id: Id, nice: i32) -> OsResult<()> {
+        let (pid, current) = self.with_process(id, |process| Ok((process.pid(), process.nice)))?;
+        if nice < current {
+            return Err(OsError::NoAccess);
+        }
+        for thread in self.threads(pid) {
+            let _ = self.with_process(thread, |process| {
+                process.nice = nice;
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Sets the affinity of the thread `id` to the cores of `mask` that exist. A
+    /// thread that isn't running and may no longer run on the core of its queue
+    /// moves to another queue; a running one moves when it is switched out.
+    ///
+    /// Returns `InvalidArgument` if `mask` has none of the cores, or if `id` is an
+    /// idle task, which stays on its core.
+    pub fn set_affinity(&self, id: Id, mask: u64) -> OsResult<()> {
+        let mask = mask & ALL_CORES;
+        if mask == 0 {
+            return Err(OsError::InvalidArgument);
+        }
+        self.with_queue_of(id, |queue, index| {
+            if queue.idle == Some(id) {
+                return Err(OsError::InvalidArgument);
+            }
+            queue.processes[index].affinity = mask;
+            Ok(())
+        })??;
+        self.migrate(id);
+        Ok(())
+    }
+
+    /// Ends every thread of the process of the thread `id` but that one, as `exec`
+    /// does.
+    pub fn kill_other_threads(&self, id: Id) {
+        let pid = match self.place(id) {
+            Some(place) => place.pid,
+            None => return,
+        };
+        for thread in self.threads(pid).into_iter().filter(|&thread| thread != id) {
+            let _ = self.with_process(thread, |process| {
+                process.state = State::Dead;
+                Ok(())
+            });
+            self.interrupt(thread);
+        }
+        // only the other threads can be parked at a futex
+        self.drop_futexes(pid);
+    }
+
+    /// Returns `true` if the thread `id`, which the current core executes, is
+    /// running. A thread whose process was terminated by another core is not,
+    /// although its core still executes it.
+    pub fn is_running(&self, id: Id) -> bool {
+        self.queue(affinity(), |queue| queue.is_running(id))
+    }
+
+    /// Returns how long the idle tasks have run in total, including their current
+    /// runs, that is how long the cores had nothing to do since the scheduler
+    /// started. Zero if the scheduler is not initialized.
+    pub fn idle_time(&self) -> Duration {
+        let now = current_time();
+        let mut total = Duration::from_secs(0);
+        for queue in self.queues.iter() {
+            if let Some(queue) = queue.lock().as_ref() {
+                if let Some(idle) = queue.idle.and_then(|id| queue.find(id)) {
+                    total += idle.cpu_time(now);
+                }
+            }
+        }
+        total
+    }
+
+    /// Returns the resource usage of the process of the thread `id`, summed over
+    /// its threads. Returns `NoEntry` if there is no such thread.
+    pub fn usage(&self, id: Id) -> OsResult<Rusage> {
+        let pid = self.place(id).ok_or(OsError::NoEntry)?.pid;
+        let now = current_time();
+        let mut total: Option<Rusage> = None;
+        for thread in self.threads(pid) {
+            if let Ok(usage) = self.with_process(thread, |process| Ok(process.usage(now))) {
+                match total {
+                    Some(ref mut total) => add_usage(total, &usage),
+                    None => total = Some(usage),
+                }
+            }
+        }
+        total.ok_or(OsError::NoEntry)
+    }
+
+    /// Returns a snapshot of every process in the queues, in process ID order. The
+    /// queues are looked at one after the other, so a thread moving between two of
+    /// them meanwhile may be left out. Empty if the scheduler is not initialized.
+    pub fn snapshot(&self) -> Vec<ProcessInfo> {
+        let now = current_time();
+        let mut threads = Vec::new();
+        for queue in self.queues.iter() {
+            if let Some(queue) = queue.lock().as_ref() {
+                threads.extend(queue.processes.iter().map(|process| (process.context.tpidr, ProcessInfo {
+                    pid: process.pid(),
+                    threads: 1,
+                    name: process.name.clone(),
+                    state: process.state_name(),
+                    core: queue.core,
+                    nice: process.nice,
+                    usage: process.usage(now),
+                })));
+            }
+        }
+        // the main thread, whose ID is the process ID, comes first
+        threads.sort_by_key(|&(id, ref info)| (info.pid, id));
+        threads.dedup_by_key(|&mut (id, _)| id);
+
+        let mut processes: Vec<ProcessInfo> = Vec::new();
+        for (_, thread) in threads {
+            match processes.last_mut() {
+                Some(process) if process.pid == thread.pid => {
+                    process.threads += 1;
+                    add_usage(&mut process.usage, &thread.usage);
+                },
+                _ => processes.push(thread),
+            }
+        }
+        processes
+    }
+
+    /// Handles an inter-processor interrupt from another core that has work for
+    /// this one: switches away from the idle task, or from a process that was
+    /// terminated meanwhile. Otherwise the current process resumes, with a time
+    /// slice if another process of the queue now competes for the core, and its
+    /// signals are delivered.
+    pub fn ipi_handler(tf: &mut TrapFrame) {
+        let core = affinity();
+        LocalController::new(core).clear_mailbox(KICK_MAILBOX, !0);
+        let id = tf.tpidr;
+        let switch = SCHEDULER.queue(core, |queue| {
+            if queue.idle == Some(id) || !queue.is_running(id) {
+                return true;
+            }
+            let now = current_time();
+            if queue.slice_end.is_none() && queue.competes() {
+                SCHEDULER.start_slice(queue, now);
+            } else {
+                queue.arm_timer(now); // a sleeper may have moved to the queue
+            }
+            false
+        });
+        if switch {
+            SCHEDULER.switch(State::Ready, tf);
+        }
+    }
+
+    /// Handles the timer interrupt: wakes the sleepers whose deadline has passed and
+    /// switches to the next process if the time slice of the current one is over.
+    /// Otherwise the current process resumes undisturbed.
     pub fn tick_handler(tf : &mut TrapFrame) {
-        kprintln!("tick and switch");
-        tick_in(TICK);
-        SCHEDULER.switch(State::Ready, tf);
+        if SCHEDULER.tick(tf.tpidr) {
+            SCHEDULER.switch(State::Ready, tf);
+        }
     }
 
-    /// Starts executing processes in user space using timer interrupt based
-    /// preemptive scheduling. This method should not return under normal conditions.
+    /// Starts executing processes on the current core using timer interrupt based
+    /// preemptive scheduling. Every core calls this method once the scheduler is
+    /// initialized; the application cores wait for core 0 to initialize it. This
+    /// method should not return under normal conditions.
     pub fn start(&self) -> ! {
-        let mut controller : Controller = Controller::new(); // create interrupt controller
-        controller.enable(Interrupt::Timer1); // enable timer interrupt
-        tick_in(TICK); // set timer interrupt to occur in TICK second
-        IRQ.register(Interrupt::Timer1, Box::new(GlobalScheduler::tick_handler)); 
+        while self.shared.lock().is_none() {
+            aarch64::wfe(); // `initialize()` ends with an event
+        }
+        let mut controller = LocalController::new(affinity());
+        controller.enable_local_timer();
+        controller.enable_mailbox(KICK_MAILBOX);
 
         let mut tf : TrapFrame = TrapFrame::default();
         self.switch_to(&mut tf);
-        use crate::param::USER_STACK_BASE;
-        let next_page = USER_STACK_BASE - 100*PAGE_SIZE;
-        unsafe {
-            asm!("mov sp, $0
-                  bl context_restore"
-                :: "r"(&tf as *const _ as u64)
-                :: "volatile");           
-        }
-        let next_page = (tf.sp + PAGE_SIZE as u64) & !(0b1111);
+        // the exceptions of the processes use the kernel stack below `tf`
         unsafe {
             asm!("mov sp, $0
+                  bl context_restore
+                  ldp x28, x29, [sp], #16
+                  ldp lr, xzr, [sp], #16
                   eret"
-                :: "r"(next_page as u64)
+                :: "r"(&tf as *const _ as u64)
                 :: "volatile");
         }
-
-        //1. Before eret, just set your kernel's sp to the next page address of the kernel's sp.. 
-        //Just add one page_size to sp, mask it so that it is 16 byte aligned, then set it back to sp. 
-        //Since stacks grow downwards, we are resetting the stack pointer to the top of the page.
         loop {}
     }
 
     /// Initializes the scheduler and add userspace processes to the Scheduler
     pub unsafe fn initialize(&self) {
-        let scheduler : Scheduler = Scheduler::new();
-        *self.0.lock() = Some(scheduler);
+        for (core, queue) in self.queues.iter().enumerate() {
+            *queue.lock() = Some(RunQueue::new(core));
+        }
+        *self.shared.lock() = Some(Shared::new());
+        info!("Scheduling policy: {}", default_policy().name());
+
+        // the init process, `INIT_ID`, collects the orphans it adopts
+        let init = self.spawn_kernel("init", || loop {
+            if kernel_api::syscall::wait().is_err() { // no children yet
+                let _ = kernel_api::syscall::sleep(INIT_REAP_INTERVAL);
+            }
+        });
+        assert_eq!(init, Some(INIT_ID));
+
         let p1 = Process::load(Path::new("fib")).unwrap();
-        
+
         self.add(p1);
+
+        // each core runs its idle task when no other process is ready
+        for core in 0..NCORES {
+            let mut idle = Process::kernel_thread(&format!("idle{}", core), Box::new(|| loop {
+                // the other cores send an inter-processor interrupt when there is work
+                aarch64::wfi();
+                let _ = kernel_api::syscall::sleep(Duration::from_secs(0));
+            })).expect("failed to create the idle task");
+            idle.affinity = 1 << core;
+            let id = self.add(idle);
+            self.queue(core, |queue| queue.idle = id);
+        }
+
+        // write the file system cache back in the background
+        self.spawn_kernel("fat-flush", || loop {
+            let _ = kernel_api::syscall::sleep(FLUSH_INTERVAL);
+            if let Err(error) = FILESYSTEM.flush() {
+                error!("Failed to flush the file system cache: {:?}", error);
+            }
+        });
+
+        self.spawn_kernel("shell", || loop {
+            crate::shell::shell(">");
+        });
+
+        // let the application cores into `start()`
+        aarch64::sev();
     }
 
     // The following method may be useful for testing Phase 3:
@@ -118,146 +668,690 @@ impl GlobalScheduler {
     // * A method to load a extern function to the user process's page table.
     //
     pub fn test_phase_3(&self, process: &mut Process){
-        let mut page = process.vmap.alloc(
-            VirtualAddr::from(USER_IMG_BASE as u64), PagePerm::RWX);
+        let mut space = process.space.lock();
+        let page = space.vmap.alloc(
+            VirtualAddr::from(USER_IMG_BASE as u64), PagePerm::RWX).expect("out of memory");
         let text = unsafe {
             core::slice::from_raw_parts(test_user_process as *const u8, 24)
         };
         page[0..24].copy_from_slice(text);
     }
-}
-
-#[derive(Debug)]
-pub struct Scheduler {
-    processes: VecDeque<Process>,
-    last_id: Option<Id>,
-}
-
-impl Scheduler {
-    /// Returns a new `Scheduler` with an empty queue.
-    fn new() -> Scheduler {
-        let vec = VecDeque::new();
-        Scheduler {
-            processes : vec,
-            last_id : None
-        }
-    }
-
-    /// Adds a process to the scheduler's queue and returns that process's ID if
-    /// a new process can be scheduled. The process ID is newly allocated for
-    /// the process and saved in its `trap_frame`. If no further processes can
-    /// be scheduled, returns `None`.
-    ///
-    /// It is the caller's responsibility to ensure that the first time `switch`
-    /// is called, that process is executing on the CPU.
-    fn add(&mut self, mut process: Process) -> Option<Id> {
-       let return_id = match self.last_id {
-            Some(id) => {
-                process.context.tpidr = id.checked_add(1).unwrap();
-                id + 1
-            },
-            None => { // queue is empty, process ID 1
-                process.context.tpidr = 1u64;
-                1u64
-            }
-        };
-        self.processes.push_back(process); // add to the back of the queue
-        self.last_id = Some(return_id);
-        return Some(return_id);
-    }
 
     /// Finds the currently running process, sets the current process's state
     /// to `new_state`, prepares the context switch on `tf` by saving `tf`
     /// into the current process, and push the current process back to the
-    /// end of `processes` queue.
+    /// end of the queue of the current core, or of another core if its affinity
+    /// no longer allows the current one.
     ///
-    /// If the `processes` queue is empty or there is no current process,
-    /// returns `false`. Otherwise, returns `true`.
+    /// If there is no current process, returns `false`. Otherwise, returns `true`.
+    fn schedule_out(&self, new_state: State, tf: &TrapFrame) -> bool {
+        match self.queue(affinity(), |queue| self.schedule_out_locked(queue, new_state, tf)) {
+            Some(allowed) => {
+                if !allowed {
+                    self.migrate(tf.tpidr);
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Does the work of `schedule_out()` on `queue`, the queue of the current core,
+    /// but the move to another queue. Returns whether the process may stay on the
+    /// core, or `None` if there is no current process.
     // we remove(schedule out) the current running process.
     // we find this by looking at the ID in our current trapframe and look for a running
-    // process that match. If find it, we move it to the back of the queue(remove -> push_back). 
-    // save the current trap frame into this process to make sure we can return to this trapframe 
+    // process that match. If find it, we move it to the back of the queue(remove -> push_back).
+    // save the current trap frame into this process to make sure we can return to this trapframe
     // when we run it again.
-    fn schedule_out(&mut self, new_state: State, tf: &mut TrapFrame) -> bool {
-        if self.processes.is_empty() {
-            return false;
+    fn schedule_out_locked(&self, queue: &mut RunQueue, new_state: State, tf: &TrapFrame) -> Option<bool> {
+        let index = queue.position(tf.tpidr)?;
+        match queue.processes[index].state {
+            State::Running => {},
+            _ => return None, // does not find a running process..
         }
-        let mut count = 0;
-        for process in self.processes.iter_mut() {
-            if process.context.tpidr == tf.tpidr { // find one that matches with current trapframe ID
-                match process.state {
-                    State::Running => {
-                        let mut running_process = match self.processes.remove(count) {
-                            Some(p) => p, // remove running process
-                            None => {
-                                return false;
-                            }
-                        };
-                        running_process.state = new_state; // set it to the new state
-                        *running_process.context = *tf; // set up trapframe
-                        self.processes.push_back(running_process); // push to the end of queue
-                        return true;
-                    },
-                    _ => {
-                    }
-                }
-            }
-            count += 1;
+        let mut running_process = queue.processes.remove(index)?; // remove running process
+        match new_state {
+            State::Ready => running_process.sched.involuntary_switches += 1,
+            _ => running_process.sched.voluntary_switches += 1,
         }
-        return false; // does not find a running process..
+        running_process.state = new_state; // set it to the new state
+        *running_process.context = *tf; // set up trapframe
+        let ran = current_time() - running_process.sched.started;
+        running_process.sched.runtime += ran;
+        queue.policy.charge(&mut running_process, ran);
+        let allowed = running_process.may_run_on(queue.core);
+        queue.processes.push_back(running_process); // push to the end of queue
+        queue.current = None;
+        self.running[queue.core].store(0, Ordering::Relaxed);
+        Some(allowed)
     }
 
-    /// Finds the next process to switch to, brings the next process to the
-    /// front of the `processes` queue, changes the next process's state to
-    /// `Running`, and performs context switch by restoring the next process`s
-    /// trap frame into `tf`.
+    /// Finds the next process of `queue`, the queue of the current core, to switch
+    /// to, brings it to the front of the queue, changes its state to `Running`, and
+    /// performs context switch by restoring its trap frame into `tf`. The sleepers
+    /// of the queue that are due are woken first.
+    ///
+    /// The scheduling policy of the queue picks the next process among its ready
+    /// ones. If none is ready, the idle task is picked if `or_idle` is `true`.
     ///
     /// If there is no process to switch to, returns `None`. Otherwise, returns
     /// `Some` of the next process`s process ID.
-    // find the process that is ready to the front of the queue 
+    // poll every process and let the policy pick one of the ready ones,
     // change state to running, then context switch
-    // context switching: restore this running process's trapframe into 
+    // context switching: restore this running process's trapframe into
     // the current trapframe. After this, we can call context_restore to reload registers
     // back into the vector!
-    fn switch_to(&mut self, tf: &mut TrapFrame) -> Option<Id> {
-        if self.processes.is_empty() {
-            return None;
-        }
-        let mut count = 0;
-        for process in self.processes.iter_mut() {
-            if process.is_ready() {     // if find the first process that is ready
-                let mut ready_process = match self.processes.remove(count) {
-                    Some(p) => p,
-                    None => {
-                        return None;
+    fn run_next(&self, queue: &mut RunQueue, tf: &mut TrapFrame, or_idle: bool) -> Option<Id> {
+        let core = queue.core;
+        let now = current_time();
+        self.wake_sleepers(queue, now);
+        let idle = queue.idle;
+        let ready = queue.processes.iter_mut().enumerate().filter_map(|(index, process)| {
+            if Some(process.context.tpidr) != idle && process.may_run_on(core) && process.is_ready() {
+                Some((index, process))
+            } else {
+                None
+            }
+        });
+        let (index, others) = match pick(&mut *queue.policy, ready) {
+            Some(next) => next,
+            None if or_idle => (queue.position(idle?)?, 0), // nothing else to do
+            None => return None,
+        };
+        // the other ready processes may run on an idle core meanwhile
+        if others != 0 {
+            self.kick_idle_core(others);
+        }
+
+        let mut ready_process = queue.processes.remove(index)?;
+        let id = ready_process.context.tpidr;
+        ready_process.state = State::Running; // set state to running
+        ready_process.sched.started = now;
+        *tf = *(ready_process.context); // restore by setting tf to process trap frame
+        queue.processes.push_front(ready_process);
+        queue.current = Some(id);
+        self.running[core].store(id, Ordering::Relaxed);
+        // the process gets a time slice only if others compete for the core: the ready
+        // processes of the queue, or the waiting ones, which the core polls when it
+        // switches. `kick()` starts the slice if one becomes ready later on.
+        queue.slice_end = if others != 0 || queue.has_waiting() {
+            Some(now + TICK)
+        } else {
+            None
+        };
+        let is_idle = Some(id) == idle;
+        set_core(&self.idle_cores, core, is_idle);
+        set_core(&self.unsliced_cores, core, !is_idle && queue.slice_end.is_none());
+        self.publish_load(queue);
+        queue.arm_timer(now);
+        Some(id)
+    }
+
+    /// Moves a ready process the core `core` may run from the queue of another core
+    /// to its own. The queues of the most loaded cores are looked at first, and the
+    /// policy of a queue picks among its ready processes. Returns `false` if there
+    /// is no such process.
+    fn steal(&self, core: usize) -> bool {
+        let mut victims = [0; NCORES];
+        for (index, victim) in victims.iter_mut().enumerate() {
+            *victim = index;
+        }
+        victims.sort_unstable_by_key(|&other| (Reverse(self.loads[other].load(Ordering::Relaxed)), other));
+        for &victim in victims.iter().filter(|&&other| other != core) {
+            if self.loads[victim].load(Ordering::Relaxed) == 0 {
+                break; // the remaining queues have nothing to steal either
+            }
+            let stolen = self.queue_pair(core, victim, |local, remote| {
+                let idle = remote.idle;
+                let ready = remote.processes.iter_mut().enumerate().filter_map(|(index, process)| {
+                    // the running process of another core is `Running`, not ready
+                    if Some(process.context.tpidr) != idle && process.may_run_on(core) && process.is_ready() {
+                        Some((index, process))
+                    } else {
+                        None
                     }
-                };
-                ready_process.state = State::Running; // set state to running
-                *tf = *(ready_process.context); // restore by setting tf to process trap frame
-                let id = ready_process.context.tpidr;
-                self.processes.push_front(ready_process);
-                return Some(id);
-            }
-            count += 1; // count index for remove
-        }
-        return None;
-    }
-
-    /// Kills currently running process by scheduling out the current process
-    /// as `Dead` state. Removes the dead process from the queue, drop the
-    /// dead process's instance, and returns the dead process's process ID.
-    fn kill(&mut self, tf: &mut TrapFrame) -> Option<Id> {
-        self.schedule_out(State::Dead, tf); // move this process to the back of the queue;
-        match self.processes.pop_back() { // pop this process, drop it's instance
-            Some(dead_process) => {
-                let id = dead_process.context.tpidr; // store id
-                drop(dead_process); // drop it
-                return Some(id); // return id
-            }, 
-            None => {
-                return None;
-            }
-        } 
+                });
+                let (index, _) = pick(&mut *remote.policy, ready)?;
+                Some(self.transfer(remote, index, local))
+            });
+            if stolen.is_some() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Moves the process `id`, unless it runs, to the back of the queue of the
+    /// least loaded core its affinity allows if it may no longer run on the core of
+    /// its queue, and kicks that core (see `kick()`).
+    fn migrate(&self, id: Id) {
+        let (from, mask) = match self.with_queue_of(id, |queue, index| (queue.core, queue.processes[index].affinity)) {
+            Ok(location) => location,
+            Err(_) => return,
+        };
+        if mask & (1 << from) != 0 {
+            return;
+        }
+        let to = self.least_loaded(mask);
+        if to == from {
+            return;
+        }
+        self.queue_pair(from, to, |from_queue, to_queue| {
+            let index = match from_queue.position(id) {
+                Some(index) => index,
+                None => return, // moved meanwhile
+            };
+            if let State::Running = from_queue.processes[index].state {
+                return; // it moves when it is switched out
+            }
+            self.transfer(from_queue, index, to_queue);
+            self.kick(to_queue);
+        });
+    }
+
+    /// Moves the process at `index` of `from` to the back of `to`, with its entries
+    /// in the sleep queue of `from`, and returns its ID.
+    fn transfer(&self, from: &mut RunQueue, index: usize, to: &mut RunQueue) -> Id {
+        let mut process = from.processes.remove(index).expect("no process to transfer");
+        let id = process.context.tpidr;
+        while let Some(position) = from.sleepers.iter().position(|sleeper| sleeper.id == id) {
+            if let Some(sleeper) = from.sleepers.remove(position) {
+                to.insert_sleeper(sleeper);
+            }
+        }
+        to.policy.enqueue(&mut process);
+        to.processes.push_back(process);
+        let core = to.core;
+        self.shared(|shared| {
+            if let Some(place) = shared.places.get_mut(&id) {
+                place.core = core;
+            }
+        });
+        self.publish_load(from);
+        self.publish_load(to);
+        id
+    }
+
+    /// Removes the process at `index` of `queue` from the scheduler and returns it.
+    fn remove(&self, queue: &mut RunQueue, index: usize) -> Option<Process> {
+        let process = queue.processes.remove(index)?;
+        let id = process.context.tpidr;
+        self.shared(|shared| shared.places.remove(&id));
+        self.publish_load(queue);
+        Some(process)
+    }
+
+    /// Publishes the load of `queue`, see `least_loaded()`.
+    fn publish_load(&self, queue: &RunQueue) {
+        self.loads[queue.core].store(queue.load(), Ordering::Relaxed);
+    }
+
+    /// Returns the least loaded of the cores of the affinity mask `mask`, the
+    /// first one if several are, as of the loads their queues last published.
+    /// Returns the current core if `mask` has none.
+    fn least_loaded(&self, mask: u64) -> usize {
+        (0..NCORES)
+            .filter(|core| mask & (1 << core) != 0)
+            .min_by_key(|&core| (self.loads[core].load(Ordering::Relaxed), core))
+            .unwrap_or_else(affinity)
+    }
+
+    /// Lets the core of `queue` take up a process of it that became ready. If the
+    /// core runs its idle task, sends it an inter-processor interrupt so that it
+    /// looks for work. If it runs a process without a time slice, which that process
+    /// now competes for, the slice is started: by the current core itself, or by the
+    /// other core once interrupted (see `ipi_handler()`).
+    fn kick(&self, queue: &mut RunQueue) {
+        self.publish_load(queue);
+        if queue.idle.is_none() {
+            return; // the core doesn't run processes yet
+        }
+        let core = queue.core;
+        if core == affinity() {
+            let busy = queue.current.is_some() && queue.current != queue.idle;
+            if busy && queue.slice_end.is_none() {
+                self.start_slice(queue, current_time());
+            }
+        } else {
+            // both are only updated with the queue locked
+            let quiet = self.idle_cores.load(Ordering::Relaxed) | self.unsliced_cores.load(Ordering::Relaxed);
+            if quiet & (1 << core) != 0 {
+                LocalController::new(core).send_mailbox(KICK_MAILBOX, 1);
+            }
+        }
+    }
+
+    /// Starts the time slice of the process running on the current core, whose
+    /// queue is `queue`, and programs the timer for it.
+    fn start_slice(&self, queue: &mut RunQueue, now: Duration) {
+        queue.slice_end = Some(now + TICK);
+        set_core(&self.unsliced_cores, queue.core, false);
+        queue.arm_timer(now);
+    }
+
+    /// Interrupts the other core running the thread `id`, if any, so that it notices
+    /// right away that the thread was terminated or sent a signal, even if the thread
+    /// has no time slice to end.
+    fn interrupt(&self, id: Id) {
+        let this = affinity();
+        let core = (0..NCORES).find(|&core| core != this && self.running[core].load(Ordering::Relaxed) == id);
+        if let Some(core) = core {
+            LocalController::new(core).send_mailbox(KICK_MAILBOX, 1);
+        }
+    }
+
+    /// Interrupts one of the idle cores of the affinity mask `mask`, other than
+    /// the current one, so that it steals a ready process.
+    fn kick_idle_core(&self, mask: u64) {
+        let idle = self.idle_cores.load(Ordering::Relaxed) & mask & !(1 << affinity());
+        if idle != 0 {
+            LocalController::new(idle.trailing_zeros() as usize).send_mailbox(KICK_MAILBOX, 1);
+        }
+    }
+
+    /// Lets the process at `index` of `queue`, which may have become ready, run
+    /// soon: kicks the core of the queue (see `kick()`), and if that core is busy,
+    /// interrupts an idle core the process may run on as well, which steals it.
+    fn wake(&self, queue: &mut RunQueue, index: usize) {
+        let busy = queue.current != queue.idle;
+        let mask = queue.processes[index].affinity;
+        self.kick(queue);
+        if busy {
+            self.kick_idle_core(mask);
+        }
+    }
+
+    /// Makes the sleepers of `queue` whose deadline is not after `now` ready. The
+    /// `futex_wait` of a parked thread whose deadline passed returns `IoErrorTimedOut`.
+    /// Entries of processes that were woken otherwise, by a signal for instance, are
+    /// dropped. Returns `true` if a process was woken.
+    fn wake_sleepers(&self, queue: &mut RunQueue, now: Duration) -> bool {
+        let mut woken = false;
+        loop {
+            let sleeper = match queue.sleepers.front() {
+                Some(sleeper) if sleeper.deadline <= now => *sleeper,
+                _ => break,
+            };
+            queue.sleepers.pop_front();
+            let index = match queue.position(sleeper.id) {
+                Some(index) => index,
+                None => continue,
+            };
+            let process = &mut queue.processes[index];
+            match process.state {
+                State::Sleeping(deadline) if deadline == sleeper.deadline => {
+                    process.state = State::Ready;
+                    process.context.x0 = (now - sleeper.start).as_millis() as u64;
+                    process.context.x7 = 1;
+                },
+                State::Parked(Some(deadline)) if deadline == sleeper.deadline => {
+                    self.unpark(queue, index, OsError::IoErrorTimedOut);
+                },
+                _ => continue,
+            }
+            woken = true;
+            self.wake(queue, index);
+        }
+        woken
+    }
+
+    /// Handles a timer interrupt of the current core while the process `id` runs:
+    /// wakes the sleepers that are due, and returns `true` if the time slice of the
+    /// running process is over, it was terminated by another core, or the idle task
+    /// runs and a sleeper woke up. Otherwise programs the next timer interrupt and
+    /// returns `false`.
+    fn tick(&self, id: Id) -> bool {
+        self.queue(affinity(), |queue| {
+            let now = current_time();
+            let woken = self.wake_sleepers(queue, now);
+            let expired = match queue.slice_end {
+                Some(end) => now >= end,
+                None => false,
+            };
+            if expired || !queue.is_running(id) || (woken && queue.idle == Some(id)) {
+                return true;
+            }
+            queue.arm_timer(now);
+            false
+        })
+    }
+
+    /// Re-parents the children of the thread `id` to the init process, and sends
+    /// `SIGCHLD` to its parent. Returns the ID of the parent.
+    fn release(&self, id: Id) -> Option<Id> {
+        let (parent, children) = self.with_process(id, |process| {
+            Ok((process.parent, core::mem::replace(&mut process.children, Vec::new())))
+        }).ok()?;
+
+        let init = if id != INIT_ID && self.place(INIT_ID).is_some() {
+            Some(INIT_ID)
+        } else {
+            None
+        };
+        for &child in children.iter() {
+            let _ = self.with_process(child, |process| {
+                process.parent = init;
+                if init.is_none() {
+                    if let State::Zombie(_) = process.state {
+                        process.state = State::Dead; // nobody left to collect it
+                    }
+                }
+                Ok(())
+            });
+        }
+        if let Some(init) = init {
+            let _ = self.with_process(init, |process| {
+                process.children.extend(children);
+                Ok(())
+            });
+        }
+
+        if let Some(parent) = parent {
+            self.signal(parent, SIGCHLD);
+        }
+        parent
+    }
+
+    /// Takes the thread at `index` of `queue` out of the wait queue of the futex it
+    /// is parked at, if any, and makes it ready, its `futex_wait` returning `error`.
+    fn unpark(&self, queue: &mut RunQueue, index: usize, error: OsError) {
+        let process = &mut queue.processes[index];
+        let addr = match process.futex.take() {
+            Some(addr) => addr,
+            None => return,
+        };
+        process.state = State::Ready;
+        process.context.x7 = error as u64;
+        let id = process.context.tpidr;
+        let key = (process.pid(), addr);
+        self.shared(|shared| {
+            if let Some(waiters) = shared.futexes.get_mut(&key) {
+                waiters.retain(|&waiter| waiter != id);
+                if waiters.is_empty() {
+                    shared.futexes.remove(&key);
+                }
+            }
+        });
+    }
+
+    /// Drops the futex wait queues of the process `pid`.
+    fn drop_futexes(&self, pid: Id) {
+        self.shared(|shared| {
+            let futexes: Vec<(Id, usize)> = shared.futexes.keys()
+                .filter(|&&(owner, _)| owner == pid)
+                .cloned()
+                .collect();
+            for key in futexes {
+                shared.futexes.remove(&key);
+            }
+        });
+    }
+
+    /// Removes the dead processes from the queue of `core`, and hands the exit
+    /// status of each zombie process of the queue to its parent if the parent is
+    /// waiting for it, and of each zombie thread to the thread joining it. Collected
+    /// zombies are removed from the queue as well. A process the core still runs
+    /// is left alone.
+    ///
+    /// The removed processes are dropped with no queue locked, since dropping an
+    /// address space writes its shared file mappings back.
+    fn reap(&self, core: usize) {
+        let (dead, zombies) = self.queue(core, |queue| {
+            let mut dead = Vec::new();
+            let mut zombies = Vec::new();
+            let mut index = 0;
+            while index < queue.processes.len() {
+                let process = &queue.processes[index];
+                let id = process.context.tpidr;
+                match process.state {
+                    _ if queue.current == Some(id) => {},
+                    State::Dead => {
+                        dead.extend(self.remove(queue, index));
+                        continue;
+                    },
+                    State::Zombie(code) => zombies.push((id, process.parent, process.leader, code)),
+                    _ => {},
+                }
+                index += 1;
+            }
+            (dead, zombies)
+        });
+        drop(dead);
+
+        for (id, parent, leader, code) in zombies {
+            let collected = match leader {
+                // a thread is collected by the thread joining it
+                Some(pid) => self.threads(pid).into_iter().any(|thread| {
+                    self.with_queue_of(thread, |queue, index| {
+                        let joiner = &mut queue.processes[index];
+                        if joiner.join_target != Some(id) {
+                            return false;
+                        }
+                        joiner.collect_thread(code);
+                        self.wake(queue, index);
+                        true
+                    }).unwrap_or(false)
+                }),
+                None => parent.map_or(false, |parent| {
+                    self.with_queue_of(parent, |queue, index| {
+                        let parent = &mut queue.processes[index];
+                        if !parent.waits_for(id) {
+                            return false;
+                        }
+                        parent.collect_child(id, code);
+                        self.wake(queue, index);
+                        true
+                    }).unwrap_or(false)
+                }),
+            };
+            if collected {
+                let zombie = self.with_queue_of(id, |queue, index| self.remove(queue, index));
+                drop(zombie);
+            }
+        }
+    }
+}
+
+/// Adds `thread`, the usage of a thread, to `total`, the usage of its process. The
+/// threads share the address space, whose pages are counted once.
+fn add_usage(total: &mut Rusage, thread: &Rusage) {
+    total.cpu_time += thread.cpu_time;
+    total.start_time = core::cmp::min(total.start_time, thread.start_time);
+    total.voluntary_switches += thread.voluntary_switches;
+    total.involuntary_switches += thread.involuntary_switches;
+}
+
+/// Sets the bit of `core` in the core mask `mask` if `value` is `true`, and clears
+/// it otherwise.
+fn set_core(mask: &AtomicU64, core: usize, value: bool) {
+    if value {
+        mask.fetch_or(1 << core, Ordering::Relaxed);
+    } else {
+        mask.fetch_and(!(1 << core), Ordering::Relaxed);
+    }
+}
+
+/// The accounting of a process at some point, as listed by `ps` and `top`: the
+/// state of its main thread, and the usage of all its threads.
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub pid: Id,
+    /// The number of threads of the process.
+    pub threads: usize,
+    pub name: String,
+    pub state: &'static str,
+    /// The core whose queue holds the main thread.
+    pub core: usize,
+    pub nice: i32,
+    pub usage: Rusage,
+}
+
+/// The run queue of a core and the state of its scheduling.
+#[derive(Debug)]
+struct RunQueue {
+    core: usize,
+    /// The processes of the core, the one it runs or last ran first.
+    processes: VecDeque<Process>,
+    /// The policy picking the next process among the ready ones.
+    policy: Box<dyn Policy>,
+    /// The sleeping processes of the queue, sorted by deadline.
+    sleepers: VecDeque<Sleeper>,
+    /// When the time slice of the running process ends, `None` while no other
+    /// process competes for the core.
+    slice_end: Option<Duration>,
+    /// The ID of the idle task of the core, which runs when no other process is
+    /// ready.
+    idle: Option<Id>,
+    /// The ID of the process running on the core. A process that was terminated
+    /// by another core while running stays in the queue until the core switches
+    /// away from it.
+    current: Option<Id>,
+}
+
+/// The scheduling state the cores share.
+#[derive(Debug)]
+struct Shared {
+    last_id: Option<Id>,
+    /// Where each process is.
+    places: BTreeMap<Id, Place>,
+    /// The threads parked in `futex_wait`, in arrival order, per process ID and
+    /// futex address.
+    futexes: BTreeMap<(Id, usize), VecDeque<Id>>,
+}
+
+/// Where a process is: the core whose queue holds it, and its process ID.
+#[derive(Debug, Copy, Clone)]
+struct Place {
+    core: usize,
+    pid: Id,
+}
+
+/// An entry of the sleep queue of a run queue.
+#[derive(Debug, Copy, Clone)]
+struct Sleeper {
+    /// When the process wakes up.
+    deadline: Duration,
+    /// When the process went to sleep.
+    start: Duration,
+    id: Id,
+}
+
+/// The mailbox of a core other cores write to when they have work for it.
+const KICK_MAILBOX: usize = 0;
+
+/// The shortest delay the timer is programmed with, so that a deadline that is
+/// already due still raises an interrupt.
+const MIN_TIMER_DELAY: Duration = Duration::from_micros(100);
+
+impl Shared {
+    fn new() -> Shared {
+        Shared {
+            last_id : None,
+            places : BTreeMap::new(),
+            futexes : BTreeMap::new(),
+        }
+    }
+}
+
+impl RunQueue {
+    /// Returns the empty run queue of `core`.
+    fn new(core: usize) -> RunQueue {
+        RunQueue {
+            core : core,
+            processes : VecDeque::new(),
+            policy : default_policy(),
+            sleepers : VecDeque::new(),
+            slice_end : None,
+            idle : None,
+            current : None,
+        }
+    }
+
+    /// Returns the index of the process `id` in the queue, if it is there.
+    fn position(&self, id: Id) -> Option<usize> {
+        self.processes.iter().position(|process| process.context.tpidr == id)
+    }
+
+    /// Returns the process `id` if it is in the queue.
+    fn find(&self, id: Id) -> Option<&Process> {
+        self.processes.iter().find(|process| process.context.tpidr == id)
+    }
+
+    /// Returns the number of processes of the queue that are ready or running, not
+    /// counting the idle task.
+    fn load(&self) -> usize {
+        self.processes.iter()
+            .filter(|process| !process.stopped && Some(process.context.tpidr) != self.idle)
+            .filter(|process| match process.state {
+                State::Ready | State::Running => true,
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Returns `true` if a process of the queue waits for an event only polling can
+    /// detect.
+    fn has_waiting(&self) -> bool {
+        self.processes.iter().any(|process| match process.state {
+            State::Waiting(_) => !process.stopped,
+            _ => false,
+        })
+    }
+
+    /// Returns `true` if a process of the queue other than the running one competes
+    /// for the core: a ready one, or a waiting one.
+    fn competes(&mut self) -> bool {
+        let (core, current, idle) = (self.core, self.current, self.idle);
+        self.has_waiting() || self.processes.iter_mut().any(|process| {
+            let id = Some(process.context.tpidr);
+            id != current && id != idle && process.may_run_on(core) && process.is_ready()
+        })
+    }
+
+    /// Returns `true` if the process `id` is in the queue and running.
+    fn is_running(&self, id: Id) -> bool {
+        match self.find(id).map(|process| &process.state) {
+            Some(State::Running) => true,
+            _ => false,
+        }
+    }
+
+    /// Inserts the process `id` in the sleep queue, to be woken at `deadline`.
+    fn add_sleeper(&mut self, id: Id, deadline: Duration) {
+        self.insert_sleeper(Sleeper {
+            deadline: deadline,
+            start: current_time(),
+            id: id,
+        });
+    }
+
+    /// Inserts `sleeper` in the sleep queue, after the entries due no later.
+    fn insert_sleeper(&mut self, sleeper: Sleeper) {
+        let index = self.sleepers.iter()
+            .position(|other| other.deadline > sleeper.deadline)
+            .unwrap_or(self.sleepers.len());
+        self.sleepers.insert(index, sleeper);
+    }
+
+    /// Programs the timer of the core, which has to be the current one, for the end
+    /// of its time slice or the earliest deadline of its sleepers, whichever comes
+    /// first. If there is neither, no timer interrupt is raised until the core is
+    /// kicked or a process blocks.
+    fn arm_timer(&self, now: Duration) {
+        let next = match (self.sleepers.front(), self.slice_end) {
+            (Some(sleeper), Some(end)) => Some(core::cmp::min(sleeper.deadline, end)),
+            (Some(sleeper), None) => Some(sleeper.deadline),
+            (None, end) => end,
+        };
+        let mut timer = LocalController::new(self.core);
+        match next {
+            Some(next) => {
+                let delay = if next > now { next - now } else { Duration::from_secs(0) };
+                timer.tick_in(core::cmp::max(delay, MIN_TIMER_DELAY));
+            },
+            None => timer.clear_tick(),
+        }
     }
 }
 
@@ -0,0 +1,202 @@
+use kernel_api::{OsError, OsResult, NSIG, SIG_DFL, SIG_IGN};
+use kernel_api::{SIGCHLD, SIGCONT, SIGKILL, SIGSTOP, SIGTSTP, SIGTTIN, SIGTTOU, SIGURG, SIGWINCH};
+
+use crate::traps::TrapFrame;
+
+/// What a process does when it receives a signal.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    /// The default action of the signal, see `default_action()`.
+    Default,
+    /// The signal is discarded.
+    Ignore,
+    /// The user function at `handler` is called, and returns to `restorer`, which
+    /// issues `sigreturn`.
+    Handler { handler: u64, restorer: u64 },
+}
+
+impl Action {
+    /// Returns the handler address `sigaction` reports for this action.
+    pub fn handler(&self) -> u64 {
+        match *self {
+            Action::Default => SIG_DFL,
+            Action::Ignore => SIG_IGN,
+            Action::Handler { handler, .. } => handler,
+        }
+    }
+}
+
+/// What happens to a process receiving a signal whose action is `Action::Default`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+/// Returns the default action of the signal `sig`.
+pub fn default_action(sig: u64) -> DefaultAction {
+    match sig {
+        SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        SIGCONT => DefaultAction::Continue,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+/// What the kernel has to do with a signal taken by `Signals::take()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Delivery {
+    /// Enter the user handler of the signal.
+    Handle { sig: u64, handler: u64, restorer: u64 },
+    /// Terminate the process.
+    Terminate(u64),
+    /// Stop the process until it receives `SIGCONT`.
+    Stop(u64),
+}
+
+/// The context saved on the user stack when a signal handler is entered, which
+/// `sigreturn` restores.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SignalFrame {
+    /// The trap frame of the interrupted code.
+    pub context: TrapFrame,
+    /// The blocked signals of the interrupted code.
+    pub blocked: u64,
+    /// The signal being handled.
+    pub sig: u64,
+}
+
+/// The signal state of a process: the action of each signal, and the signals
+/// that were sent but not delivered yet and those that are blocked, as bit masks.
+#[derive(Debug, Clone)]
+pub struct Signals {
+    actions: [Action; NSIG as usize],
+    pending: u64,
+    blocked: u64,
+}
+
+impl Signals {
+    /// Returns the signal state of a new process: every action is the default one
+    /// and nothing is pending or blocked.
+    pub fn new() -> Signals {
+        Signals {
+            actions: [Action::Default; NSIG as usize],
+            pending: 0,
+            blocked: 0,
+        }
+    }
+
+    /// Returns `true` if `sig` is a signal number.
+    pub fn is_valid(sig: u64) -> bool {
+        sig > 0 && sig < NSIG
+    }
+
+    /// Sets the action of `sig` to `action` and returns the previous one.
+    ///
+    /// Returns `InvalidArgument` if `sig` is not a signal number or is `SIGKILL` or
+    /// `SIGSTOP`, whose action can't be changed.
+    pub fn set_action(&mut self, sig: u64, action: Action) -> OsResult<Action> {
+        if !Signals::is_valid(sig) || sig == SIGKILL || sig == SIGSTOP {
+            return Err(OsError::InvalidArgument);
+        }
+        let previous = core::mem::replace(&mut self.actions[sig as usize], action);
+        if self.is_ignored(sig) {
+            self.pending &= !(1 << sig); // pending ones are discarded as well
+        }
+        Ok(previous)
+    }
+
+    /// Marks `sig` as pending. `SIGCONT` discards pending stop signals and stop
+    /// signals discard a pending `SIGCONT`.
+    pub fn raise(&mut self, sig: u64) {
+        match default_action(sig) {
+            DefaultAction::Continue => {
+                self.pending &= !((1 << SIGSTOP) | (1 << SIGTSTP) | (1 << SIGTTIN) | (1 << SIGTTOU));
+            },
+            DefaultAction::Stop => self.pending &= !(1 << SIGCONT),
+            _ => {},
+        }
+        self.pending |= 1 << sig;
+    }
+
+    /// Returns `true` if receiving `sig` has no effect on the process.
+    pub fn is_ignored(&self, sig: u64) -> bool {
+        match self.actions[sig as usize] {
+            Action::Ignore => true,
+            Action::Default => match default_action(sig) {
+                DefaultAction::Ignore | DefaultAction::Continue => true,
+                _ => false,
+            },
+            Action::Handler { .. } => false,
+        }
+    }
+
+    /// Returns `true` if `sig` would enter a user handler right away.
+    pub fn catches(&self, sig: u64) -> bool {
+        match self.actions[sig as usize] {
+            Action::Handler { .. } => self.blocked & (1 << sig) == 0,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if a pending signal is not blocked and has an effect.
+    pub fn has_deliverable(&self) -> bool {
+        (1..NSIG).any(|sig| self.pending & !self.blocked & (1 << sig) != 0 && !self.is_ignored(sig))
+    }
+
+    /// Removes the lowest pending signal that is not blocked from the pending set
+    /// and returns what to do with it. Ignored signals are discarded on the way.
+    pub fn take(&mut self) -> Option<Delivery> {
+        for sig in 1..NSIG {
+            if self.pending & !self.blocked & (1 << sig) == 0 {
+                continue;
+            }
+            self.pending &= !(1 << sig);
+            match self.actions[sig as usize] {
+                Action::Ignore => continue,
+                Action::Handler { handler, restorer } => {
+                    return Some(Delivery::Handle { sig: sig, handler: handler, restorer: restorer });
+                },
+                Action::Default => match default_action(sig) {
+                    DefaultAction::Terminate => return Some(Delivery::Terminate(sig)),
+                    DefaultAction::Stop => return Some(Delivery::Stop(sig)),
+                    DefaultAction::Ignore | DefaultAction::Continue => continue,
+                },
+            }
+        }
+        None
+    }
+
+    /// Returns the mask of blocked signals.
+    pub fn blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    /// Sets the mask of blocked signals. `SIGKILL` and `SIGSTOP` can't be blocked.
+    pub fn set_blocked(&mut self, blocked: u64) {
+        self.blocked = blocked & !((1 << SIGKILL) | (1 << SIGSTOP) | 1);
+    }
+
+    /// Returns the signal state of a child created by `fork()`: the actions and
+    /// blocked signals are inherited, nothing is pending.
+    pub fn fork(&self) -> Signals {
+        Signals {
+            actions: self.actions,
+            pending: 0,
+            blocked: self.blocked,
+        }
+    }
+
+    /// Resets the handled signals to their default action, as their handlers are
+    /// gone after `exec()`. Ignored signals stay ignored.
+    pub fn reset_handlers(&mut self) {
+        for action in self.actions.iter_mut() {
+            if let Action::Handler { .. } = *action {
+                *action = Action::Default;
+            }
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use kernel_api::{OsError, OsResult};
+
+use crate::fs::PiVFatHandle;
+use crate::log::error;
+use crate::mutex::Mutex;
+use crate::param::*;
+use crate::process::Process;
+use crate::vm::*;
+
+/// The user address space of a process, shared by all of its threads: the page
+/// table, the regions pages are mapped from on demand, and the heap.
+#[derive(Debug)]
+pub struct AddressSpace {
+    /// The page table describing the Virtual Memory of the process
+    pub vmap: Box<UserPageTable>,
+    /// The regions of the address space the process may access. Their pages are
+    /// mapped into `vmap` on first touch.
+    pub regions: Vec<Region>,
+    /// The start of the heap, the first page after the loaded image.
+    pub heap_start: usize,
+    /// The program break, the end of the heap set by `brk`.
+    pub brk: usize,
+    /// The mapped pages of shared file mappings written to since they were last
+    /// written back. The others are mapped read-only so that the first write faults.
+    dirty: BTreeSet<usize>,
+}
+
+impl AddressSpace {
+    /// Returns a new, empty address space.
+    pub fn new() -> AddressSpace {
+        AddressSpace {
+            vmap: Box::new(UserPageTable::new()),
+            regions: Vec::new(),
+            heap_start: USER_IMG_BASE,
+            brk: USER_IMG_BASE,
+            dirty: BTreeSet::new(),
+        }
+    }
+
+    /// Returns a copy of this address space for `fork()`. The pages are shared
+    /// copy-on-write between both, except the pages of shared file mappings, which
+    /// both keep writing to. They are clean in the copy: each address space only
+    /// writes back the pages it wrote to.
+    pub fn fork(&mut self) -> AddressSpace {
+        let regions = self.regions.clone();
+        let vmap = self.vmap.fork(|va| {
+            regions.iter().any(|region| region.is_shared_file() && region.overlaps(va.as_usize()))
+        });
+        AddressSpace {
+            vmap: Box::new(vmap),
+            regions: regions,
+            heap_start: self.heap_start,
+            brk: self.brk,
+            dirty: BTreeSet::new(),
+        }
+    }
+
+    /// Resolves a translation fault on `va` caused by an `access` to this address space by
+    /// mapping the page on demand. The page gets the permissions of every region that
+    /// overlaps it and is filled from all of them. A page of a shared file mapping is
+    /// only made writable by a write, which marks it dirty.
+    ///
+    /// Returns `BadAddress` if `va` does not belong to any region, `NoAccess` if the
+    /// region doesn't allow `access` and `NoMemory` if no page is left. Errors reading
+    /// a backing file are returned as is.
+    pub fn handle_fault(&mut self, va: VirtualAddr, access: Access) -> OsResult<()> {
+        let addr = va.as_usize();
+        self.grow_stack(addr);
+        let perm = match self.regions.iter().find(|region| region.contains(addr)) {
+            Some(region) => region.perm,
+            None => return Err(OsError::BadAddress),
+        };
+        if !perm.allows(access) {
+            return Err(OsError::NoAccess);
+        }
+
+        let page_va = addr & PAGE_MASK;
+        if self.vmap.get_perm(VirtualAddr::from(page_va)).is_some() {
+            return Err(OsError::NoAccess); // already mapped, not a missing page
+        }
+        let mut perm = self.page_perm(page_va, perm);
+        let tracked = self.is_shared_file(page_va);
+        if tracked && access != Access::Write {
+            perm = perm.read_only();
+        }
+        let page = self.vmap.alloc(VirtualAddr::from(page_va), perm)?;
+        for region in self.regions.iter().filter(|region| region.overlaps(page_va)) {
+            if let Err(error) = region.fill(page_va, page) {
+                self.vmap.unmap(VirtualAddr::from(page_va));
+                return Err(error);
+            }
+        }
+        if tracked && access == Access::Write {
+            self.dirty.insert(page_va);
+        }
+        Ok(())
+    }
+
+    /// Resolves a permission fault on `va` caused by a write to a page this address
+    /// space shares copy-on-write with the one of a forked process, or to a clean page
+    /// of a shared file mapping, which becomes writable and dirty.
+    ///
+    /// Returns `NoAccess` if the page is neither or the regions of the address space
+    /// don't allow writing to it.
+    pub fn handle_cow_fault(&mut self, va: VirtualAddr) -> OsResult<()> {
+        let addr = va.as_usize();
+        let perm = match self.regions.iter().find(|region| region.contains(addr)) {
+            Some(region) if region.perm.is_writable() => region.perm,
+            _ => return Err(OsError::NoAccess),
+        };
+        let page_va = addr & PAGE_MASK;
+        if !self.is_shared_file(page_va) {
+            return self.vmap.copy_on_write(va);
+        }
+        match self.vmap.copy_on_write(va) {
+            Err(OsError::NoAccess) => {
+                let perm = self.page_perm(page_va, perm);
+                if !self.vmap.set_perm(VirtualAddr::from(page_va), perm) {
+                    return Err(OsError::NoAccess);
+                }
+            },
+            result => result?,
+        }
+        self.dirty.insert(page_va);
+        Ok(())
+    }
+
+    /// Maps the pages of the user range of `len` bytes at `va` that were not touched
+    /// yet and, for `Access::Write`, copies the copy-on-write ones, so that the kernel
+    /// can access the range through the page table. It stops at the first page the
+    /// process may not access: accessing it through `vm::copy_from_user()` or
+    /// `vm::copy_to_user()` then fails with `BadAddress`.
+    ///
+    /// Returns `NoMemory` if no page is left to map one of the range.
+    pub fn fault_in(&mut self, va: usize, len: usize, access: Access) -> OsResult<()> {
+        let end = match va.checked_add(len) {
+            Some(end) if len > 0 => end,
+            _ => return Ok(()),
+        };
+        let mut page = va & PAGE_MASK;
+        loop {
+            let addr = VirtualAddr::from(core::cmp::max(page, va));
+            let result = match self.vmap.get_perm(VirtualAddr::from(page)) {
+                None => self.handle_fault(addr, access),
+                Some(perm) if access == Access::Write && !perm.is_writable() => self.handle_cow_fault(addr),
+                Some(_) => Ok(()),
+            };
+            match result {
+                Ok(()) => {},
+                Err(OsError::NoMemory) => return Err(OsError::NoMemory),
+                Err(_) => return Ok(()),
+            }
+            match page.checked_add(PAGE_SIZE) {
+                Some(next) if next < end => page = next,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Copies `buf.len()` bytes of this address space at the user address `va`
+    /// into `buf`. Returns `BadAddress` if the process may not read the range.
+    pub fn copy_from_user(&mut self, va: usize, buf: &mut [u8]) -> OsResult<()> {
+        self.fault_in(va, buf.len(), Access::Read)?;
+        copy_from_user(&self.vmap, va, buf)
+    }
+
+    /// Copies `buf` into this address space at the user address `va`.
+    /// Returns `BadAddress` if the process may not write the range.
+    pub fn copy_to_user(&mut self, va: usize, buf: &[u8]) -> OsResult<()> {
+        self.fault_in(va, buf.len(), Access::Write)?;
+        copy_to_user(&mut self.vmap, va, buf)
+    }
+
+    /// Reads the UTF-8 string of `len` bytes at the user address `va` of this address space.
+    /// Returns `InvalidArgument` if the string is longer than a page or not valid UTF-8.
+    pub fn string_from_user(&mut self, va: usize, len: usize) -> OsResult<String> {
+        // checked before any page is mapped for it
+        if len > PAGE_SIZE {
+            return Err(OsError::InvalidArgument);
+        }
+        self.fault_in(va, len, Access::Read)?;
+        string_from_user(&self.vmap, va, len)
+    }
+
+    /// Moves the program break of this address space to `new_brk`, growing or shrinking the
+    /// heap region to the page boundary above it. Pages above the new end of the heap
+    /// are unmapped.
+    ///
+    /// Returns `InvalidArgument` if `new_brk` is below the start of the heap and
+    /// `NoMemory` if the heap would run into another region.
+    pub fn set_brk(&mut self, new_brk: usize) -> OsResult<()> {
+        if new_brk < self.heap_start {
+            return Err(OsError::InvalidArgument);
+        }
+        let heap_start = self.heap_start;
+        let new_end = new_brk.checked_add(PAGE_SIZE - 1).ok_or(OsError::NoMemory)? & PAGE_MASK;
+        let old_end = heap_start + self.heap_region().map_or(0, |heap| heap.size);
+        if new_end > old_end && !self.fits(old_end, new_end - old_end) {
+            return Err(OsError::NoMemory);
+        }
+
+        match self.heap_region() {
+            Some(heap) => heap.size = new_end - heap_start,
+            None => return Err(OsError::NoMemory),
+        }
+        self.unmap_range(new_end, old_end);
+        self.brk = new_brk;
+        Ok(())
+    }
+
+    /// Maps `len` bytes of zero-filled memory with permission `perm` and returns their
+    /// address. `addr` is used if it is a free page-aligned address, otherwise the
+    /// lowest free space from `USER_MMAP_BASE` up. Pages are mapped on first touch.
+    ///
+    /// Returns `InvalidArgument` if `len` is 0 and `NoVmSpace` if there is no free space.
+    pub fn mmap(&mut self, addr: usize, len: usize, perm: PagePerm) -> OsResult<usize> {
+        let (start, size) = self.place(addr, len)?;
+        self.regions.push(Region::new(start, size, perm, Backing::Anonymous));
+        Ok(start)
+    }
+
+    /// Maps `len` bytes of `file` from the page-aligned `offset` on with permission
+    /// `perm` and returns their address, placed like `mmap()`. Pages are filled from
+    /// the file on first touch; bytes past the end of the file read as zero. If
+    /// `shared` is set, writes to the pages go back to the file on `munmap()`,
+    /// `msync()` and exit, otherwise they stay private to the process.
+    ///
+    /// Returns `InvalidArgument` if `len` is 0 or `offset` is not page-aligned and
+    /// `NoVmSpace` if there is no free space.
+    pub fn mmap_file(
+        &mut self,
+        addr: usize,
+        len: usize,
+        perm: PagePerm,
+        file: fat32::vfat::File<PiVFatHandle>,
+        offset: usize,
+        shared: bool,
+    ) -> OsResult<usize> {
+        if offset % PAGE_SIZE != 0 {
+            return Err(OsError::InvalidArgument);
+        }
+        let (start, size) = self.place(addr, len)?;
+        let backing = Backing::File {
+            file: Arc::new(Mutex::new(file)),
+            offset: offset,
+            shared: shared,
+        };
+        self.regions.push(Region::new(start, size, perm, backing));
+        Ok(start)
+    }
+
+    /// Writes the dirty pages of the shared file mappings in the `len` bytes from
+    /// the page-aligned `addr` back to their files, and the files to the disk.
+    ///
+    /// Returns `InvalidArgument` if `addr` is not page-aligned or `len` is 0, and an
+    /// I/O error if a file can't be written.
+    pub fn msync(&mut self, addr: usize, len: usize) -> OsResult<()> {
+        if addr % PAGE_SIZE != 0 || len == 0 {
+            return Err(OsError::InvalidArgument);
+        }
+        let end = addr.checked_add(len).ok_or(OsError::InvalidArgument)?;
+        self.write_back_range(addr, end)?;
+        for region in self.regions.iter().filter(|region| region.intersects(addr, end - addr)) {
+            region.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Removes the `mmap` mappings in the `len` bytes from the page-aligned `addr`,
+    /// splitting the ones that are only partially covered, and unmaps their pages.
+    /// Dirty pages of shared file mappings are written back to their files first;
+    /// writing the files to the disk is left to `msync()`.
+    ///
+    /// Returns `InvalidArgument` if `addr` is not page-aligned, `len` is 0, or the
+    /// range intersects a region that was not created by `mmap`, and an I/O error if
+    /// a file can't be written, in which case nothing is unmapped.
+    pub fn munmap(&mut self, addr: usize, len: usize) -> OsResult<()> {
+        if addr % PAGE_SIZE != 0 || len == 0 {
+            return Err(OsError::InvalidArgument);
+        }
+        let size = len.checked_add(PAGE_SIZE - 1).ok_or(OsError::InvalidArgument)? & PAGE_MASK;
+        let end = addr.checked_add(size).ok_or(OsError::InvalidArgument)?;
+        let not_mapped = self.regions.iter()
+            .filter(|region| region.intersects(addr, size))
+            .any(|region| match region.backing {
+                Backing::Anonymous | Backing::File { .. } => false,
+                _ => true,
+            });
+        if not_mapped {
+            return Err(OsError::InvalidArgument);
+        }
+        self.write_back_range(addr, end)?;
+
+        let mut regions = Vec::new();
+        for region in self.regions.drain(..) {
+            if !region.intersects(addr, size) {
+                regions.push(region);
+                continue;
+            }
+            let region_end = region.start + region.size;
+            if region.start < addr {
+                regions.push(region.slice(region.start, addr));
+            }
+            if region_end > end {
+                regions.push(region.slice(end, region_end));
+            }
+        }
+        self.regions = regions;
+        self.unmap_range(addr, end);
+        Ok(())
+    }
+
+    /// Returns the start and the page-rounded size of a new mapping of `len` bytes:
+    /// `addr` if it is a free page-aligned address, otherwise the lowest free space
+    /// from `USER_MMAP_BASE` up.
+    fn place(&self, addr: usize, len: usize) -> OsResult<(usize, usize)> {
+        if len == 0 {
+            return Err(OsError::InvalidArgument);
+        }
+        let size = len.checked_add(PAGE_SIZE - 1).ok_or(OsError::NoVmSpace)? & PAGE_MASK;
+        let start = if addr != 0 && addr % PAGE_SIZE == 0 && self.fits(addr, size) {
+            addr
+        } else {
+            self.find_free(USER_MMAP_BASE, size)?
+        };
+        Ok((start, size))
+    }
+
+    /// Writes the dirty pages of the shared file mappings between `start` and `end`
+    /// back to their files. Each page is clean and read-only again once written back.
+    fn write_back_range(&mut self, start: usize, end: usize) -> OsResult<()> {
+        let pages: Vec<usize> = self.dirty.range((start & PAGE_MASK)..end).cloned().collect();
+        for page_va in pages {
+            for region in self.regions.iter().filter(|region| region.is_shared_file() && region.overlaps(page_va)) {
+                for page in user_slices(&self.vmap, page_va, PAGE_SIZE)? {
+                    region.write_back(page_va, page)?;
+                }
+            }
+            if let Some(perm) = self.vmap.get_perm(VirtualAddr::from(page_va)) {
+                self.vmap.set_perm(VirtualAddr::from(page_va), perm.read_only());
+            }
+            self.dirty.remove(&page_va);
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the page starting at `page_va` belongs to a shared file
+    /// mapping, whose writes are tracked to write back only the dirty pages.
+    fn is_shared_file(&self, page_va: usize) -> bool {
+        self.regions.iter().any(|region| region.is_shared_file() && region.overlaps(page_va))
+    }
+
+    /// Returns `perm` extended with the permissions of every region that overlaps
+    /// the page starting at `page_va`.
+    fn page_perm(&self, page_va: usize, perm: PagePerm) -> PagePerm {
+        self.regions.iter()
+            .filter(|region| region.overlaps(page_va))
+            .fold(perm, |perm, region| perm.union(region.perm))
+    }
+
+    /// Returns `true` if the `size` bytes from `start` lie in the user address space
+    /// below the stack guard page and no region of this address space intersects them.
+    fn fits(&self, start: usize, size: usize) -> bool {
+        match start.checked_add(size) {
+            Some(end) if start >= USER_IMG_BASE && end <= Process::get_stack_guard().as_usize() => {
+                !self.regions.iter().any(|region| region.intersects(start, size))
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the lowest page-aligned address from `from` up where `size` bytes fit.
+    fn find_free(&self, from: usize, size: usize) -> OsResult<usize> {
+        let guard = Process::get_stack_guard().as_usize();
+        let mut start = from;
+        loop {
+            match start.checked_add(size) {
+                Some(end) if end <= guard => {},
+                _ => return Err(OsError::NoVmSpace),
+            }
+            match self.regions.iter().find(|region| region.intersects(start, size)) {
+                // regions below the guard page end below it, this can't overflow
+                Some(region) => start = (region.start + region.size + (PAGE_SIZE - 1)) & PAGE_MASK,
+                None => return Ok(start),
+            }
+        }
+    }
+
+    /// Unmaps the pages between `start` and `end` that no region of this address space
+    /// overlaps anymore.
+    fn unmap_range(&mut self, start: usize, end: usize) {
+        let mut page = start & PAGE_MASK;
+        while page < end {
+            if !self.regions.iter().any(|region| region.overlaps(page)) {
+                self.vmap.unmap(VirtualAddr::from(page));
+                self.dirty.remove(&page);
+            }
+            page += PAGE_SIZE;
+        }
+    }
+
+    /// Returns the heap region of this address space.
+    fn heap_region(&mut self) -> Option<&mut Region> {
+        self.regions.iter_mut().find(|region| match region.backing {
+            Backing::Heap => true,
+            _ => false,
+        })
+    }
+
+    /// Grows the stack region down so that it covers `va` if `va` is between the
+    /// stack limit and the current bottom of the stack, and no other region is in the way.
+    /// Returns `true` if the stack was grown.
+    fn grow_stack(&mut self, va: usize) -> bool {
+        let limit = Process::get_stack_limit().as_usize();
+        let bottom = match self.stack_region() {
+            Some(stack) => stack.start,
+            None => return false,
+        };
+        if va < limit || va >= bottom {
+            return false;
+        }
+        let new_bottom = va & PAGE_MASK;
+        if self.regions.iter().any(|region| region.start < bottom && region.start + region.size > new_bottom) {
+            return false;
+        }
+        let stack = self.stack_region().unwrap();
+        stack.size += bottom - new_bottom;
+        stack.start = new_bottom;
+        true
+    }
+
+    /// Returns the region of the user stack.
+    fn stack_region(&mut self) -> Option<&mut Region> {
+        self.regions.iter_mut().find(|region| match region.backing {
+            Backing::Stack => true,
+            _ => false,
+        })
+    }
+}
+
+impl Drop for AddressSpace {
+    /// Writes the dirty pages of the shared file mappings back to their files once
+    /// the last thread of the process lets go of the address space, on exit or exec.
+    fn drop(&mut self) {
+        if let Err(error) = self.write_back_range(0, usize::max_value()) {
+            error!("Failed to write back shared file mappings: {:?}", error);
+        }
+    }
+}
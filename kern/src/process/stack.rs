@@ -2,17 +2,19 @@ use core::alloc::{GlobalAlloc, Layout};
 use core::fmt;
 use core::ptr::Unique;
 
+use aarch64::{affinity, SP};
+
 use crate::vm::PhysicalAddr;
 use crate::ALLOCATOR;
-use crate::param::PAGE_SIZE;
-/// A process stack. The default size is 1MiB with an alignment of 16 bytes.
+use crate::param::{KERN_STACK_BASE, KERN_STACK_SIZE, PAGE_SIZE};
+/// The stack of a kernel thread, of 256KiB with an alignment of 16 bytes.
 pub struct Stack {
-    pub ptr: Unique<[u8; PAGE_SIZE]>,
+    pub ptr: Unique<u8>,
 }
 
 impl Stack {
-    /// The default stack size is 1MiB.
-    pub const SIZE: usize = PAGE_SIZE;
+    /// The default stack size is 256KiB.
+    pub const SIZE: usize = 4 * PAGE_SIZE;
 
     /// The default stack alignment is 16 bytes.
     pub const ALIGN: usize = 16;
@@ -33,13 +35,13 @@ impl Stack {
             raw_ptr
         };
 
-        let ptr = Unique::new(raw_ptr as *mut _).expect("non-null");
+        let ptr = Unique::new(raw_ptr).expect("non-null");
         Some(Stack { ptr })
     }
 
     /// Internal method to cast to a `*mut u8`.
     unsafe fn as_mut_ptr(&self) -> *mut u8 {
-        self.ptr.as_ptr() as _
+        self.ptr.as_ptr()
     }
 
     /// Returns the physical address of top of the stack.
@@ -68,3 +70,34 @@ impl fmt::Debug for Stack {
             .finish()
     }
 }
+
+/// The word `mark_core_stack()` leaves at the bottom of the kernel stack of a
+/// core. A core running past the end of its stack overwrites it.
+const STACK_MARK: u64 = 0x6b73_7461_7473_6b6d;
+
+/// Returns the lowest address of the kernel stack of `core`.
+fn core_stack_bottom(core: usize) -> usize {
+    KERN_STACK_BASE - KERN_STACK_SIZE * (core + 1)
+}
+
+/// Marks the bottom of the kernel stack of the current core for
+/// `check_core_stack()`. Every core calls it once, when it starts.
+pub fn mark_core_stack() {
+    unsafe { (core_stack_bottom(affinity()) as *mut u64).write_volatile(STACK_MARK) };
+}
+
+/// Checks that the current core, running on its kernel stack, has not run past
+/// the end of it into the stack of the next core.
+///
+/// # Panics
+///
+/// Panics if the stack pointer is below the bottom of the stack or the mark
+/// `mark_core_stack()` left there was overwritten.
+pub fn check_core_stack() {
+    let core = affinity();
+    let bottom = core_stack_bottom(core);
+    let sp = SP.get();
+    let mark = unsafe { (bottom as *const u64).read_volatile() };
+    assert!(sp > bottom && mark == STACK_MARK,
+        "core {} ran past the end of its kernel stack (sp 0x{:x})", core, sp);
+}
@@ -1,4 +1,5 @@
 use core::fmt;
+use core::time::Duration;
 
 use alloc::boxed::Box;
 
@@ -17,8 +18,17 @@ pub enum State {
     Ready,
     /// The process is waiting on an event to occur before it can be scheduled.
     Waiting(EventPollFn),
+    /// The process is sleeping until the given time. The scheduler wakes it from
+    /// its sleep queue instead of polling it.
+    Sleeping(Duration),
+    /// The thread is parked at the futex `Process::futex` until `futex_wake()` or,
+    /// if any, the given time. The scheduler wakes it instead of polling it.
+    Parked(Option<Duration>),
     /// The process is currently running.
     Running,
+    /// The process has exited with the given status and is waiting for its parent
+    /// to collect it with `wait`.
+    Zombie(i32),
     /// The process is currently dead (ready to be reclaimed).
     Dead,
 }
@@ -29,6 +39,9 @@ impl fmt::Debug for State {
             State::Ready => write!(f, "State::Ready"),
             State::Running => write!(f, "State::Running"),
             State::Waiting(_) => write!(f, "State::Waiting"),
+            State::Sleeping(deadline) => write!(f, "State::Sleeping({:?})", deadline),
+            State::Parked(deadline) => write!(f, "State::Parked({:?})", deadline),
+            State::Zombie(code) => write!(f, "State::Zombie({})", code),
             State::Dead => write!(f, "State::Dead"),
         }
     }
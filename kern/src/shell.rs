@@ -1,10 +1,18 @@
 use shim::path::{Path, PathBuf};
 use stack_vec::StackVec;
 use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::format;
+use core::time::Duration;
 use fat32::traits::FileSystem;
 use fat32::traits::{Dir, Entry, Timestamp, Metadata};
-use crate::console::{kprint, kprintln, CONSOLE};
+use crate::console::{kprint, kprintln, WriteThrough, CONSOLE};
 use crate::FILESYSTEM;
+use crate::SCHEDULER;
+use crate::param::NCORES;
+use crate::process::ProcessInfo;
+use crate::log::{Level, LOGGER};
+use pi::timer::current_time;
 use core::fmt::Write;
 use core::str::FromStr;
 use crate::elfparser::{ELF, SectionTable, SymbolTable, DynamicSymbolTable, GnuVersionReq, GnuVersion, RelaTable, RelaPLT, DynamicTable};
@@ -58,14 +66,23 @@ pub fn shell(prefix: &str) {
     const NEWLINE: u8   = 0x0A;         // Line Feed
     const CR: u8        = 0x0D;         // Carriage Return, move cursor back to the head of line
     let mut working_dir = PathBuf::from("/");
+    // the commands print with `kprint!`, so the console is only locked for each write
+    let mut console = WriteThrough;
     'shell: loop { // loop lines to lines
         kprint!("{} ",prefix);
         
         let mut command_buf = [0u8; 512]; // store the command str in here :D
         let mut command = StackVec::new(&mut command_buf);
         'line: loop { // loop characters to characters
-            let mut console = CONSOLE.lock();
-            let input = console.read_byte();
+            // wait for input without holding the console, which the other cores print to
+            while !CONSOLE.lock().has_byte() {
+                if aarch64::sp_sel() == 0 {
+                    // in a kernel thread rather than an exception handler: let the
+                    // other processes run meanwhile
+                    let _ = kernel_api::syscall::sleep(core::time::Duration::from_millis(10));
+                }
+            }
+            let input = CONSOLE.lock().read_byte();
 
             if !input.is_ascii() { // invalid ascii, unregconizable
                 console.write_byte(BELL); // ring bell and go back
@@ -98,7 +115,7 @@ pub fn shell(prefix: &str) {
                             loop {
                                 match iterator.next() {
                                     Some(element) => {
-                                        (*console).write_str(element);
+                                        console.write_str(element);
                                         console.write_byte(b' ');
                                     },
                                     None          => {
@@ -116,7 +133,7 @@ pub fn shell(prefix: &str) {
                                 kprintln!("Usage: sleep <ms>");
                             }
                             let ms : u32 = u32::from_str(com.args[1]).unwrap();
-                            
+
                             kernel_api::syscall::sleep(core::time::Duration::from_millis(ms as u64));
                         
                         } else if Command::path(&com) == "readelf" {
@@ -371,6 +388,82 @@ pub fn shell(prefix: &str) {
                                     }
                                 }
                             }
+                        } else if Command::path(&com) == "uptime" {
+                            if com.args.len() > 1 {
+                                kprintln!("Too many arguments");
+                                kprintln!("Usage: uptime");
+                                break 'line;
+                            }
+                            let up = current_time();
+                            let idle = SCHEDULER.idle_time();
+                            // every core has an idle task
+                            let capacity = up.as_micros() * NCORES as u128;
+                            let busy = if capacity == 0 {
+                                0
+                            } else {
+                                100 - idle.as_micros() * 100 / capacity
+                            };
+                            kprint!("up {}.{:03}s, idle {}.{:03}s, {}% busy",
+                                up.as_secs(), up.subsec_millis(), idle.as_secs(), idle.subsec_millis(), busy);
+                        } else if Command::path(&com) == "ps" {
+                            if com.args.len() > 1 {
+                                kprintln!("Too many arguments");
+                                kprintln!("Usage: ps");
+                                break 'line;
+                            }
+                            let processes = SCHEDULER.snapshot();
+                            print_processes(&processes, None);
+                            break 'line;
+                        } else if Command::path(&com) == "top" {
+                            if com.args.len() > 1 {
+                                kprintln!("Too many arguments");
+                                kprintln!("Usage: top");
+                                break 'line;
+                            }
+                            top();
+                            break 'line;
+                        } else if Command::path(&com) == "dmesg" {
+                            // dmesg [-c] [-l <level>] [-n <level>]
+                            let mut level = Level::Trace;
+                            let mut console_level = None;
+                            let mut clear = false;
+                            let mut index = 1;
+                            while index < com.args.len() {
+                                let option = com.args[index];
+                                if option == "-c" {
+                                    clear = true;
+                                } else if (option == "-l" || option == "-n") && index + 1 < com.args.len() {
+                                    index += 1;
+                                    match Level::from_str(com.args[index]) {
+                                        Ok(parsed) if option == "-l" => level = parsed,
+                                        Ok(parsed) => console_level = Some(parsed),
+                                        Err(_) => {
+                                            kprintln!("Unknown level: {}", com.args[index]);
+                                            break 'line;
+                                        }
+                                    }
+                                } else {
+                                    kprintln!("Usage: dmesg [-c] [-l <level>] [-n <level>]");
+                                    kprintln!("  -c          Clear the log after printing it");
+                                    kprintln!("  -l <level>  Print the records of <level> or more severe");
+                                    kprintln!("  -n <level>  Set the level of the records printed to the console");
+                                    kprintln!(" Levels are error, warn, info, debug and trace, or 1 to 5");
+                                    break 'line;
+                                }
+                                index += 1;
+                            }
+                            if let Some(console_level) = console_level {
+                                LOGGER.set_console_level(console_level);
+                                kprintln!("Console log level: {}", console_level.name());
+                                break 'line;
+                            }
+                            for (record_level, text) in LOGGER.records(level) {
+                                kprintln!("{:<5} {}", record_level.name(), text);
+                            }
+                            if clear {
+                                LOGGER.clear();
+                            }
+                            break 'line;
                         } else {
                             kprint!("unknown command: ");
                             kprint!("{}", Command::path(&com));
@@ -449,3 +542,92 @@ fn write_timestamp<TS: Timestamp>(timestamp : TS) {
     kprint!("{:02}/{:02}/{} {:02}:{:02}:{:02}   ",
         timestamp.month(), timestamp.day(), timestamp.year(), timestamp.hour(), timestamp.minute(), timestamp.second());
 }
+
+/// How often `top` refreshes its view.
+const TOP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shows the processes every `TOP_INTERVAL`, the busiest first, until a key is
+/// pressed.
+fn top() {
+    let mut previous = SCHEDULER.snapshot();
+    let mut idle = SCHEDULER.idle_time();
+    let mut last = current_time();
+    loop {
+        while current_time() < last + TOP_INTERVAL {
+            if CONSOLE.lock().has_byte() {
+                CONSOLE.lock().read_byte();
+                return;
+            }
+            if aarch64::sp_sel() == 0 {
+                let _ = kernel_api::syscall::sleep(Duration::from_millis(10));
+            }
+        }
+        let now = current_time();
+        let processes = SCHEDULER.snapshot();
+        let now_idle = SCHEDULER.idle_time();
+        let elapsed = now - last;
+        // every core has an idle task
+        let capacity = elapsed.as_micros() * NCORES as u128;
+        let idle_delta = now_idle.checked_sub(idle).unwrap_or(Duration::from_secs(0));
+        let busy = if capacity == 0 {
+            0
+        } else {
+            100 - core::cmp::min(idle_delta.as_micros() * 100 / capacity, 100)
+        };
+        // clear the screen and go to its top left corner
+        kprint!("\x1b[2J\x1b[H");
+        kprintln!("up {}.{:03}s, {} processes, {}% busy, press any key to quit",
+            now.as_secs(), now.subsec_millis(), processes.len(), busy);
+        print_processes(&processes, Some((&previous[..], elapsed)));
+        previous = processes;
+        idle = now_idle;
+        last = now;
+    }
+}
+
+/// Prints a line per process of `processes`. With `previous`, a snapshot taken
+/// the given time earlier, the percentage of a core each process used meanwhile
+/// is printed too, and the busiest processes come first.
+fn print_processes(processes: &[ProcessInfo], previous: Option<(&[ProcessInfo], Duration)>) {
+    let mut rows: Vec<(&ProcessInfo, Option<u64>)> = processes.iter()
+        .map(|info| {
+            let share = previous.map(|(previous, elapsed)| {
+                let before = previous.iter()
+                    .find(|old| old.pid == info.pid)
+                    .map(|old| old.usage.cpu_time)
+                    .unwrap_or(0);
+                let elapsed = elapsed.as_micros() as u64;
+                if elapsed == 0 {
+                    0
+                } else {
+                    info.usage.cpu_time.saturating_sub(before) * 100 / elapsed
+                }
+            });
+            (info, share)
+        })
+        .collect();
+    if previous.is_some() {
+        rows.sort_by_key(|&(info, share)| (core::cmp::Reverse(share), info.pid));
+    }
+
+    kprint!("{:>5} {:>3} {:>3} {:<7} {:>3} ", "PID", "THR", "CPU", "STATE", "NI");
+    if previous.is_some() {
+        kprint!("{:>4} ", "%CPU");
+    }
+    kprintln!("{:>10} {:>6} {:>6} {:>5} {:>10} {}", "TIME", "VCSW", "IVCSW", "PAGES", "START", "NAME");
+    for (info, share) in rows {
+        kprint!("{:>5} {:>3} {:>3} {:<7} {:>3} ", info.pid, info.threads, info.core, info.state, info.nice);
+        if let Some(share) = share {
+            kprint!("{:>4} ", share);
+        }
+        kprintln!("{:>10} {:>6} {:>6} {:>5} {:>10} {}",
+            format_micros(info.usage.cpu_time), info.usage.voluntary_switches,
+            info.usage.involuntary_switches, info.usage.pages,
+            format_micros(info.usage.start_time), info.name);
+    }
+}
+
+/// Formats a number of microseconds as seconds with three decimals.
+fn format_micros(us: u64) -> String {
+    format!("{}.{:03}", us / 1_000_000, us / 1000 % 1000)
+}
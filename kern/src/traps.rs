@@ -6,12 +6,24 @@ pub mod irq;
 use crate::shell::shell;
 pub use self::frame::TrapFrame;
 
-use pi::interrupt::{Interrupt};
-use crate::vm::{VirtualAddr};
-use self::syndrome::Syndrome;
+use pi::interrupt::{Controller, Interrupt};
+use pi::local_interrupt::{LocalController, LocalInterrupt};
+use aarch64::{affinity, FAR_EL1};
+use crate::vm::{VirtualAddr, Access};
+use self::syndrome::{Syndrome, Fault};
 use self::syscall::handle_syscall;
-use crate::console::{kprintln};
+use crate::backtrace;
+use crate::log::{error, info, warn};
 use crate::IRQ;
+use crate::SCHEDULER;
+use crate::process::{check_core_stack, Delivery, GlobalScheduler, State};
+use kernel_api::{OsError, SIGBUS, SIGSEGV};
+use alloc::string::String;
+
+/// The `M` bits of `SPSR_EL1`, the exception level and stack pointer to return to.
+/// They are 0 for EL0.
+const SPSR_MODE: u64 = 0b1111;
+
 #[repr(u16)]
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Kind {
@@ -41,29 +53,150 @@ pub struct Info {
 /// specifies the source and kind of exception that has occurred. The `esr` is
 /// the value of the exception syndrome register. Finally, `tf` is a pointer to
 /// the trap frame for the exception.
+///
+/// Exceptions are handled on the kernel stack of the core, which is checked for
+/// an overflow on the way in and out: it has no guard page.
 #[no_mangle]
 pub extern "C" fn handle_exception(info: Info, esr: u32, tf: &mut TrapFrame) {
+    check_core_stack();
     let syndrome = Syndrome::from(esr);
     if info.kind == Kind::Irq {
-        IRQ.invoke(Interrupt::Timer1, tf);
+        handle_irq(tf);
     } else {
         match syndrome {
             Syndrome::Brk(a) => {
-                kprintln!("Encountering brk {}", a);
+                info!("Encountering brk {}", a);
                 shell(">");
                 tf.elr += 4 as u64; 
             },
             Syndrome::Svc(a) => {
                 handle_syscall(a as u16, tf); // sleep: a = 1 : svc(1)
             },
+            Syndrome::DataAbort { kind, write, .. } if info.source == Source::LowerAArch64 => {
+                let access = if write { Access::Write } else { Access::Read };
+                handle_page_fault(kind, access, tf);
+            },
+            Syndrome::InstructionAbort { kind, .. } if info.source == Source::LowerAArch64 => {
+                handle_page_fault(kind, Access::Execute, tf);
+            },
             _ => {
-                kprintln!("info: {:?}", info);
-                kprintln!("Program counter: {:?}", VirtualAddr::from(tf.elr));
-                kprintln!("EXCEPTION ENCOUNTERED...SOMETHING WENT WRONG");
+                error!("info: {:?}", info);
+                error!("Program counter: {:?}", VirtualAddr::from(tf.elr));
+                if info.source == Source::CurrentSpEl0 || info.source == Source::CurrentSpElx {
+                    backtrace::print_kernel(tf.elr, tf.x29);
+                }
+                error!("EXCEPTION ENCOUNTERED...SOMETHING WENT WRONG");
                 loop {} // use this when debugging            
             }
         }  
         
     }
-    
+
+    if tf.spsr & SPSR_MODE == 0 { // returning to EL0
+        handle_signals(tf);
+    }
+    check_core_stack();
+}
+
+/// Handles an IRQ on the current core: the mailbox of the core carries the
+/// inter-processor interrupts of the scheduler, the timer of the core drives the
+/// scheduler, and the pending peripheral interrupts, routed to core 0, go to their
+/// handlers.
+fn handle_irq(tf: &mut TrapFrame) {
+    let local = LocalController::new(affinity());
+    if local.is_pending(LocalInterrupt::Mailbox0) {
+        GlobalScheduler::ipi_handler(tf);
+        return;
+    }
+    if local.is_pending(LocalInterrupt::CntPnsIrq) {
+        GlobalScheduler::tick_handler(tf);
+        return;
+    }
+    let controller = Controller::new();
+    for int in Interrupt::iter() {
+        if controller.is_pending(*int) {
+            IRQ.invoke(*int, tf);
+        }
+    }
+}
+
+/// Delivers the pending signals of the process about to return to user space with
+/// `tf`. A caught signal makes `tf` enter its handler. A signal terminating the
+/// process or stopping it switches to the next process, whose signals are then
+/// delivered in turn.
+fn handle_signals(tf: &mut TrapFrame) {
+    if !SCHEDULER.is_running(tf.tpidr) {
+        // the process was terminated on another core during the exception
+        SCHEDULER.switch_to(tf);
+    }
+    loop {
+        let delivery = SCHEDULER.with_process(tf.tpidr, |process| Ok(process.deliver_signal(tf)))
+            .unwrap_or(None);
+        match delivery {
+            Some(Delivery::Terminate(sig)) => {
+                info!("Process {} terminated by signal {}", tf.tpidr, sig);
+                let _ = SCHEDULER.exit(128 + sig as i32, tf);
+                SCHEDULER.switch_to(tf);
+            },
+            Some(Delivery::Stop(_)) => {
+                SCHEDULER.switch(State::Ready, tf);
+            },
+            _ => return,
+        }
+    }
+}
+
+/// Handles an instruction or data abort of kind `kind` taken from user space on the
+/// address in `FAR_EL1`. A translation fault inside one of the regions of the process
+/// is resolved by mapping the page on demand, and a write to a copy-on-write page by
+/// copying it; the faulting instruction is then retried. Any other fault is a
+/// segmentation fault: it is delivered as `SIGSEGV` (`SIGBUS` for alignment faults)
+/// if the process has a handler for it, otherwise it is reported and the process is
+/// killed. A process no page is left for is killed as well.
+fn handle_page_fault(kind: Fault, access: Access, tf: &mut TrapFrame) {
+    let far = VirtualAddr::from(unsafe { FAR_EL1.get() });
+    let result = SCHEDULER.with_process(tf.tpidr, |process| {
+        let result = match kind {
+            Fault::Translation => process.handle_fault(far, access),
+            Fault::Permission if access == Access::Write => process.handle_cow_fault(far),
+            _ => Err(OsError::NoAccess),
+        };
+        let sig = match kind {
+            Fault::Alignment => SIGBUS,
+            _ => SIGSEGV,
+        };
+        let caught = match result {
+            Ok(()) | Err(OsError::NoMemory) => false,
+            Err(_) => process.signals.catches(sig),
+        };
+        if caught {
+            // the handler runs on the way back to user space
+            process.send_signal(sig);
+            return Ok(Ok(()));
+        }
+        Ok(result.map_err(|error| {
+            (error, process.space.lock().vmap.get_perm(far), process.is_stack_overflow(far, tf.sp), process.name.clone())
+        }))
+    }).unwrap_or_else(|error| Err((error, None, false, String::new())));
+
+    if let Err((error, perm, overflow, name)) = result {
+        if error == OsError::NoMemory {
+            warn!("Out of memory in process {} ({}): no page for {:?} access to {:?}",
+                tf.tpidr, name, access, far);
+        } else if overflow {
+            warn!("Stack overflow in process {} ({}): {:?} access to {:?}, stack pointer {:?}",
+                tf.tpidr, name, access, far, VirtualAddr::from(tf.sp));
+        } else {
+            warn!("Segmentation fault in process {} ({}): {:?} fault on {:?} access to {:?} ({:?})",
+                tf.tpidr, name, kind, access, far, error);
+        }
+        match perm {
+            Some(perm) => warn!("  page permission: {:?}", perm),
+            None => warn!("  page is not mapped"),
+        }
+        warn!("  program counter: {:?}", VirtualAddr::from(tf.elr));
+        backtrace::print_user(tf);
+        let _ = SCHEDULER.kill(tf);
+        SCHEDULER.switch_to(tf);
+    }
 }
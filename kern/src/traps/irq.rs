@@ -3,7 +3,7 @@ use pi::interrupt::Interrupt;
 
 use crate::mutex::Mutex;
 use crate::traps::TrapFrame;
-use crate::console::{kprintln};
+use crate::log::warn;
 pub type IrqHandler = Box<dyn FnMut(&mut TrapFrame) + Send>; // wrapper handler function
 pub type IrqHandlers = [Option<IrqHandler>; Interrupt::MAX]; // vectors of wrapper handler
 
@@ -42,7 +42,7 @@ impl Irq {
                         function(tf);
                     },
                     None => {
-                        kprintln!("There is no handler function for this interrupt");
+                        warn!("There is no handler function for interrupt {}", index);
                     }
                 }
             },
@@ -41,7 +41,7 @@ pub enum Syndrome {
     MsrMrsSystem,
     InstructionAbort { kind: Fault, level: u8 },
     PCAlignmentFault,
-    DataAbort { kind: Fault, level: u8 },
+    DataAbort { kind: Fault, level: u8, write: bool },
     SpAlignmentFault,
     TrappedFpu,
     SError,
@@ -79,6 +79,7 @@ impl From<u32> for Syndrome {
                 Syndrome::DataAbort {
                     kind : Fault::from(esr),
                     level : level,
+                    write : (hsvc_imm >> 6) & 0b1 == 1, // WnR bit
                 }
             },
             0b100110 => Syndrome::SpAlignmentFault,
@@ -1,37 +1,33 @@
 use alloc::boxed::Box;
-use crate::process::{State, Process};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use shim::io::SeekFrom;
+use shim::path::Path;
+use fat32::traits::{Entry, FileSystem};
+use crate::mutex::Mutex;
+use crate::param::{PAGE_SIZE, USER_IMG_BASE};
+use crate::process::{pipe, Action, State, Process, Signals, WaitTarget, Descriptor};
+use crate::vm::PagePerm;
+use crate::FILESYSTEM;
 use crate::traps::TrapFrame;
 use crate::SCHEDULER;
 use kernel_api::*;
 use pi::timer::current_time;
-use crate::console::{kprintln, kprint};
+use core::time::Duration;
+use crate::console::kprint;
+use crate::log::debug;
 /// Sleep for `ms` milliseconds.
 ///
 /// This system call takes one parameter: the number of milliseconds to sleep.
 ///
 /// In addition to the usual status value, this system call returns one
 /// parameter: the approximate true elapsed time from when `sleep` was called to
-/// when `sleep` returned.
+/// when `sleep` returned. It returns `Interrupted` if a signal arrives first.
 pub fn sys_sleep(ms: u32, tf: &mut TrapFrame) {
-    let time_start = current_time().as_millis();
-    let mut time_done = 0u128;
-    let final_time = current_time().as_millis() + ms as u128;
-    let boxed_fnmut = Box::new(move |process : &mut Process| -> bool { 
-        kprintln!("Timer Progress for process: {}, current_time: {}. Final time: {}",process.context.tpidr, current_time().as_millis(), final_time);
-        if current_time().as_millis() <= final_time {
-            kprintln!("TIME IS NOT UP");
-            false
-        } else {
-            time_done = current_time().as_millis();
-            kprintln!("TIME IS UP!!");
-            process.state = State::Ready;
-            process.context.x0 = (time_done - time_start) as u64; // elapsed time
-            process.context.x7 = 1;
-            true
-        }
-    });
-    
-    SCHEDULER.switch(State::Waiting(boxed_fnmut), tf);
+    // the scheduler wakes the process from its sleep queue once the deadline passed
+    let deadline = current_time() + Duration::from_millis(ms as u64);
+    SCHEDULER.sleep(deadline, tf);
 }
 
 /// Returns current time.
@@ -51,12 +47,49 @@ pub fn sys_time(tf: &mut TrapFrame) {
     tf.x7 = 1;
 }
 
-/// Kills current process.
+/// Terminates current process.
 ///
-/// This system call does not take paramer and does not return any value.
-pub fn sys_exit(tf: &mut TrapFrame) {
-    kprintln!("Sys_exit is called for process {}", tf.tpidr);
-    SCHEDULER.switch(State::Dead, tf);
+/// This system call takes one parameter: the exit status of the process, which
+/// its parent collects with `wait`. It does not return.
+pub fn sys_exit(code: i32, tf: &mut TrapFrame) {
+    debug!("Process {} exits with status {}", tf.tpidr, code);
+    let _ = SCHEDULER.exit(code, tf);
+    SCHEDULER.switch_to(tf);
+}
+
+/// Waits for a child of the current process to exit.
+///
+/// This system call takes one parameter: the ID of the child to wait for, or 0
+/// to wait for any child.
+///
+/// In addition to the usual status value, this system call returns two
+/// parameters: the ID of the child that exited and its exit status. It returns
+/// `NoEntry` if the process has no such child.
+pub fn sys_wait(pid: u64, tf: &mut TrapFrame) {
+    let target = match pid {
+        0 => WaitTarget::Any,
+        pid => WaitTarget::Child(pid),
+    };
+    let result = SCHEDULER.with_process(tf.tpidr, |process| {
+        let has_child = match target {
+            WaitTarget::Any => !process.children.is_empty(),
+            WaitTarget::Child(pid) => process.children.contains(&pid),
+        };
+        if !has_child {
+            return Err(OsError::NoEntry);
+        }
+        process.wait_target = Some(target);
+        Ok(())
+    });
+
+    match result {
+        // the scheduler hands over the status of the child once it has exited
+        Ok(()) => {
+            let collected = Box::new(|process: &mut Process| process.wait_target.is_none());
+            SCHEDULER.switch(State::Waiting(collected), tf);
+        },
+        Err(error) => tf.x7 = error as u64,
+    }
 }
 
 /// Write to console.
@@ -76,10 +109,683 @@ pub fn sys_write(b: u8, tf: &mut TrapFrame) {
 /// In addition to the usual status value, this system call returns a
 /// parameter: the current process's ID.
 pub fn sys_getpid(tf: &mut TrapFrame) {
-    tf.x0 = tf.tpidr;
+    let pid = SCHEDULER.with_process(tf.tpidr, |process| Ok(process.pid()));
+    tf.x0 = pid.unwrap_or(tf.tpidr);
     tf.x7 = 1;
 }
 
+/// Creates a copy of the current process.
+///
+/// This system call does not take parameter.
+///
+/// In addition to the usual status value, this system call returns a
+/// parameter: the ID of the new process to the parent, and 0 to the child.
+pub fn sys_fork(tf: &mut TrapFrame) {
+    let child = SCHEDULER.with_process(tf.tpidr, |process| process.fork(tf));
+    let result = child.and_then(|child| SCHEDULER.add(child).ok_or(OsError::NoMemory));
+    if let Ok(id) = result {
+        let _ = SCHEDULER.with_process(tf.tpidr, |process| {
+            process.children.push(id);
+            Ok(())
+        });
+    }
+    match result {
+        Ok(id) => {
+            tf.x0 = id;
+            tf.x7 = 1;
+        },
+        Err(error) => tf.x7 = error as u64,
+    }
+}
+
+/// Reads the path and the arguments passed to `exec` by the current process.
+fn read_exec_args(tf: &TrapFrame) -> OsResult<(String, Vec<String>)> {
+    let argc = tf.x3 as usize;
+    if argc > MAX_EXEC_ARGS {
+        return Err(OsError::InvalidArgument);
+    }
+    SCHEDULER.with_process(tf.tpidr, |process| {
+        let path = process.string_from_user(tf.x0 as usize, tf.x1 as usize)?;
+        let mut args = Vec::new();
+        for index in 0..argc {
+            let mut pair = [0u8; 16];
+            process.copy_from_user((tf.x2 as usize).wrapping_add(index * 16), &mut pair)?;
+            let mut ptr = [0u8; 8];
+            let mut len = [0u8; 8];
+            ptr.copy_from_slice(&pair[..8]);
+            len.copy_from_slice(&pair[8..]);
+            let arg = process.string_from_user(u64::from_le_bytes(ptr) as usize, u64::from_le_bytes(len) as usize)?;
+            args.push(arg);
+        }
+        Ok((path, args))
+    })
+}
+
+/// Replaces the image of the current process with an ELF executable.
+///
+/// This system call takes four parameters: the address and the length of the
+/// path of the executable, and the address and the number of `[ptr, len]` pairs
+/// describing its arguments.
+///
+/// It does not return on success: the process, with the same ID, starts at the
+/// entry point of the new image with the number of arguments in `x0` and the
+/// address of their pairs in `x1`, and the other threads of the process are
+/// ended. Otherwise it returns the usual status value and the current image is
+/// left untouched. It returns `InvalidArgument` if called by a thread other than
+/// the main one.
+pub fn sys_exec(tf: &mut TrapFrame) {
+    let result = read_exec_args(tf).and_then(|(path, args)| {
+        let mut image = Process::load(Path::new(&path))?;
+        image.push_args(&args)?;
+        let old_space = SCHEDULER.with_process(tf.tpidr, |process| {
+            if process.leader.is_some() {
+                return Err(OsError::InvalidArgument); // only the main thread may exec
+            }
+            let old_space = process.exec(image);
+            *tf = *process.context;
+            Ok(old_space)
+        })?;
+        SCHEDULER.kill_other_threads(tf.tpidr);
+        // writes the shared file mappings back, with no queue locked
+        drop(old_space);
+        Ok(())
+    });
+    if let Err(error) = result {
+        tf.x7 = error as u64;
+    }
+}
+
+/// Returns the object the descriptor `fd` of the current process refers to.
+fn get_descriptor(fd: u64, tf: &TrapFrame) -> OsResult<Arc<Mutex<Descriptor>>> {
+    SCHEDULER.with_process(tf.tpidr, |process| {
+        process.files.lock().get(fd as usize)
+    })
+}
+
+/// Sets the return values of a system call returning `result` in `x0`.
+fn set_result(result: OsResult<u64>, tf: &mut TrapFrame) {
+    match result {
+        Ok(value) => {
+            tf.x0 = value;
+            tf.x7 = 1;
+        },
+        Err(error) => tf.x7 = error as u64,
+    }
+}
+
+/// Opens a file for reading.
+///
+/// This system call takes two parameters: the address and the length of the
+/// absolute path of the file.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the new file descriptor. It returns `NoEntry` if there is no file
+/// at the path.
+pub fn sys_open(path: usize, len: usize, tf: &mut TrapFrame) {
+    let path = SCHEDULER.with_process(tf.tpidr, |process| {
+        process.string_from_user(path, len)
+    });
+    let file = path.and_then(|path| {
+        let entry = FILESYSTEM.open(Path::new(&path))?;
+        entry.into_file().ok_or(OsError::NoEntry)
+    });
+    let result = file.and_then(|file| {
+        SCHEDULER.with_process(tf.tpidr, |process| {
+            process.files.lock().insert(Descriptor::File(file))
+        })
+    });
+    set_result(result.map(|fd| fd as u64), tf);
+}
+
+/// Reads up to a page from `descriptor` into a new buffer of at most `len` bytes.
+fn read_descriptor(descriptor: &Mutex<Descriptor>, len: usize) -> OsResult<Vec<u8>> {
+    let mut data = Vec::new();
+    data.resize(core::cmp::min(len, PAGE_SIZE), 0u8);
+    let count = descriptor.lock().read(&mut data)?;
+    data.truncate(count);
+    Ok(data)
+}
+
+/// Reads from a file descriptor.
+///
+/// This system call takes three parameters: the file descriptor, and the
+/// address and the length of the user buffer to read into.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the number of bytes read, 0 at the end of the file or of a pipe
+/// whose writing ends are all closed. At most a page is read per call. Reading
+/// from an empty pipe blocks until data arrives.
+pub fn sys_read(fd: u64, buf: usize, len: usize, tf: &mut TrapFrame) {
+    let descriptor = match get_descriptor(fd, tf) {
+        Ok(descriptor) => descriptor,
+        Err(error) => {
+            tf.x7 = error as u64;
+            return;
+        },
+    };
+
+    if descriptor.lock().read_would_block() {
+        // the scheduler does the read once there is something to read
+        let readable = Box::new(move |process: &mut Process| {
+            if descriptor.lock().read_would_block() {
+                return false;
+            }
+            let result = read_descriptor(&descriptor, len).and_then(|data| {
+                process.copy_to_user(buf, &data)?;
+                Ok(data.len() as u64)
+            });
+            set_result(result, &mut process.context);
+            true
+        });
+        SCHEDULER.switch(State::Waiting(readable), tf);
+        return;
+    }
+
+    let result = read_descriptor(&descriptor, len).and_then(|data| {
+        SCHEDULER.with_process(tf.tpidr, |process| {
+            process.copy_to_user(buf, &data)
+        })?;
+        Ok(data.len() as u64)
+    });
+    set_result(result, tf);
+}
+
+/// Writes to a file descriptor.
+///
+/// This system call takes three parameters: the file descriptor, and the
+/// address and the length of the user buffer to write.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the number of bytes written. At most a page is written per call.
+/// Writing to a full pipe blocks until a reader makes room, and writing to a
+/// pipe whose reading ends are all closed returns `BrokenPipe`.
+pub fn sys_write_fd(fd: u64, buf: usize, len: usize, tf: &mut TrapFrame) {
+    let data = get_descriptor(fd, tf).and_then(|descriptor| {
+        let mut data = Vec::new();
+        data.resize(core::cmp::min(len, PAGE_SIZE), 0u8);
+        SCHEDULER.with_process(tf.tpidr, |process| {
+            process.copy_from_user(buf, &mut data)
+        })?;
+        Ok((descriptor, data))
+    });
+    let (descriptor, data) = match data {
+        Ok(data) => data,
+        Err(error) => {
+            tf.x7 = error as u64;
+            return;
+        },
+    };
+
+    if descriptor.lock().write_would_block() {
+        // the scheduler does the write once there is room for it
+        let writable = Box::new(move |process: &mut Process| {
+            if descriptor.lock().write_would_block() {
+                return false;
+            }
+            let result = descriptor.lock().write(&data);
+            set_result(result.map(|count| count as u64), &mut process.context);
+            true
+        });
+        SCHEDULER.switch(State::Waiting(writable), tf);
+        return;
+    }
+
+    let result = descriptor.lock().write(&data);
+    set_result(result.map(|count| count as u64), tf);
+}
+
+/// Creates a pipe in the current process.
+///
+/// This system call does not take parameter.
+///
+/// In addition to the usual status value, this system call returns two
+/// parameters: the file descriptors of the reading end and of the writing end.
+pub fn sys_pipe(tf: &mut TrapFrame) {
+    let (reader, writer) = pipe();
+    let result = SCHEDULER.with_process(tf.tpidr, |process| {
+        let read_fd = process.files.lock().insert(Descriptor::PipeRead(reader))?;
+        match process.files.lock().insert(Descriptor::PipeWrite(writer)) {
+            Ok(write_fd) => Ok((read_fd, write_fd)),
+            Err(error) => {
+                let _ = process.files.lock().close(read_fd);
+                Err(error)
+            },
+        }
+    });
+    match result {
+        Ok((read_fd, write_fd)) => {
+            tf.x0 = read_fd as u64;
+            tf.x1 = write_fd as u64;
+            tf.x7 = 1;
+        },
+        Err(error) => tf.x7 = error as u64,
+    }
+}
+
+/// Duplicates a file descriptor onto another one.
+///
+/// This system call takes two parameters: the file descriptor to duplicate and
+/// the descriptor number to make refer to the same object, which is closed
+/// first if it is open.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the new file descriptor.
+pub fn sys_dup2(old_fd: u64, new_fd: u64, tf: &mut TrapFrame) {
+    let result = SCHEDULER.with_process(tf.tpidr, |process| {
+        process.files.lock().dup2(old_fd as usize, new_fd as usize)
+    });
+    set_result(result.map(|fd| fd as u64), tf);
+}
+
+/// Closes a file descriptor.
+///
+/// This system call takes one parameter: the file descriptor.
+///
+/// It only returns the usual status value.
+pub fn sys_close(fd: u64, tf: &mut TrapFrame) {
+    let result = SCHEDULER.with_process(tf.tpidr, |process| {
+        process.files.lock().close(fd as usize)
+    });
+    set_result(result.map(|_| 0), tf);
+}
+
+/// Moves the offset of a file descriptor.
+///
+/// This system call takes three parameters: the file descriptor, the offset and
+/// where it is relative to (`SEEK_SET`, `SEEK_CUR` or `SEEK_END`).
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the new offset from the start of the file.
+pub fn sys_lseek(fd: u64, offset: i64, whence: u64, tf: &mut TrapFrame) {
+    let pos = match whence {
+        SEEK_SET if offset >= 0 => Ok(SeekFrom::Start(offset as u64)),
+        SEEK_CUR => Ok(SeekFrom::Current(offset)),
+        SEEK_END => Ok(SeekFrom::End(offset)),
+        _ => Err(OsError::InvalidArgument),
+    };
+    let result = pos.and_then(|pos| {
+        let descriptor = get_descriptor(fd, tf)?;
+        let offset = descriptor.lock().seek(pos)?;
+        Ok(offset)
+    });
+    set_result(result, tf);
+}
+
+/// Returns the status of a file descriptor.
+///
+/// This system call takes two parameters: the file descriptor and the address
+/// of the user `Stat` to fill in.
+///
+/// It only returns the usual status value.
+pub fn sys_fstat(fd: u64, stat: usize, tf: &mut TrapFrame) {
+    let result = get_descriptor(fd, tf).and_then(|descriptor| {
+        let status = descriptor.lock().stat();
+        let bytes = unsafe {
+            core::slice::from_raw_parts(&status as *const Stat as *const u8, core::mem::size_of::<Stat>())
+        };
+        SCHEDULER.with_process(tf.tpidr, |process| {
+            process.copy_to_user(stat, bytes)
+        })
+    });
+    set_result(result.map(|_| 0), tf);
+}
+
+/// Moves the program break of the current process.
+///
+/// This system call takes one parameter: the new program break, or 0 to only
+/// query it.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the program break. It returns `NoMemory` if the heap can't grow
+/// that far.
+pub fn sys_brk(addr: usize, tf: &mut TrapFrame) {
+    let result = SCHEDULER.with_process(tf.tpidr, |process| {
+        if addr != 0 {
+            process.space.lock().set_brk(addr)?;
+        }
+        Ok(process.space.lock().brk as u64)
+    });
+    set_result(result, tf);
+}
+
+/// Maps anonymous zero-filled memory into the current process.
+///
+/// This system call takes three parameters: the preferred page-aligned address
+/// (0 for any), the length in bytes and the `PROT_*` protection bits, which must
+/// include `PROT_READ`.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the address of the mapping.
+pub fn sys_mmap(addr: usize, len: usize, prot: u64, tf: &mut TrapFrame) {
+    let result = prot_perm(prot).and_then(|perm| {
+        SCHEDULER.with_process(tf.tpidr, |process| {
+            process.space.lock().mmap(addr, len, perm)
+        })
+    });
+    set_result(result.map(|addr| addr as u64), tf);
+}
+
+/// Maps a file open in the current process into its memory.
+///
+/// This system call takes six parameters: the preferred page-aligned address (0
+/// for any), the length in bytes, the `PROT_*` protection bits, which must
+/// include `PROT_READ`, the `MAP_SHARED` or `MAP_PRIVATE` flag, the file
+/// descriptor and the page-aligned offset in the file.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the address of the mapping. It returns `InvalidArgument` if the
+/// descriptor is not a file.
+pub fn sys_mmap_file(tf: &mut TrapFrame) {
+    let (addr, len, prot, flags, fd, offset) = (tf.x0 as usize, tf.x1 as usize, tf.x2, tf.x3, tf.x4, tf.x5 as usize);
+    let shared = match flags {
+        MAP_SHARED => Ok(true),
+        MAP_PRIVATE => Ok(false),
+        _ => Err(OsError::InvalidArgument),
+    };
+    let file = get_descriptor(fd, tf).and_then(|descriptor| {
+        let descriptor = descriptor.lock();
+        match *descriptor {
+            Descriptor::File(ref file) => Ok(file.clone()),
+            _ => Err(OsError::InvalidArgument),
+        }
+    });
+    let result = prot_perm(prot).and_then(|perm| {
+        let shared = shared?;
+        let file = file?;
+        SCHEDULER.with_process(tf.tpidr, |process| {
+            process.space.lock().mmap_file(addr, len, perm, file, offset, shared)
+        })
+    });
+    set_result(result.map(|addr| addr as u64), tf);
+}
+
+/// Writes the shared file mappings of the current process back to their files.
+///
+/// This system call takes two parameters: the page-aligned address and the
+/// length in bytes of the range to write back.
+///
+/// It only returns the usual status value, an I/O error if a file can't be
+/// written to the disk.
+pub fn sys_msync(addr: usize, len: usize, tf: &mut TrapFrame) {
+    // the files are written with no queue locked
+    let result = SCHEDULER.with_process(tf.tpidr, |process| Ok(process.space.clone()))
+        .and_then(|space| space.lock().msync(addr, len));
+    set_result(result.map(|_| 0), tf);
+}
+
+/// Returns the page permission matching the `PROT_*` bits `prot`, or
+/// `InvalidArgument` if they don't include `PROT_READ`.
+fn prot_perm(prot: u64) -> OsResult<PagePerm> {
+    match (prot & PROT_READ != 0, prot & PROT_WRITE != 0, prot & PROT_EXEC != 0) {
+        (true, true, true) => Ok(PagePerm::RWX),
+        (true, true, false) => Ok(PagePerm::RW),
+        (true, false, true) => Ok(PagePerm::RX),
+        (true, false, false) => Ok(PagePerm::RO),
+        _ => Err(OsError::InvalidArgument),
+    }
+}
+
+/// Unmaps memory mapped by `mmap` from the current process. Shared file
+/// mappings are written back to their files first.
+///
+/// This system call takes two parameters: the page-aligned address and the
+/// length in bytes of the range to unmap.
+///
+/// It only returns the usual status value.
+pub fn sys_munmap(addr: usize, len: usize, tf: &mut TrapFrame) {
+    // the files are written with no queue locked
+    let result = SCHEDULER.with_process(tf.tpidr, |process| Ok(process.space.clone()))
+        .and_then(|space| space.lock().munmap(addr, len));
+    set_result(result.map(|_| 0), tf);
+}
+
+/// Sends a signal to a process.
+///
+/// This system call takes two parameters: the ID of the process and the signal
+/// number, or 0 to only check that the process exists.
+///
+/// It only returns the usual status value. It returns `NoEntry` if there is no
+/// such process, and `NoAccess` if it is a kernel thread.
+pub fn sys_kill(pid: u64, sig: u64, tf: &mut TrapFrame) {
+    if sig != 0 && !Signals::is_valid(sig) {
+        tf.x7 = OsError::InvalidArgument as u64;
+        return;
+    }
+    let result = SCHEDULER.with_process(pid, |process| match process.state {
+        _ if process.is_kernel_thread() => Err(OsError::NoAccess),
+        State::Zombie(_) | State::Dead => Ok(false),
+        _ => Ok(true),
+    });
+    if let Ok(true) = result {
+        if sig != 0 {
+            SCHEDULER.signal(pid, sig);
+        }
+    }
+    set_result(result.map(|_| 0), tf);
+}
+
+/// Sets the action of a signal for the current process.
+///
+/// This system call takes three parameters: the signal number, the handler,
+/// which is `SIG_DFL`, `SIG_IGN` or the address of a user function taking the
+/// signal number, and the address handlers return to, which issues `sigreturn`.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the previous handler. It returns `InvalidArgument` for `SIGKILL`
+/// and `SIGSTOP`.
+pub fn sys_sigaction(sig: u64, handler: u64, restorer: u64, tf: &mut TrapFrame) {
+    let action = match handler {
+        SIG_DFL => Ok(Action::Default),
+        SIG_IGN => Ok(Action::Ignore),
+        _ if handler >= USER_IMG_BASE as u64 && restorer >= USER_IMG_BASE as u64 => {
+            Ok(Action::Handler { handler: handler, restorer: restorer })
+        },
+        _ => Err(OsError::InvalidArgument),
+    };
+    let result = action.and_then(|action| {
+        SCHEDULER.with_process(tf.tpidr, |process| {
+            process.signals.set_action(sig, action)
+        })
+    });
+    set_result(result.map(|previous| previous.handler()), tf);
+}
+
+/// Returns from a signal handler.
+///
+/// This system call does not take parameter; the signal frame is at the stack
+/// pointer.
+///
+/// It does not return: the code the signal interrupted resumes. If the signal
+/// frame can't be read, the process is terminated as by `SIGSEGV`.
+pub fn sys_sigreturn(tf: &mut TrapFrame) {
+    let result = SCHEDULER.with_process(tf.tpidr, |process| {
+        process.sigreturn(tf)
+    });
+    if result.is_err() {
+        let _ = SCHEDULER.exit(128 + SIGSEGV as i32, tf);
+        SCHEDULER.switch_to(tf);
+    }
+}
+
+/// Creates a thread in the current process.
+///
+/// This system call takes three parameters: the address of the function the
+/// thread runs, the argument passed to it, and the address the function returns
+/// to, which issues `thread_exit`.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the ID of the new thread. It returns `NoMemory` if its stack can't
+/// be mapped.
+pub fn sys_thread_spawn(entry: u64, arg: u64, exit: u64, tf: &mut TrapFrame) {
+    if entry < USER_IMG_BASE as u64 || exit < USER_IMG_BASE as u64 {
+        tf.x7 = OsError::InvalidArgument as u64;
+        return;
+    }
+    let thread = SCHEDULER.with_process(tf.tpidr, |process| {
+        process.spawn_thread(tf, entry, arg, exit)
+    });
+    let result = thread.and_then(|thread| SCHEDULER.add(thread).ok_or(OsError::NoMemory));
+    set_result(result, tf);
+}
+
+/// Terminates the current thread.
+///
+/// This system call takes one parameter: the exit status of the thread, which
+/// the thread joining it collects. It does not return. Called by the main thread,
+/// it terminates the process as `exit` does.
+pub fn sys_thread_exit(code: i32, tf: &mut TrapFrame) {
+    let _ = SCHEDULER.exit_thread(code, tf);
+    SCHEDULER.switch_to(tf);
+}
+
+/// Waits for a thread of the current process to exit.
+///
+/// This system call takes one parameter: the ID of the thread.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the exit status of the thread. It returns `NoEntry` if the current
+/// process has no such thread, or if it is the calling thread.
+pub fn sys_thread_join(tid: u64, tf: &mut TrapFrame) {
+    let result = SCHEDULER.with_process(tf.tpidr, |process| Ok(process.pid())).and_then(|pid| {
+        let leader = SCHEDULER.with_process(tid, |thread| Ok(thread.leader))?;
+        if tid == tf.tpidr || leader != Some(pid) {
+            return Err(OsError::NoEntry);
+        }
+        SCHEDULER.with_process(tf.tpidr, |process| {
+            process.join_target = Some(tid);
+            Ok(())
+        })
+    });
+
+    match result {
+        // the scheduler hands over the status of the thread once it has exited
+        Ok(()) => {
+            let collected = Box::new(|process: &mut Process| process.join_target.is_none());
+            SCHEDULER.switch(State::Waiting(collected), tf);
+        },
+        Err(error) => tf.x7 = error as u64,
+    }
+}
+
+/// Blocks the current thread until another thread wakes the futex at `addr`.
+///
+/// This system call takes three parameters: the address of a 32-bit word, the
+/// value the caller expects it to hold, and a timeout in milliseconds, or 0 to
+/// wait without one. The thread only blocks if the word still holds the
+/// expected value.
+///
+/// It only returns the usual status value. It returns `WouldBlock` if the word
+/// holds another value, and `IoErrorTimedOut` if the timeout expired first.
+pub fn sys_futex_wait(addr: usize, expected: u32, timeout: u64, tf: &mut TrapFrame) {
+    let deadline = match timeout {
+        0 => None,
+        ms => Some(current_time() + Duration::from_millis(ms)),
+    };
+    // `futex_wake` sets the return values of the threads it wakes
+    if let Err(error) = SCHEDULER.futex_wait(addr, expected, deadline, tf) {
+        tf.x7 = error as u64;
+    }
+}
+
+/// Wakes threads blocked in `futex_wait` on a futex.
+///
+/// This system call takes two parameters: the address of the futex and the
+/// maximum number of threads to wake.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the number of threads woken.
+pub fn sys_futex_wake(addr: usize, count: usize, tf: &mut TrapFrame) {
+    let result = SCHEDULER.futex_wake(tf.tpidr, addr, count);
+    set_result(result.map(|woken| woken as u64), tf);
+}
+
+/// Sets the nice value of all the threads of a process.
+///
+/// This system call takes two parameters: the ID of the process, or 0 for the
+/// current one, and the nice value, which is clamped to `PRIO_MIN..=PRIO_MAX`.
+/// A lower value is a higher priority.
+///
+/// It only returns the usual status value. It returns `NoEntry` if there is no
+/// such process, and `NoAccess` if it is a kernel thread or if it would raise the
+/// priority of the process, the current one included.
+pub fn sys_setpriority(pid: u64, nice: i64, tf: &mut TrapFrame) {
+    let nice = core::cmp::min(core::cmp::max(nice, PRIO_MIN), PRIO_MAX);
+    let id = if pid == 0 { tf.tpidr } else { pid };
+    let result = check_user_thread(id).and_then(|_| SCHEDULER.set_nice(id, nice as i32));
+    set_result(result.map(|_| 0), tf);
+}
+
+/// Returns `NoAccess` if the thread `id` is a kernel thread, which user processes
+/// may not reschedule, and `NoEntry` if there is no such thread.
+fn check_user_thread(id: u64) -> OsResult<()> {
+    SCHEDULER.with_process(id, |process| {
+        if process.is_kernel_thread() {
+            return Err(OsError::NoAccess);
+        }
+        Ok(())
+    })
+}
+
+/// Returns the nice value of a process.
+///
+/// This system call takes one parameter: the ID of the process, or 0 for the
+/// current one.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the nice value. It returns `NoEntry` if there is no such process.
+pub fn sys_getpriority(pid: u64, tf: &mut TrapFrame) {
+    let id = if pid == 0 { tf.tpidr } else { pid };
+    let result = SCHEDULER.with_process(id, |process| Ok(process.nice as i64 as u64));
+    set_result(result, tf);
+}
+
+/// Sets the CPU affinity of a process: the cores it may run on.
+///
+/// This system call takes two parameters: the ID of the process, or 0 for the
+/// current one, and the affinity mask, bit `n` standing for core `n`. Bits of
+/// cores that don't exist are ignored.
+///
+/// It only returns the usual status value. It returns `NoEntry` if there is no
+/// such process, `NoAccess` if it is a kernel thread, and `InvalidArgument` if
+/// the mask has none of the cores.
+pub fn sys_sched_setaffinity(pid: u64, mask: u64, tf: &mut TrapFrame) {
+    let id = if pid == 0 { tf.tpidr } else { pid };
+    let result = check_user_thread(id).and_then(|_| SCHEDULER.set_affinity(id, mask));
+    set_result(result.map(|_| 0), tf);
+}
+
+/// Returns the CPU affinity of a process.
+///
+/// This system call takes one parameter: the ID of the process, or 0 for the
+/// current one.
+///
+/// In addition to the usual status value, this system call returns one
+/// parameter: the affinity mask. It returns `NoEntry` if there is no such
+/// process.
+pub fn sys_sched_getaffinity(pid: u64, tf: &mut TrapFrame) {
+    let id = if pid == 0 { tf.tpidr } else { pid };
+    let result = SCHEDULER.with_process(id, |process| Ok(process.affinity));
+    set_result(result, tf);
+}
+
+/// Returns the resource usage of a process.
+///
+/// This system call takes two parameters: the ID of the process, or 0 for the
+/// current one, and the address of the user `Rusage` to fill in.
+///
+/// It only returns the usual status value. It returns `NoEntry` if there is no
+/// such process.
+pub fn sys_getrusage(pid: u64, usage: usize, tf: &mut TrapFrame) {
+    let id = if pid == 0 { tf.tpidr } else { pid };
+    let result = SCHEDULER.usage(id).and_then(|rusage| {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(&rusage as *const Rusage as *const u8, core::mem::size_of::<Rusage>())
+        };
+        SCHEDULER.with_process(tf.tpidr, |process| process.copy_to_user(usage, bytes))
+    });
+    set_result(result.map(|_| 0), tf);
+}
+
 pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
     if num == NR_SLEEP as u16 { // sleep 
         let ms = tf.x0 as u32; 
@@ -90,8 +796,68 @@ pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
     } else if num == NR_GETPID as u16 { // get id
         sys_getpid(tf);
     } else if num == NR_EXIT as u16 { // exit
-        sys_exit(tf);
+        let code = tf.x0 as i32;
+        sys_exit(code, tf);
     } else if num == NR_TIME as u16 {// time
         sys_time(tf);
+    } else if num == NR_FORK as u16 { // fork
+        sys_fork(tf);
+    } else if num == NR_EXEC as u16 { // exec
+        sys_exec(tf);
+    } else if num == NR_WAIT as u16 { // wait
+        let pid = tf.x0;
+        sys_wait(pid, tf);
+    } else if num == NR_OPEN as u16 { // open
+        sys_open(tf.x0 as usize, tf.x1 as usize, tf);
+    } else if num == NR_READ as u16 { // read
+        sys_read(tf.x0, tf.x1 as usize, tf.x2 as usize, tf);
+    } else if num == NR_WRITE_FD as u16 { // write to a file descriptor
+        sys_write_fd(tf.x0, tf.x1 as usize, tf.x2 as usize, tf);
+    } else if num == NR_CLOSE as u16 { // close
+        sys_close(tf.x0, tf);
+    } else if num == NR_LSEEK as u16 { // lseek
+        sys_lseek(tf.x0, tf.x1 as i64, tf.x2, tf);
+    } else if num == NR_FSTAT as u16 { // fstat
+        sys_fstat(tf.x0, tf.x1 as usize, tf);
+    } else if num == NR_BRK as u16 { // brk
+        sys_brk(tf.x0 as usize, tf);
+    } else if num == NR_MMAP as u16 { // mmap
+        sys_mmap(tf.x0 as usize, tf.x1 as usize, tf.x2, tf);
+    } else if num == NR_MUNMAP as u16 { // munmap
+        sys_munmap(tf.x0 as usize, tf.x1 as usize, tf);
+    } else if num == NR_MMAP_FILE as u16 { // mmap a file
+        sys_mmap_file(tf);
+    } else if num == NR_MSYNC as u16 { // msync
+        sys_msync(tf.x0 as usize, tf.x1 as usize, tf);
+    } else if num == NR_PIPE as u16 { // pipe
+        sys_pipe(tf);
+    } else if num == NR_DUP2 as u16 { // dup2
+        sys_dup2(tf.x0, tf.x1, tf);
+    } else if num == NR_KILL as u16 { // kill
+        sys_kill(tf.x0, tf.x1, tf);
+    } else if num == NR_SIGACTION as u16 { // sigaction
+        sys_sigaction(tf.x0, tf.x1, tf.x2, tf);
+    } else if num == NR_SIGRETURN as u16 { // sigreturn
+        sys_sigreturn(tf);
+    } else if num == NR_THREAD_SPAWN as u16 { // thread_spawn
+        sys_thread_spawn(tf.x0, tf.x1, tf.x2, tf);
+    } else if num == NR_THREAD_EXIT as u16 { // thread_exit
+        sys_thread_exit(tf.x0 as i32, tf);
+    } else if num == NR_THREAD_JOIN as u16 { // thread_join
+        sys_thread_join(tf.x0, tf);
+    } else if num == NR_FUTEX_WAIT as u16 { // futex_wait
+        sys_futex_wait(tf.x0 as usize, tf.x1 as u32, tf.x2, tf);
+    } else if num == NR_FUTEX_WAKE as u16 { // futex_wake
+        sys_futex_wake(tf.x0 as usize, tf.x1 as usize, tf);
+    } else if num == NR_SETPRIORITY as u16 { // setpriority
+        sys_setpriority(tf.x0, tf.x1 as i64, tf);
+    } else if num == NR_GETPRIORITY as u16 { // getpriority
+        sys_getpriority(tf.x0, tf);
+    } else if num == NR_SCHED_SETAFFINITY as u16 { // sched_setaffinity
+        sys_sched_setaffinity(tf.x0, tf.x1, tf);
+    } else if num == NR_SCHED_GETAFFINITY as u16 { // sched_getaffinity
+        sys_sched_getaffinity(tf.x0, tf);
+    } else if num == NR_GETRUSAGE as u16 { // getrusage
+        sys_getrusage(tf.x0, tf.x1 as usize, tf);
     }
 }
@@ -1,16 +1,29 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 use crate::mutex::Mutex;
 
 use aarch64::*;
 
 mod address;
 mod pagetable;
+mod region;
+mod uaccess;
 
 pub use self::address::{PhysicalAddr, VirtualAddr};
 pub use self::pagetable::*;
-use crate::param::{KERNEL_MASK_BITS, USER_MASK_BITS};
+pub use self::region::{Backing, Region};
+pub use self::uaccess::{copy_from_user, copy_to_user, string_from_user, user_slices, user_slices_mut};
+use crate::param::{KERNEL_MASK_BITS, NCORES, USER_MASK_BITS};
 
 /// Thread-safe (locking) wrapper around a kernel page table.
-pub struct VMManager(Mutex<Option<KernPageTable>>);
+pub struct VMManager {
+    kern_pt: Mutex<Option<KernPageTable>>,
+    /// The base address of the kernel page table, which the cores read without the
+    /// lock while their MMU is still off.
+    kern_pt_addr: AtomicUsize,
+    /// The number of cores that enabled their MMU in `wait()`.
+    ready_core_cnt: AtomicUsize,
+}
 
 impl VMManager {
     /// Returns an uninitialized `VMManager`.
@@ -18,15 +31,21 @@ impl VMManager {
     /// The virtual memory manager must be initialized by calling `initialize()` and `setup()`
     /// before the first memory allocation. Failure to do will result in panics.
     pub const fn uninitialized() -> Self {
-        VMManager(Mutex::new(None))
+        VMManager {
+            kern_pt: Mutex::new(None),
+            kern_pt_addr: AtomicUsize::new(0),
+            ready_core_cnt: AtomicUsize::new(0),
+        }
     }
 
-    /// Initializes the virtual memory manager.
+    /// Initializes the virtual memory manager by creating the kernel page table.
+    /// Each core then enables its MMU with `wait()`.
     /// The caller should assure that the method is invoked only once during the kernel
     /// initialization.
     pub fn initialize(&self) {
-        *self.0.lock() = Some(KernPageTable::new());
-        self.setup();
+        let kern_pt = KernPageTable::new();
+        self.kern_pt_addr.store(kern_pt.get_baddr().as_usize(), Ordering::Relaxed);
+        *self.kern_pt.lock() = Some(kern_pt);
     }
 
     /// Set up the virtual memory manager.
@@ -37,8 +56,7 @@ impl VMManager {
     ///
     /// Panics if the current system does not support 64KB memory translation granule size.
     pub fn setup(&self) {
-        let kern_page_table = self.0.lock();
-        let baddr = kern_page_table.as_ref().unwrap().get_baddr().as_u64();
+        let baddr = self.kern_pt_addr.load(Ordering::Relaxed) as u64;
 
         unsafe {
             assert!(ID_AA64MMFR0_EL1.get_value(ID_AA64MMFR0_EL1::TGran64) == 0);
@@ -82,10 +100,18 @@ impl VMManager {
         }
     }
 
+    /// Sets up the MMU of the current core with `setup()`, then waits until every
+    /// core has done so. Each core calls this function once.
+    pub fn wait(&self) {
+        self.setup();
+        self.ready_core_cnt.fetch_add(1, Ordering::AcqRel);
+        while self.ready_core_cnt.load(Ordering::Acquire) < NCORES {
+            core::sync::atomic::spin_loop_hint();
+        }
+    }
+
     /// Returns the base address of the kernel page table as `PhysicalAddr`.
     pub fn get_baddr(&self) -> PhysicalAddr {
-        let kern_page_table = self.0.lock();
-        let baddr = kern_page_table.as_ref().unwrap().get_baddr();
-        baddr
+        PhysicalAddr::from(self.kern_pt_addr.load(Ordering::Relaxed))
     }
 }
\ No newline at end of file
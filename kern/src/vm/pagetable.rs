@@ -1,15 +1,19 @@
 use core::ops::{Deref, DerefMut};
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::fmt;
 use core::alloc::{GlobalAlloc, Layout};
+use kernel_api::{OsError, OsResult};
 
 use crate::allocator;
+use crate::mutex::Mutex;
 use crate::param::*;
 use crate::vm::{PhysicalAddr, VirtualAddr};
 use crate::ALLOCATOR;
-use crate::console::kprintln;
 use crate::console::kprint;
+use crate::log::error;
 use aarch64::vmsa::*;
+use aarch64::{tlb_invalidate_all, tlb_invalidate_va};
 use shim::const_assert_size;
 
 #[repr(C)]
@@ -33,14 +37,6 @@ pub struct L2PageTable {
 const_assert_size!(L2PageTable, PAGE_SIZE);
 
 impl L2PageTable {
-    /// Returns a new `L2PageTable`
-    fn new() -> L2PageTable {
-        let entries = [RawL2Entry::new(0); 8192];
-        L2PageTable {
-            entries : entries
-        }
-    }
-
     /// Returns a `PhysicalAddr` of the pagetable.
     pub fn as_ptr(&self) -> PhysicalAddr {
         PhysicalAddr::from(&(self.entries) as *const _ as u64)
@@ -51,11 +47,6 @@ impl L2PageTable {
 pub struct L3Entry(RawL3Entry);
 
 impl L3Entry {
-    /// Returns a new `L3Entry`.
-    fn new() -> L3Entry {
-        L3Entry(RawL3Entry::new(0))
-    }
-
     /// Returns `true` if the L3Entry is valid and `false` otherwise.
     fn is_valid(&self) -> bool {
         self.0.get_value(RawL3Entry::VALID) == EntryValid::Valid
@@ -79,35 +70,49 @@ pub struct L3PageTable {
 const_assert_size!(L3PageTable, PAGE_SIZE);
 
 impl L3PageTable {
-    /// Returns a new `L3PageTable`.
-    fn new() -> L3PageTable {
-        let entries = [L3Entry::new(); 8192];
-        L3PageTable {entries: entries}
-    }
-
     /// Returns a `PhysicalAddr` of the pagetable.
     pub fn as_ptr(&self) -> PhysicalAddr {
         PhysicalAddr::from(&(self.entries[0]) as *const _ as u64)
     }
 }
 
+/// The L3 tables of a `PageTable`: an array of as many as the L2 entries it uses.
+///
+/// `PageTable::new()` builds the tables zeroed on the heap, so implementors must be
+/// plain tables, valid with all of their entries zero, that is invalid.
+pub unsafe trait L3Tables: AsRef<[L3PageTable]> + AsMut<[L3PageTable]> {}
+
+/// The L3 tables of the kernel. The third one only exists so that the kernel can
+/// map the local peripherals right above 1GB.
+pub type KernL3Tables = [L3PageTable; 3];
+unsafe impl L3Tables for KernL3Tables {}
+
+/// The L3 tables of a process, which translate its 1GB of virtual memory.
+pub type UserL3Tables = [L3PageTable; 2];
+unsafe impl L3Tables for UserL3Tables {}
+
 #[repr(C)]
 #[repr(align(65536))]
-pub struct PageTable {
+pub struct PageTable<T: L3Tables> {
     pub l2: L2PageTable,
-    pub l3: [L3PageTable; 2],
+    pub l3: T,
 }
 
-impl PageTable {
-    /// Returns a new `Box` containing `PageTable`.
-    /// Entries in L2PageTable should be initialized properly before return.
-    fn new(perm: u64) -> Box<PageTable> {
-        let l2 : L2PageTable = L2PageTable::new();
-        let l3 = [L3PageTable::new(), L3PageTable::new()];
-        let mut pt = Box::new(PageTable {
-            l2: l2,
-            l3: l3,
-        });
+impl<T: L3Tables> PageTable<T> {
+    /// Returns a new `Box` containing `PageTable` with empty L3 tables, whose L2
+    /// entries point to them with the permission `perm`.
+    ///
+    /// The table is zeroed in place on the heap: built on the stack, it would take
+    /// more than a kernel stack.
+    fn new(perm: u64) -> Box<PageTable<T>> {
+        let layout = Layout::new::<PageTable<T>>();
+        let mut pt = unsafe {
+            let raw = ALLOCATOR.alloc_zeroed(layout) as *mut PageTable<T>;
+            if raw.is_null() {
+                alloc::alloc::handle_alloc_error(layout);
+            }
+            Box::from_raw(raw)
+        };
         let mut l2_entry : RawL2Entry = RawL2Entry::new(0);
         l2_entry.set_value(1, RawL2Entry::AF);
         l2_entry.set_value(EntrySh::ISh, RawL2Entry::SH);
@@ -115,24 +120,24 @@ impl PageTable {
         l2_entry.set_value(EntryType::Table, RawL2Entry::TYPE);
         l2_entry.set_value(EntryValid::Valid, RawL2Entry::VALID);
         l2_entry.set_value(EntryAttr::Mem, RawL2Entry::ATTR);
-        pt.l2.entries[0].set(l2_entry.get());
-        pt.l2.entries[1].set(l2_entry.get());
 
-        pt.l2.entries[0].set_masked(pt.l3[0].as_ptr().as_u64(), RawL2Entry::ADDR);
-        pt.l2.entries[1].set_masked(pt.l3[1].as_ptr().as_u64(), RawL2Entry::ADDR);
+        for index in 0..pt.l3.as_ref().len() {
+            let l3_addr = pt.l3.as_ref()[index].as_ptr().as_u64();
+            pt.l2.entries[index].set(l2_entry.get());
+            pt.l2.entries[index].set_masked(l3_addr, RawL2Entry::ADDR);
+        }
 
         pt
     }
 
     /// Returns the (L2index, L3index) extracted from the given virtual address.
-    /// Since we are only supporting 1GB virtual memory in this system, L2index
-    /// should be smaller than 2.
+    /// L2index should be smaller than the number of L3PageTable.
     ///
     /// # Panics
     ///
     /// Panics if the virtual address is not properly aligned to page size.
     /// Panics if extracted L2index exceeds the number of L3PageTable.
-    fn locate(va: VirtualAddr) -> (usize, usize) {
+    fn locate(&self, va: VirtualAddr) -> (usize, usize) {
         if va.as_usize() % PAGE_SIZE != 0 {
             panic!("Virtual Address is not properly aligned to page size");
         }
@@ -142,9 +147,10 @@ impl PageTable {
         let l2_index = virtual_adress.get_value(VirtAddr::L2INDEX); // shift right 29 bits, bitwise or to get the last 13 bits
         // Bits [28-16] = L3index
         let l3_index = virtual_adress.get_value(VirtAddr::L3INDEX); // shift right 16 bits, bitwise or to get the last 13 bits
-        if l2_index >= 2 {
-            kprintln!("l2_index: {}. va: {:?}", l2_index, va);
-            panic!("L2 index is >= 2. Invalid L2 index");
+        let count = self.l3.as_ref().len();
+        if l2_index as usize >= count {
+            error!("l2_index: {}. va: {:?}", l2_index, va);
+            panic!("L2 index is >= {}. Invalid L2 index", count);
         }
 
         (l2_index as usize, l3_index as usize)
@@ -153,7 +159,7 @@ impl PageTable {
     /// Returns `true` if the L3entry indicated by the given virtual address is valid.
     /// Otherwise, `false` is returned.
     pub fn is_valid(&self, va: VirtualAddr) -> bool {
-        let locate_result = PageTable::locate(va);
+        let locate_result = self.locate(va);
 
         let l2_index = locate_result.0;
         let l3_index = locate_result.1;
@@ -163,7 +169,7 @@ impl PageTable {
             return false;
         }
 
-        let l3_entry = self.l3[l2_index].entries[l3_index];
+        let l3_entry = self.l3.as_ref()[l2_index].entries[l3_index];
         if l3_entry.0.get_value(RawL3Entry::VALID) == EntryValid::Invalid { // l3 entry not valid 
             return false;
         }
@@ -177,15 +183,25 @@ impl PageTable {
         !self.is_valid(va)
     }
 
+    /// Returns the RawL3Entry indicated by the given virtual address.
+    pub fn get_entry(&self, va: VirtualAddr) -> RawL3Entry {
+        let locate_result = self.locate(va);
+
+        let l2_index = locate_result.0;
+        let l3_index = locate_result.1;
+
+        self.l3.as_ref()[l2_index].entries[l3_index].0
+    }
+
     /// Set the given RawL3Entry `entry` to the L3Entry indicated by the given virtual
     /// address.
     pub fn set_entry(&mut self, va: VirtualAddr, entry: RawL3Entry) -> &mut Self {
-        let locate_result = PageTable::locate(va);
+        let locate_result = self.locate(va);
 
         let l2_index = locate_result.0;
         let l3_index = locate_result.1;
 
-        self.l3[l2_index].entries[l3_index].0 = entry;
+        self.l3.as_mut()[l2_index].entries[l3_index].0 = entry;
         self
     }
 
@@ -196,20 +212,34 @@ impl PageTable {
     }
 }
 
-// FIXME: Implement `IntoIterator` for `&PageTable`.
-impl<'a> IntoIterator for &'a mut PageTable {
+/// Iterates over the entries of the L3 tables of a `PageTable`, in order.
+pub type L3Entries<'a> = core::iter::FlatMap<
+    core::slice::Iter<'a, L3PageTable>,
+    core::slice::Iter<'a, L3Entry>,
+    fn(&'a L3PageTable) -> core::slice::Iter<'a, L3Entry>,
+>;
+
+impl<'a, T: L3Tables> IntoIterator for &'a PageTable<T> {
+    type Item = &'a L3Entry;
+    type IntoIter = L3Entries<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let entries: fn(&'a L3PageTable) -> core::slice::Iter<'a, L3Entry> = |table| table.entries.iter();
+        self.l3.as_ref().iter().flat_map(entries)
+    }
+}
+
+impl<'a, T: L3Tables> IntoIterator for &'a mut PageTable<T> {
     type Item = &'a L3Entry;
-    type IntoIter = core::iter::Chain<core::slice::Iter<'a, L3Entry>, core::slice::Iter<'a, L3Entry>>;
+    type IntoIter = L3Entries<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        let first_iter = self.l3[0].entries.iter();
-        let second_iter = self.l3[1].entries.iter();
-        first_iter.chain(second_iter)
+        (&*self).into_iter()
     }
 }
 
 
-pub struct KernPageTable(Box<PageTable>);
+pub struct KernPageTable(Box<PageTable<KernL3Tables>>);
 
 impl KernPageTable {
     /// Returns a new `KernPageTable`. `KernPageTable` should have a `Pagetable`
@@ -264,69 +294,327 @@ impl KernPageTable {
     }
 }
 
+/// Access permission of a user page.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PagePerm {
     RW,
     RO,
+    RX,
     RWX,
 }
 
-pub struct UserPageTable(Box<PageTable>);
+/// Kind of memory access performed on a user page.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl PagePerm {
+    fn new(writable: bool, executable: bool) -> PagePerm {
+        match (writable, executable) {
+            (false, false) => PagePerm::RO,
+            (true, false) => PagePerm::RW,
+            (false, true) => PagePerm::RX,
+            (true, true) => PagePerm::RWX,
+        }
+    }
+
+    /// Returns `true` if user space may write to a page with this permission.
+    pub fn is_writable(&self) -> bool {
+        *self == PagePerm::RW || *self == PagePerm::RWX
+    }
+
+    /// Returns `true` if user space may execute a page with this permission.
+    pub fn is_executable(&self) -> bool {
+        *self == PagePerm::RX || *self == PagePerm::RWX
+    }
+
+    /// Returns `true` if this permission allows the given access. Every user page is
+    /// readable.
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => true,
+            Access::Write => self.is_writable(),
+            Access::Execute => self.is_executable(),
+        }
+    }
+
+    /// Returns this permission without write access.
+    pub fn read_only(&self) -> PagePerm {
+        PagePerm::new(false, self.is_executable())
+    }
+
+    /// Returns the permission that allows everything `self` or `other` allows.
+    pub fn union(&self, other: PagePerm) -> PagePerm {
+        PagePerm::new(self.is_writable() || other.is_writable(),
+                      self.is_executable() || other.is_executable())
+    }
+
+    /// Sets `AP`, `UXN` and `PXN` of `entry` to match this permission. The kernel
+    /// never executes user pages, so `PXN` is always set.
+    fn set_entry(&self, entry: &mut RawL3Entry) {
+        let ap = match self.is_writable() {
+            true => EntryPerm::USER_RW,
+            false => EntryPerm::USER_RO,
+        };
+        entry.set_value(ap, RawL3Entry::AP);
+        entry.set_value(!self.is_executable() as u64, RawL3Entry::UXN);
+        entry.set_value(1u64, RawL3Entry::PXN);
+    }
+
+    /// Decodes the permission of a user page from its `entry`.
+    fn from_entry(entry: &RawL3Entry) -> PagePerm {
+        PagePerm::new(entry.get_value(RawL3Entry::AP) == EntryPerm::USER_RW,
+                      entry.get_value(RawL3Entry::UXN) == 0)
+    }
+}
+
+/// Number of page tables referencing each user page shared by `UserPageTable::fork()`,
+/// keyed by physical address. Pages missing from the map have a single owner.
+static SHARED_PAGES: Mutex<Option<BTreeMap<usize, usize>>> = Mutex::new(None);
+
+/// Records one more page table referencing the user page at `pa`.
+fn share_page(pa: usize) {
+    let mut shared = SHARED_PAGES.lock();
+    let count = shared.get_or_insert_with(BTreeMap::new).entry(pa).or_insert(1);
+    *count += 1;
+}
+
+/// Drops one reference to the user page at `pa`. Returns `true` if the caller was the
+/// last page table referencing it, `false` if the page is still shared.
+fn release_page(pa: usize) -> bool {
+    let mut shared = SHARED_PAGES.lock();
+    let map = shared.get_or_insert_with(BTreeMap::new);
+    match map.get_mut(&pa) {
+        Some(count) => {
+            *count -= 1;
+            if *count == 1 {
+                map.remove(&pa);
+            }
+            false
+        },
+        None => true,
+    }
+}
+
+pub struct UserPageTable {
+    table: Box<PageTable<UserL3Tables>>,
+    /// The number of pages mapped, counted by `alloc()`, `unmap()` and `fork()`.
+    pages: usize,
+}
 
 impl UserPageTable {
     /// Returns a new `UserPageTable` containing a `PageTable` created with
     /// `USER_RW` permission.
     pub fn new() -> UserPageTable {
         let user_pt = PageTable::new(EntryPerm::USER_RW);
-        UserPageTable(user_pt)
+        UserPageTable { table: user_pt, pages: 0 }
     }
 
     /// Allocates a page and set an L3 entry translates given virtual address to the
-    /// physical address of the allocated page. Returns the allocated page.
+    /// physical address of the allocated page with the access permission `perm`.
+    /// Returns the allocated page, zeroed out, or `NoMemory` if no page is left.
     ///
     /// # Panics
     /// Panics if the virtual address is lower than `USER_IMG_BASE`.
     /// Panics if the virtual address has already been allocated.
-    /// Panics if allocator fails to allocate a page.
-    ///
-    /// TODO. use Result<T> and make it failurable
-    /// TODO. use perm properly
-    pub fn alloc(&mut self, va: VirtualAddr, _perm: PagePerm) -> &mut [u8] {
+    pub fn alloc(&mut self, va: VirtualAddr, perm: PagePerm) -> OsResult<&mut [u8]> {
         if va.as_usize() < USER_IMG_BASE {
-            kprintln!("Invalid VA. Va < USER_IMG_BASE");
+            error!("Invalid VA. Va < USER_IMG_BASE");
             panic!("Invalid VA. Va < USER_IMG_BASE");
         }
 
-        let page = unsafe {ALLOCATOR.alloc(Page::layout())};
-        if page == core::ptr::null_mut() {
-            kprintln!("Allocation fail");
-            panic!("Allocation fail");
-        }
         let real_va = va.as_usize() - USER_IMG_BASE;
         if self.is_valid(VirtualAddr::from(real_va)) { // already allocated
-            kprintln!("VA already allocated");
+            error!("VA already allocated");
             panic!("VA already allocated");
         }
+        let page = unsafe {ALLOCATOR.alloc(Page::layout())};
+        if page == core::ptr::null_mut() {
+            return Err(OsError::NoMemory);
+        }
         let mut entry : RawL3Entry = RawL3Entry::new(0u64);
         entry.set_value(EntryValid::Valid, RawL3Entry::VALID); // set entry to valid 
         entry.set_value(PageType::Page, RawL3Entry::TYPE); // set entry type to memory block
-        entry.set_value(EntryPerm::USER_RW, RawL3Entry::AP);
+        perm.set_entry(&mut entry); // AP, UXN and PXN
         entry.set_value(1u64, RawL3Entry::AF);
         entry.set_value(EntryAttr::Mem, RawL3Entry::ATTR); // normal mem attribute
         entry.set_value(EntrySh::ISh, RawL3Entry::SH); // inner sharable for normal memory entry
-        entry.set_masked(&page as *const _ as u64, RawL3Entry::ADDR);
-        let phys_addr = entry.get_value(RawL3Entry::ADDR);
+        entry.set_masked(page as u64, RawL3Entry::ADDR);
         self.set_entry(VirtualAddr::from(real_va), entry);
+        self.pages += 1;
 
-        let pa15 = VirtAddr::new(real_va as u64).get_value(VirtAddr::PA);
-        let real_pa = phys_addr << 16 | pa15;
+        let page = unsafe {core::slice::from_raw_parts_mut(page, PAGE_SIZE)};
+        for byte in page.iter_mut() { // pages are handed out to user space, never leak old data
+            *byte = 0;
+        }
+        Ok(page)
+    }
 
-        unsafe {core::slice::from_raw_parts_mut(real_pa as *mut u8, PAGE_SIZE)}
-        
+    /// Returns the page that the given page-aligned virtual address translates to,
+    /// or `None` if no page has been allocated there yet.
+    pub fn get_page(&mut self, va: VirtualAddr) -> Option<&mut [u8]> {
+        if va.as_usize() < USER_IMG_BASE {
+            return None;
+        }
+        let real_va = VirtualAddr::from(va.as_usize() - USER_IMG_BASE);
+        if self.is_invalid(real_va) {
+            return None;
+        }
+        let page = self.get_entry(real_va).get_masked(RawL3Entry::ADDR);
+        Some(unsafe {core::slice::from_raw_parts_mut(page as *mut u8, PAGE_SIZE)})
+    }
+
+    /// Returns the number of pages mapped in this page table, including the pages
+    /// still shared copy-on-write with a forked page table.
+    pub fn mapped_pages(&self) -> usize {
+        self.pages
+    }
+
+    /// Returns the permission of the page containing the given virtual address, or
+    /// `None` if no page is mapped there.
+    pub fn get_perm(&self, va: VirtualAddr) -> Option<PagePerm> {
+        if va.as_usize() < USER_IMG_BASE {
+            return None;
+        }
+        let real_va = VirtualAddr::from((va.as_usize() - USER_IMG_BASE) & PAGE_MASK);
+        if self.is_invalid(real_va) {
+            return None;
+        }
+        Some(PagePerm::from_entry(&self.get_entry(real_va)))
+    }
+
+    /// Unmaps the page at the given page-aligned virtual address and frees it unless it
+    /// is still shared with a forked page table. Returns `false` if no page was mapped.
+    pub fn unmap(&mut self, va: VirtualAddr) -> bool {
+        if va.as_usize() < USER_IMG_BASE {
+            return false;
+        }
+        let real_va = VirtualAddr::from(va.as_usize() - USER_IMG_BASE);
+        if self.is_invalid(real_va) {
+            return false;
+        }
+        let page = self.get_entry(real_va).get_masked(RawL3Entry::ADDR) as *mut u8;
+        self.set_entry(real_va, RawL3Entry::new(0u64));
+        self.pages -= 1;
+        // the page may be freed: no core may keep using it through a stale entry
+        unsafe { tlb_invalidate_va(va.as_u64()) };
+        if release_page(page as usize) {
+            unsafe { ALLOCATOR.dealloc(page, Page::layout()) };
+        }
+        true
+    }
+
+    /// Returns the physical address the user virtual address `va` translates to and the
+    /// permission of its page, or `None` if `va` is not mapped.
+    pub fn translate(&self, va: VirtualAddr) -> Option<(PhysicalAddr, PagePerm)> {
+        if va.as_usize() < USER_IMG_BASE {
+            return None;
+        }
+        let real_va = VirtualAddr::from((va.as_usize() - USER_IMG_BASE) & PAGE_MASK);
+        if self.is_invalid(real_va) {
+            return None;
+        }
+        let entry = self.get_entry(real_va);
+        let page = entry.get_masked(RawL3Entry::ADDR) as usize;
+        Some((PhysicalAddr::from(page + (va.as_usize() & !PAGE_MASK)), PagePerm::from_entry(&entry)))
+    }
+
+    /// Returns a new `UserPageTable` mapping the same pages as this one. The pages are
+    /// shared: writable ones are made read-only and marked copy-on-write in both
+    /// tables, so that the first write from either side makes a private copy through
+    /// `copy_on_write()`. The pages at the addresses `shared` returns `true` for stay
+    /// shared on writes as well: they keep their entry here and are read-only in the
+    /// new table.
+    pub fn fork<F: Fn(VirtualAddr) -> bool>(&mut self, shared: F) -> UserPageTable {
+        let mut child = UserPageTable::new();
+        let mut downgraded = false;
+        for l2_index in 0..self.l3.len() {
+            for l3_index in 0..self.l3[l2_index].entries.len() {
+                let mut entry = self.l3[l2_index].entries[l3_index].0;
+                if entry.get_value(RawL3Entry::VALID) != EntryValid::Valid {
+                    continue;
+                }
+                share_page(entry.get_masked(RawL3Entry::ADDR) as usize);
+                let va = USER_IMG_BASE + (l2_index << 29) + (l3_index << 16);
+                if shared(VirtualAddr::from(va)) {
+                    entry.set_value(EntryPerm::USER_RO, RawL3Entry::AP);
+                } else if entry.get_value(RawL3Entry::AP) == EntryPerm::USER_RW {
+                    entry.set_value(EntryPerm::USER_RO, RawL3Entry::AP);
+                    entry.set_value(1u64, RawL3Entry::COW);
+                    self.l3[l2_index].entries[l3_index].0 = entry;
+                    downgraded = true;
+                }
+                child.l3[l2_index].entries[l3_index].0 = entry;
+            }
+        }
+        child.pages = self.pages;
+        if downgraded {
+            // no core may keep writing to a shared page through a stale entry
+            unsafe { tlb_invalidate_all() };
+        }
+        child
+    }
+
+    /// Resolves a write to the copy-on-write page containing `va`. If the page is still
+    /// shared with another page table, it is first copied into a new private page. The
+    /// page is writable again afterwards.
+    ///
+    /// Returns `NoAccess` if the page is not a copy-on-write page and `NoMemory` if the
+    /// copy could not be allocated.
+    pub fn copy_on_write(&mut self, va: VirtualAddr) -> OsResult<()> {
+        if va.as_usize() < USER_IMG_BASE {
+            return Err(OsError::NoAccess);
+        }
+        let real_va = VirtualAddr::from((va.as_usize() - USER_IMG_BASE) & PAGE_MASK);
+        if self.is_invalid(real_va) {
+            return Err(OsError::NoAccess);
+        }
+        let mut entry = self.get_entry(real_va);
+        if entry.get_value(RawL3Entry::COW) == 0 {
+            return Err(OsError::NoAccess);
+        }
+
+        let old_page = entry.get_masked(RawL3Entry::ADDR) as usize;
+        if !release_page(old_page) { // someone else still uses it, take a private copy
+            let page = unsafe {ALLOCATOR.alloc(Page::layout())};
+            if page == core::ptr::null_mut() {
+                share_page(old_page); // keep our reference
+                return Err(OsError::NoMemory);
+            }
+            unsafe { core::ptr::copy_nonoverlapping(old_page as *const u8, page, PAGE_SIZE) };
+            entry.set_masked(page as u64, RawL3Entry::ADDR);
+        }
+        entry.set_value(EntryPerm::USER_RW, RawL3Entry::AP);
+        entry.set_value(0u64, RawL3Entry::COW);
+        self.set_entry(real_va, entry);
+        // the other threads of the process must not keep reading the old page
+        unsafe { tlb_invalidate_va(va.as_u64() & PAGE_MASK as u64) };
+        Ok(())
+    }
+
+    /// Changes the permission of the page mapped at the given page-aligned virtual
+    /// address to `perm`. Returns `false` if no page is mapped there.
+    pub fn set_perm(&mut self, va: VirtualAddr, perm: PagePerm) -> bool {
+        if va.as_usize() < USER_IMG_BASE {
+            return false;
+        }
+        let real_va = VirtualAddr::from(va.as_usize() - USER_IMG_BASE);
+        if self.is_invalid(real_va) {
+            return false;
+        }
+        let mut entry = self.get_entry(real_va);
+        perm.set_entry(&mut entry);
+        self.set_entry(real_va, entry);
+        unsafe { tlb_invalidate_va(va.as_u64()) };
+        true
     }
 }
 
 impl Deref for KernPageTable {
-    type Target = PageTable;
+    type Target = PageTable<KernL3Tables>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -334,10 +622,10 @@ impl Deref for KernPageTable {
 }
 
 impl Deref for UserPageTable {
-    type Target = PageTable;
+    type Target = PageTable<UserL3Tables>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.table
     }
 }
 
@@ -349,7 +637,7 @@ impl DerefMut for KernPageTable {
 
 impl DerefMut for UserPageTable {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.table
     }
 }
 
@@ -357,11 +645,15 @@ impl DerefMut for UserPageTable {
 // FIXME: Implement `fmt::Debug` as you need.
 
 // To implement Drop traits, iterate the internal pagetable and dealloc() each entry that exists.
+// Pages still shared with a forked page table are left to the last one referencing them.
 impl Drop for UserPageTable {
     fn drop(&mut self) {
         for entry in (*self).into_iter() {
             if entry.0.get_value(RawL3Entry::VALID) == EntryValid::Valid { // entry is valid, dealloc
-                let ptr : *mut u8 = entry.0.get_value(RawL3Entry::ADDR) as *mut u8;
+                let ptr : *mut u8 = entry.0.get_masked(RawL3Entry::ADDR) as *mut u8;
+                if !release_page(ptr as usize) {
+                    continue;
+                }
                 let layout : Layout = Page::layout();
                 unsafe {ALLOCATOR.dealloc(ptr, layout)};
             }
@@ -0,0 +1,200 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use shim::io::{Read, Seek, SeekFrom, Write};
+
+use fat32::traits::File;
+use kernel_api::{OsError, OsResult};
+
+use crate::fs::PiVFatHandle;
+use crate::mutex::Mutex;
+use crate::param::PAGE_SIZE;
+use crate::vm::PagePerm;
+
+/// What the pages of a `Region` are filled with when they are first touched.
+#[derive(Clone)]
+pub enum Backing {
+    /// Zero-filled memory.
+    Anonymous,
+    /// Zero-filled memory of the user stack, which grows down on demand.
+    Stack,
+    /// Zero-filled memory of the heap, which `brk` grows and shrinks.
+    Heap,
+    /// A segment of an ELF file. The first `file_size` bytes of the region come
+    /// from `data[offset..]`, the rest (.bss) is zero-filled.
+    Elf {
+        data: Arc<Vec<u8>>,
+        offset: usize,
+        file_size: usize,
+    },
+    /// A FAT32 file mapped by `mmap` from byte `offset` on. Bytes past the end of
+    /// the file are zero-filled. If `shared` is set, writes to the pages are written
+    /// back to the file by `munmap`, `msync` and the exit of the process.
+    File {
+        file: Arc<Mutex<fat32::vfat::File<PiVFatHandle>>>,
+        offset: usize,
+        shared: bool,
+    },
+}
+
+impl fmt::Debug for Backing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Backing::Anonymous => write!(f, "Anonymous"),
+            Backing::Stack => write!(f, "Stack"),
+            Backing::Heap => write!(f, "Heap"),
+            Backing::Elf { offset, file_size, .. } => {
+                write!(f, "Elf(offset: 0x{:x}, size: 0x{:x})", offset, file_size)
+            }
+            Backing::File { offset, shared, .. } => {
+                write!(f, "File(offset: 0x{:x}, shared: {})", offset, shared)
+            }
+        }
+    }
+}
+
+/// A contiguous range of the user address space of a process. Pages of a
+/// region are only mapped when they are first accessed.
+#[derive(Debug, Clone)]
+pub struct Region {
+    /// The first virtual address of the region.
+    pub start: usize,
+    /// The size of the region in bytes.
+    pub size: usize,
+    /// The permission of the pages of the region.
+    pub perm: PagePerm,
+    /// The source of the contents of the region.
+    pub backing: Backing,
+}
+
+impl Region {
+    /// Returns a new `Region` covering `size` bytes from `start`.
+    pub fn new(start: usize, size: usize, perm: PagePerm, backing: Backing) -> Region {
+        Region {
+            start: start,
+            size: size,
+            perm: perm,
+            backing: backing,
+        }
+    }
+
+    /// Returns `true` if `va` is inside this region.
+    pub fn contains(&self, va: usize) -> bool {
+        va >= self.start && va - self.start < self.size
+    }
+
+    /// Returns `true` if any byte of the page starting at `page_va` is inside
+    /// this region.
+    pub fn overlaps(&self, page_va: usize) -> bool {
+        self.intersects(page_va, PAGE_SIZE)
+    }
+
+    /// Returns `true` if any of the `size` bytes from `start` is inside this region.
+    pub fn intersects(&self, start: usize, size: usize) -> bool {
+        // compare last addresses so a region ending at the top of memory doesn't overflow
+        self.size != 0
+            && size != 0
+            && self.start <= start + (size - 1)
+            && start <= self.start + (self.size - 1)
+    }
+
+    /// Returns the part of this region between `start` and `end`, which must lie
+    /// inside it. A file backing keeps pointing at the same bytes of the file.
+    pub fn slice(&self, start: usize, end: usize) -> Region {
+        let backing = match self.backing {
+            Backing::File { ref file, offset, shared } => Backing::File {
+                file: file.clone(),
+                offset: offset + (start - self.start),
+                shared: shared,
+            },
+            ref backing => backing.clone(),
+        };
+        Region::new(start, end - start, self.perm, backing)
+    }
+
+    /// Returns `true` if writes to the pages of this region go back to a file.
+    pub fn is_shared_file(&self) -> bool {
+        match self.backing {
+            Backing::File { shared, .. } => shared && self.perm.is_writable(),
+            _ => false,
+        }
+    }
+
+    /// Copies the contents of this region that fall into the page starting at
+    /// `page_va` into `page`. `page` is expected to be zeroed.
+    ///
+    /// Returns an error if the backing file can't be read.
+    pub fn fill(&self, page_va: usize, page: &mut [u8]) -> OsResult<()> {
+        match self.backing {
+            Backing::Anonymous | Backing::Stack | Backing::Heap => Ok(()),
+            Backing::Elf { ref data, offset, file_size } => {
+                let (copy_start, length) = match self.page_range(page_va, file_size) {
+                    Some(range) => range,
+                    None => return Ok(()),
+                };
+                let src = offset + (copy_start - self.start);
+                page[(copy_start - page_va)..(copy_start - page_va + length)]
+                    .copy_from_slice(&data[src..(src + length)]);
+                Ok(())
+            }
+            Backing::File { ref file, offset, .. } => {
+                let mut file = file.lock();
+                let file_size = (file.size() as usize).saturating_sub(offset);
+                let (copy_start, length) = match self.page_range(page_va, file_size) {
+                    Some(range) => range,
+                    None => return Ok(()),
+                };
+                file.seek(SeekFrom::Start((offset + (copy_start - self.start)) as u64))?;
+                file.read_exact(&mut page[(copy_start - page_va)..(copy_start - page_va + length)])?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the bytes of the mapped page starting at `page_va`, whose contents are
+    /// `page`, back to the file if this is a shared file mapping. The file does not
+    /// grow: bytes past its end are dropped.
+    ///
+    /// Returns an error if the backing file can't be written.
+    pub fn write_back(&self, page_va: usize, page: &[u8]) -> OsResult<()> {
+        if !self.is_shared_file() {
+            return Ok(());
+        }
+        if let Backing::File { ref file, offset, .. } = self.backing {
+            let mut file = file.lock();
+            let file_size = (file.size() as usize).saturating_sub(offset);
+            let (copy_start, length) = match self.page_range(page_va, file_size) {
+                Some(range) => range,
+                None => return Ok(()),
+            };
+            file.seek(SeekFrom::Start((offset + (copy_start - self.start)) as u64))?;
+            file.write_all(&page[(copy_start - page_va)..(copy_start - page_va + length)])?;
+        }
+        Ok(())
+    }
+
+    /// Writes the modified sectors of the backing file to the disk if this is a
+    /// shared file mapping.
+    pub fn sync(&self) -> OsResult<()> {
+        match self.backing {
+            Backing::File { ref file, .. } if self.is_shared_file() => Ok(file.lock().sync()?),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the first address and the length of the part of the page starting at
+    /// `page_va` that is inside both this region and its first `data_size` bytes, or
+    /// `None` if they don't intersect.
+    fn page_range(&self, page_va: usize, data_size: usize) -> Option<(usize, usize)> {
+        if data_size == 0 {
+            return None;
+        }
+        let copy_start = core::cmp::max(page_va, self.start);
+        let data_size = core::cmp::min(data_size, self.size);
+        let copy_last = core::cmp::min(page_va + (PAGE_SIZE - 1), self.start + (data_size - 1));
+        if copy_start > copy_last {
+            return None;
+        }
+        Some((copy_start, copy_last - copy_start + 1))
+    }
+}
@@ -0,0 +1,85 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use kernel_api::{OsError, OsResult};
+
+use crate::param::{PAGE_MASK, PAGE_SIZE};
+use crate::vm::{Access, UserPageTable, VirtualAddr};
+
+/// Splits the user range of `len` bytes at `va` into the pieces that fall into each
+/// page and returns the physical address and length of each, checking that every
+/// page is mapped in `vmap` and allows `access`.
+///
+/// Returns `BadAddress` if the range wraps around, starts in kernel space, or
+/// touches a page that is unmapped or lacks the permission.
+fn translate_range(vmap: &UserPageTable, va: usize, len: usize, access: Access)
+    -> OsResult<Vec<(usize, usize)>> {
+    va.checked_add(len).ok_or(OsError::BadAddress)?;
+    let mut pieces = Vec::new();
+    let mut done = 0;
+    while done < len {
+        let addr = va + done;
+        let (pa, perm) = vmap.translate(VirtualAddr::from(addr)).ok_or(OsError::BadAddress)?;
+        if !perm.allows(access) {
+            return Err(OsError::BadAddress);
+        }
+        let length = core::cmp::min(PAGE_SIZE - (addr & !PAGE_MASK), len - done);
+        pieces.push((pa.as_usize(), length));
+        done += length;
+    }
+    Ok(pieces)
+}
+
+/// Returns the user range of `len` bytes at `va` as kernel slices, one for each
+/// page it spans. Every page must be mapped readable in `vmap`, otherwise
+/// `BadAddress` is returned.
+pub fn user_slices<'a>(vmap: &'a UserPageTable, va: usize, len: usize) -> OsResult<Vec<&'a [u8]>> {
+    let pieces = translate_range(vmap, va, len, Access::Read)?;
+    Ok(pieces.into_iter()
+        .map(|(pa, length)| unsafe { core::slice::from_raw_parts(pa as *const u8, length) })
+        .collect())
+}
+
+/// Returns the user range of `len` bytes at `va` as mutable kernel slices, one for
+/// each page it spans. Every page must be mapped writable in `vmap`, otherwise
+/// `BadAddress` is returned.
+pub fn user_slices_mut<'a>(vmap: &'a mut UserPageTable, va: usize, len: usize) -> OsResult<Vec<&'a mut [u8]>> {
+    let pieces = translate_range(vmap, va, len, Access::Write)?;
+    Ok(pieces.into_iter()
+        .map(|(pa, length)| unsafe { core::slice::from_raw_parts_mut(pa as *mut u8, length) })
+        .collect())
+}
+
+/// Copies `buf.len()` bytes of the user memory at `va` into `buf`. Every page of
+/// the range must be mapped readable in `vmap`, otherwise `BadAddress` is returned.
+pub fn copy_from_user(vmap: &UserPageTable, va: usize, buf: &mut [u8]) -> OsResult<()> {
+    let mut copied = 0;
+    for slice in user_slices(vmap, va, buf.len())? {
+        buf[copied..(copied + slice.len())].copy_from_slice(slice);
+        copied += slice.len();
+    }
+    Ok(())
+}
+
+/// Copies `buf` into the user memory at `va`. Every page of the range must be mapped
+/// writable in `vmap`, otherwise `BadAddress` is returned and nothing is written.
+pub fn copy_to_user(vmap: &mut UserPageTable, va: usize, buf: &[u8]) -> OsResult<()> {
+    let mut copied = 0;
+    for slice in user_slices_mut(vmap, va, buf.len())? {
+        let length = slice.len();
+        slice.copy_from_slice(&buf[copied..(copied + length)]);
+        copied += length;
+    }
+    Ok(())
+}
+
+/// Reads the UTF-8 string of `len` bytes at the user address `va`. Returns
+/// `InvalidArgument` if the string is longer than a page or not valid UTF-8.
+pub fn string_from_user(vmap: &UserPageTable, va: usize, len: usize) -> OsResult<String> {
+    if len > PAGE_SIZE {
+        return Err(OsError::InvalidArgument);
+    }
+    let mut buf = Vec::new();
+    buf.resize(len, 0u8);
+    copy_from_user(vmap, va, &mut buf)?;
+    String::from_utf8(buf).map_err(|_| OsError::InvalidArgument)
+}
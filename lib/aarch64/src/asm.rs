@@ -35,6 +35,36 @@ pub fn sev() {
     unsafe { asm!("sev" ::::"volatile") };
 }
 
+/// Invalidate the TLB entries of every core translating the virtual address `va`,
+/// once the page table updates before the call are visible to the table walkers.
+/// Only `VA[55:12]` goes into the operand: its upper bits are RES0 or TTL hints,
+/// which the upper bits of a TTBR1 address would otherwise set.
+#[inline(always)]
+pub unsafe fn tlb_invalidate_va(va: u64) {
+    asm!("dsb ishst
+          tlbi vaae1is, $0
+          dsb ish
+          isb"
+         :
+         : "r"((va >> 12) & 0xFFF_FFFF_FFFF)
+         : "memory"
+         : "volatile");
+}
+
+/// Invalidate every EL1&0 TLB entry of every core, once the page table updates
+/// before the call are visible to the table walkers.
+#[inline(always)]
+pub unsafe fn tlb_invalidate_all() {
+    asm!("dsb ishst
+          tlbi vmalle1is
+          dsb ish
+          isb"
+         :
+         :
+         : "memory"
+         : "volatile");
+}
+
 /// Enable (unmask) interrupts
 #[inline(always)]
 pub unsafe fn sti() {
@@ -42,4 +42,11 @@ pub fn affinity() -> usize {
     unsafe {
         MPIDR_EL1.get_value(MPIDR_EL1::Aff0) as usize
     }
-}
\ No newline at end of file
+}
+/// Returns `true` if the MMU of the current core is enabled, which exclusive
+/// (atomic) memory accesses depend on.
+pub fn is_mmu_ready() -> bool {
+    unsafe {
+        SCTLR_EL1.get_value(SCTLR_EL1::M) == 1
+    }
+}
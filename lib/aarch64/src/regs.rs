@@ -225,3 +225,23 @@ defreg!(CNTHCTL_EL2, [
 ]);
 
 defreg!(CNTVOFF_EL2);
+
+// (ref. D7.5.1: Counter-timer Frequency register)
+defreg!(CNTFRQ_EL0, [
+    ClockFreq [31-0], // Clock frequency of the system counter, in Hz
+]);
+
+// (ref. D7.5.10: Counter-timer Physical Timer Control register)
+defreg!(CNTP_CTL_EL0, [
+    ISTATUS [2-2], // The timer condition is met
+    IMASK   [1-1], // Timer interrupt mask
+    ENABLE  [0-0], // Timer enable
+]);
+
+// (ref. D7.5.12: Counter-timer Physical Timer TimerValue register)
+defreg!(CNTP_TVAL_EL0, [
+    TimerValue [31-0], // Counter ticks until the timer condition is met
+]);
+
+// (ref. D7.5.15: Counter-timer Physical Count register)
+defreg!(CNTPCT_EL0);
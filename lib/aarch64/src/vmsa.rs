@@ -66,6 +66,9 @@ defbit!(VirtAddr, [
 
 
 defbit!(RawL3Entry, [
+    COW   [55-55], // software-defined: shared copy-on-write page
+    UXN   [54-54],
+    PXN   [53-53],
     ADDR  [47-16],
 
     AF    [10-10],
@@ -179,6 +182,14 @@ impl fmt::Debug for RawL3Entry {
             _ => "????-??",
         })?;
 
+        write!(f, "|{}{}", match self.get_value(RawL3Entry::UXN) {
+            0 => "UX",
+            _ => "--",
+        }, match self.get_value(RawL3Entry::PXN) {
+            0 => "PX",
+            _ => "--",
+        })?;
+
         // NS    [05-05],
         
         write!(f, "-> {:08x} ({:x})",
@@ -484,4 +484,166 @@ fn shuffle_test() {
 // shuffle_test
 // failing at hash_file_recursive call, failing at entries() call
 // apparently next is call infinite ammount of time
-// 
\ No newline at end of file
+// 
+/// A disk image shared with the test, so that it can look at what the file system
+/// wrote to the disk.
+#[derive(Clone)]
+struct SharedImage(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+impl SharedImage {
+    /// Returns the bytes of sector `n` of the image.
+    fn sector(&self, n: u64) -> Vec<u8> {
+        let image = self.0.lock().expect("all okay");
+        let start = (n * 512) as usize;
+        image.get_ref()[start..start + 512].to_vec()
+    }
+}
+
+impl BlockDevice for SharedImage {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().expect("all okay").read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().expect("all okay").write_sector(n, buf)
+    }
+}
+
+/// The sector of the image of `mock_file_image()` where `/DATA.BIN` starts. Its
+/// second cluster is the next sector.
+const MOCK_FILE_SECTOR: u64 = 4;
+
+/// Returns a 64-sector FAT32 image with 512-byte sectors and clusters, holding
+/// `/DATA.BIN` with the contents `data`, at most two clusters long.
+fn mock_file_image(data: &[u8]) -> SharedImage {
+    assert!(data.len() <= 1024);
+    let mut image = vec![0u8; 64 * 512];
+
+    // MBR: a single FAT32 (LBA) partition from sector 1 on
+    image[446 + 4] = 0xC;
+    image[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+    image[446 + 12..446 + 16].copy_from_slice(&63u32.to_le_bytes());
+    image[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    // EBPB: one reserved sector, one FAT of one sector, root directory at cluster 2
+    let ebpb = 512;
+    image[ebpb + 11..ebpb + 13].copy_from_slice(&512u16.to_le_bytes());
+    image[ebpb + 13] = 1;
+    image[ebpb + 14..ebpb + 16].copy_from_slice(&1u16.to_le_bytes());
+    image[ebpb + 16] = 1;
+    image[ebpb + 22..ebpb + 24].copy_from_slice(&1u16.to_le_bytes());
+    image[ebpb + 44..ebpb + 48].copy_from_slice(&2u32.to_le_bytes());
+    image[ebpb + 510..ebpb + 512].copy_from_slice(&[0x55, 0xAA]);
+
+    // FAT in sector 2: the root directory is cluster 2, the file clusters 3 and 4
+    let fat = 2 * 512;
+    let entries: [u32; 5] = [0x0FFFFFF8, 0x0FFFFFFF, 0x0FFFFFFF, 4, 0x0FFFFFFF];
+    for (index, entry) in entries.iter().enumerate() {
+        image[fat + index * 4..fat + index * 4 + 4].copy_from_slice(&entry.to_le_bytes());
+    }
+
+    // root directory in sector 3, the first data sector
+    let root = 3 * 512;
+    image[root..root + 11].copy_from_slice(b"DATA    BIN");
+    image[root + 11] = 0x20;
+    image[root + 26..root + 28].copy_from_slice(&3u16.to_le_bytes());
+    image[root + 28..root + 32].copy_from_slice(&(data.len() as u32).to_le_bytes());
+
+    let start = (MOCK_FILE_SECTOR * 512) as usize;
+    image[start..start + data.len()].copy_from_slice(data);
+    SharedImage(Arc::new(Mutex::new(Cursor::new(image))))
+}
+
+/// Returns `len` bytes of test data.
+fn mock_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn open_mock_file(image: &SharedImage) -> vfat::File<StdVFatHandle> {
+    let vfat = VFat::<StdVFatHandle>::from(image.clone()).expect("failed to initialize VFAT from image");
+    vfat.open_file("/data.bin").expect("file")
+}
+
+#[test]
+fn test_file_seek_end() {
+    let image = mock_file_image(&mock_data(700));
+    let mut file = open_mock_file(&image);
+
+    let mut buf = [0u8; 100];
+    file.read_exact(&mut buf).expect("read");
+    assert_eq!(file.seek(io::SeekFrom::End(0)).expect("seek to the end"), 700);
+    assert_eq!(file.seek(io::SeekFrom::End(-10)).expect("seek before the end"), 690);
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).expect("read the tail");
+    assert_eq!(tail, &mock_data(700)[690..]);
+
+    assert!(file.seek(io::SeekFrom::End(1)).is_err());
+    assert!(file.seek(io::SeekFrom::End(-701)).is_err());
+    assert_eq!(file.seek(io::SeekFrom::Current(0)).expect("position"), 700);
+}
+
+#[test]
+fn test_file_write_across_clusters() {
+    let data = mock_data(1000);
+    let image = mock_file_image(&data);
+    let mut file = open_mock_file(&image);
+
+    let patch = [0xA5u8; 300];
+    file.seek(io::SeekFrom::Start(400)).expect("seek");
+    file.write_all(&patch).expect("write across the cluster boundary");
+    assert_eq!(file.seek(io::SeekFrom::Current(0)).expect("position"), 700);
+    assert_eq!(file.size(), 1000);
+
+    let mut expected = data.clone();
+    expected[400..700].copy_from_slice(&patch);
+    let mut contents = Vec::new();
+    file.seek(io::SeekFrom::Start(0)).expect("seek");
+    file.read_to_end(&mut contents).expect("read back");
+    assert_eq!(contents, expected);
+}
+
+#[test]
+fn test_file_write_does_not_extend() {
+    let data = mock_data(700);
+    let image = mock_file_image(&data);
+    let mut file = open_mock_file(&image);
+
+    file.seek(io::SeekFrom::End(-10)).expect("seek");
+    assert_eq!(file.write(&[0xFFu8; 100]).expect("write up to the end"), 10);
+    match file.write(&[0xFFu8; 1]) {
+        Err(ref error) if error.kind() == io::ErrorKind::WriteZero => {}
+        result => panic!("expected WriteZero at the end of the file, got {:?}", result),
+    }
+    assert_eq!(file.size(), 700);
+
+    // the cluster chain itself isn't extended either
+    let first_cluster = file.first_cluster;
+    let written = file.vfat.lock(|vfat| vfat.write_chain(first_cluster, 1000, &[0xFFu8; 100]));
+    assert_eq!(written.expect("write to the last cluster"), 24);
+}
+
+#[test]
+fn test_file_flush_writes_dirty_sectors() {
+    let data = mock_data(1000);
+    let image = mock_file_image(&data);
+    let root = image.sector(MOCK_FILE_SECTOR - 1);
+    let mut file = open_mock_file(&image);
+
+    file.seek(io::SeekFrom::Start(510)).expect("seek");
+    file.write_all(b"dirty").expect("write");
+    assert_eq!(image.sector(MOCK_FILE_SECTOR), &data[..512], "written before flush");
+    assert_eq!(image.sector(MOCK_FILE_SECTOR + 1)[..488], data[512..1000]);
+
+    file.flush().expect("flush");
+    assert_eq!(&image.sector(MOCK_FILE_SECTOR)[510..], b"di");
+    assert_eq!(&image.sector(MOCK_FILE_SECTOR + 1)[..3], b"rty");
+    assert_eq!(image.sector(MOCK_FILE_SECTOR - 1), root);
+
+    // a fresh file system reads what was flushed
+    let mut file = open_mock_file(&image);
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).expect("read back");
+    let mut expected = data.clone();
+    expected[510..515].copy_from_slice(b"dirty");
+    assert_eq!(contents, expected);
+}
@@ -118,7 +118,35 @@ impl CachedPartition {
             let mut cache_entry = self.read_entry(sector)?;
             self.cache.insert(sector, cache_entry); // insert this cache at the sector key
         }
-        Ok(&mut self.cache.get_mut(&sector).unwrap().data)
+        let cache_entry = self.cache.get_mut(&sector).unwrap();
+        cache_entry.dirty = true;
+        Ok(&mut cache_entry.data)
+    }
+
+    /// Writes every dirty cached sector back to the disk.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to the disk fails. Sectors that could not be
+    /// written stay dirty.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let factor = self.factor();
+        let device_sector_size = self.device.sector_size() as usize;
+        for (sector, cache_entry) in self.cache.iter_mut() {
+            if !cache_entry.dirty {
+                continue;
+            }
+            if *sector < self.partition.start {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "flush: can't map logical to physical"));
+            }
+            let physical_sector = self.partition.start + (sector - self.partition.start) * factor;
+            for index in 0..factor {
+                let start = index as usize * device_sector_size;
+                self.device.write_sector(physical_sector + index, &cache_entry.data[start..(start + device_sector_size)])?;
+            }
+            cache_entry.dirty = false;
+        }
+        Ok(())
     }
 
     /// Returns a reference to the cached sector `sector`. If the sector is not
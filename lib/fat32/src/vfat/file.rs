@@ -6,7 +6,7 @@ use shim::io::{self, SeekFrom};
 use crate::traits;
 use crate::vfat::{Cluster, Metadata, VFatHandle};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct File<HANDLE: VFatHandle> {
     pub vfat: HANDLE,
     pub file_name : alloc::string::String,
@@ -21,7 +21,7 @@ pub struct File<HANDLE: VFatHandle> {
 impl<HANDLE: VFatHandle> traits::File for File<HANDLE> {
     /// Writes any buffered data to disk.
     fn sync(&mut self) -> io::Result<()> {
-        Ok(())
+        self.vfat.lock(|vfat| vfat.flush())
     }
 
     /// Returns the size of the file in bytes.
@@ -31,11 +31,21 @@ impl<HANDLE: VFatHandle> traits::File for File<HANDLE> {
 }
 
 impl<HANDLE: VFatHandle> io::Write for File<HANDLE> {
+    /// Overwrites the file from the current position on. The file is never
+    /// extended: writing at the end of the file returns a `WriteZero` error.
     fn write(&mut self, buf : &[u8]) -> Result<usize, io::Error> {
-        unimplemented!("io::Write - Write for file");
+        let can_write = core::cmp::min(self.file_size - self.file_ptr, buf.len() as u32);
+        if can_write == 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Write fail. Files can't grow"));
+        }
+
+        let file_ptr = self.file_ptr as usize;
+        let written = self.vfat.lock(|vfat| vfat.write_chain(self.first_cluster, file_ptr, &buf[..can_write as usize]))?;
+        self.file_ptr += written as u32;
+        Ok(written)
     }
     fn flush(&mut self) -> Result<(), io::Error> {
-        unimplemented!("io::Write - Flush for file");
+        traits::File::sync(self)
     }
 }
 
@@ -108,32 +118,14 @@ impl<HANDLE: VFatHandle> io::Seek for File<HANDLE> {
                 }
             },
             SeekFrom::End(offset_add) => {
-                let mut pointer : u32 = 0u32;
-                if offset_add.is_negative() {
-                    match self.file_ptr.checked_sub(-offset_add as u32) {
-                        Some(new_pointer) => {
-                            pointer = new_pointer;
-                        },
-                        None => {
-                            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek fail. Offset > file_size"));
-                        }
+                let pointer = match (self.file_size as i64).checked_add(offset_add) {
+                    Some(pointer) if pointer >= 0 && pointer <= self.file_size as i64 => pointer,
+                    _ => {
+                        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek fail. Offset > file_size"));
                     }
-                } else {
-                    match self.file_ptr.checked_add(offset_add as u32) {
-                        Some(new_pointer) => {
-                            pointer = new_pointer;
-                        },
-                        None => {
-                            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek fail. Offset > file_size"));
-                        }
-                    }
-                }
-                if pointer <= self.file_size as u32{
-                    self.file_ptr = pointer;
-                    return Ok(self.file_ptr as u64);
-                } else {
-                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek fail. Offset > file_size"));
-                }
+                };
+                self.file_ptr = pointer as u32;
+                return Ok(self.file_ptr as u64);
             }
         }
     }
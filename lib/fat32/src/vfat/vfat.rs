@@ -145,6 +145,52 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
         }
 
     }
+
+    //  * A method to overwrite the bytes of the cluster chain starting at `start`
+    //    from byte `offset` on with `buf`. The chain is not extended: returns the
+    //    number of bytes written, which is less than `buf.len()` if the chain ends.
+    pub fn write_chain(&mut self, start: Cluster, offset: usize, buf: &[u8]) -> io::Result<usize> {
+        let bytes_per_sector = self.bytes_per_sector as usize;
+        let cluster_size = bytes_per_sector * self.sectors_per_cluster as usize;
+        let mut cluster_node = start;
+        let mut cluster_start = 0; // offset of `cluster_node` in the chain
+        let mut written = 0;
+
+        while written < buf.len() {
+            let position = offset + written;
+            if position < cluster_start + cluster_size {
+                let cluster_offset = match cluster_node.offset() {
+                    Some(cluster_offset) => cluster_offset as u64,
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "can't get cluster offset")),
+                };
+                let in_cluster = position - cluster_start;
+                let sector = self.data_start_sector
+                    + cluster_offset * self.sectors_per_cluster as u64
+                    + (in_cluster / bytes_per_sector) as u64;
+                let in_sector = in_cluster % bytes_per_sector;
+                let length = core::cmp::min(bytes_per_sector - in_sector, buf.len() - written);
+                let data = self.device.get_mut(sector)?;
+                data[in_sector..(in_sector + length)].copy_from_slice(&buf[written..(written + length)]);
+                written += length;
+                continue;
+            }
+
+            match self.fat_entry(cluster_node)?.status() {
+                Status::Data(next_cluster) => {
+                    cluster_node = next_cluster;
+                    cluster_start += cluster_size;
+                },
+                Status::Eoc(_) => return Ok(written),
+                _ => return Err(io::Error::new(io::ErrorKind::Other, "Write into invalid cluster")),
+            }
+        }
+        Ok(written)
+    }
+
+    //  * A method to write every modified sector back to the disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.device.flush()
+    }
 }
 
 impl<'a, HANDLE: VFatHandle> FileSystem for &'a HANDLE {
@@ -0,0 +1,123 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::syscall::{exit, mmap, munmap, sbrk};
+use crate::{PROT_READ, PROT_WRITE};
+
+/// The size of the chunks the heap grows by, one page.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Blocks of bin `k` are `2^(MIN_BIN_SHIFT + k)` bytes large.
+const MIN_BIN_SHIFT: usize = 4;
+
+/// Number of bins, from 16 bytes up to half a chunk. Larger allocations are
+/// mapped with `mmap` on their own.
+const BIN_COUNT: usize = 12;
+
+/// The user-space heap allocator. Small allocations are served from
+/// power-of-two size classes whose blocks are carved out of chunks taken from
+/// the heap with `sbrk`; freed blocks go back to the free list of their class.
+/// Large allocations are mapped and unmapped with `mmap` and `munmap`.
+pub struct Allocator {
+    locked: AtomicBool,
+    /// Heads of the free lists. Each free block stores the address of the next one.
+    bins: UnsafeCell<[usize; BIN_COUNT]>,
+}
+
+unsafe impl Sync for Allocator {}
+
+impl Allocator {
+    /// Returns a new allocator with empty free lists.
+    pub const fn new() -> Allocator {
+        Allocator {
+            locked: AtomicBool::new(false),
+            bins: UnsafeCell::new([0; BIN_COUNT]),
+        }
+    }
+
+    /// Runs `f` with the free lists while holding the allocator lock.
+    fn with_bins<R>(&self, f: impl FnOnce(&mut [usize; BIN_COUNT]) -> R) -> R {
+        while self.locked.compare_and_swap(false, true, Ordering::Acquire) {}
+        let result = f(unsafe { &mut *self.bins.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+/// Returns the bin serving `layout`, or `None` if it is too large for any bin.
+fn bin_of(layout: &Layout) -> Option<usize> {
+    let size = core::cmp::max(core::cmp::max(layout.size(), layout.align()), 1 << MIN_BIN_SHIFT);
+    let bin = size.next_power_of_two().trailing_zeros() as usize - MIN_BIN_SHIFT;
+    if bin < BIN_COUNT {
+        Some(bin)
+    } else {
+        None
+    }
+}
+
+/// Grows the heap by a chunk and splits it into free blocks of `bin`.
+/// Returns `false` if the heap can't grow.
+fn refill(bins: &mut [usize; BIN_COUNT], bin: usize) -> bool {
+    let current = match sbrk(0) {
+        Ok(current) => current as usize,
+        Err(_) => return false,
+    };
+    // keep chunks aligned so that every block is aligned to its size
+    let start = (current + (CHUNK_SIZE - 1)) & !(CHUNK_SIZE - 1);
+    if sbrk((start + CHUNK_SIZE - current) as i64).is_err() {
+        return false;
+    }
+    let block_size = 1 << (bin + MIN_BIN_SHIFT);
+    let mut block = start + CHUNK_SIZE;
+    while block > start {
+        block -= block_size;
+        unsafe { *(block as *mut usize) = bins[bin] };
+        bins[bin] = block;
+    }
+    true
+}
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match bin_of(&layout) {
+            Some(bin) => self.with_bins(|bins| {
+                if bins[bin] == 0 && !refill(bins, bin) {
+                    return ptr::null_mut();
+                }
+                let block = bins[bin];
+                bins[bin] = *(block as *const usize);
+                block as *mut u8
+            }),
+            None if layout.align() <= CHUNK_SIZE => {
+                match mmap(0, layout.size() as u64, PROT_READ | PROT_WRITE) {
+                    Ok(addr) => addr as *mut u8,
+                    Err(_) => ptr::null_mut(),
+                }
+            },
+            None => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match bin_of(&layout) {
+            Some(bin) => self.with_bins(|bins| {
+                *(ptr as *mut usize) = bins[bin];
+                bins[bin] = ptr as usize;
+            }),
+            None => {
+                let _ = munmap(ptr as u64, layout.size() as u64);
+            }
+        }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator::new();
+
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    crate::println!("memory allocation of {} bytes failed", layout.size());
+    exit(-1)
+}
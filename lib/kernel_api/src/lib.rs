@@ -1,6 +1,11 @@
 #![feature(asm)]
+#![feature(naked_functions)]
+#![cfg_attr(feature = "user-allocator", feature(alloc_error_handler))]
 #![no_std]
 
+#[cfg(feature = "user-allocator")]
+extern crate alloc;
+
 use core::fmt;
 
 use shim::io;
@@ -8,6 +13,12 @@ use shim::io;
 #[cfg(feature = "user-space")]
 pub mod syscall;
 
+#[cfg(feature = "user-space")]
+pub mod sync;
+
+#[cfg(feature = "user-allocator")]
+pub mod allocator;
+
 pub type OsResult<T> = core::result::Result<T, OsError>;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -22,6 +33,9 @@ pub enum OsError {
     BadAddress = 50,
     FileExists = 60,
     InvalidArgument = 70,
+    BrokenPipe = 80,
+    Interrupted = 90,
+    WouldBlock = 100,
 
     IoError = 101,
     IoErrorEof = 102,
@@ -46,11 +60,15 @@ impl core::convert::From<u64> for OsError {
             50 => OsError::BadAddress,
             60 => OsError::FileExists,
             70 => OsError::InvalidArgument,
+            80 => OsError::BrokenPipe,
+            90 => OsError::Interrupted,
+            100 => OsError::WouldBlock,
 
             101 => OsError::IoError,
             102 => OsError::IoErrorEof,
             103 => OsError::IoErrorInvalidData,
             104 => OsError::IoErrorInvalidInput,
+            105 => OsError::IoErrorTimedOut,
 
             200 => OsError::InvalidSocket,
             201 => OsError::SocketAlreadyOpen,
@@ -69,6 +87,9 @@ impl core::convert::From<io::Error> for OsError {
             io::ErrorKind::InvalidInput => OsError::IoErrorInvalidInput,
             io::ErrorKind::TimedOut => OsError::IoErrorTimedOut,
             io::ErrorKind::NotFound => OsError::NoEntry,
+            io::ErrorKind::BrokenPipe => OsError::BrokenPipe,
+            io::ErrorKind::Interrupted => OsError::Interrupted,
+            io::ErrorKind::WouldBlock => OsError::WouldBlock,
             _ => OsError::IoError,
         }
     }
@@ -79,3 +100,123 @@ pub const NR_TIME: usize = 2;
 pub const NR_EXIT: usize = 3;
 pub const NR_WRITE: usize = 4;
 pub const NR_GETPID: usize = 5;
+pub const NR_FORK: usize = 6;
+pub const NR_EXEC: usize = 7;
+pub const NR_WAIT: usize = 8;
+pub const NR_OPEN: usize = 9;
+pub const NR_READ: usize = 10;
+pub const NR_WRITE_FD: usize = 11;
+pub const NR_CLOSE: usize = 12;
+pub const NR_LSEEK: usize = 13;
+pub const NR_FSTAT: usize = 14;
+pub const NR_BRK: usize = 15;
+pub const NR_MMAP: usize = 16;
+pub const NR_MUNMAP: usize = 17;
+pub const NR_MMAP_FILE: usize = 18;
+pub const NR_MSYNC: usize = 19;
+pub const NR_PIPE: usize = 20;
+pub const NR_DUP2: usize = 21;
+pub const NR_KILL: usize = 22;
+pub const NR_SIGACTION: usize = 23;
+pub const NR_SIGRETURN: usize = 24;
+pub const NR_THREAD_SPAWN: usize = 25;
+pub const NR_THREAD_EXIT: usize = 26;
+pub const NR_THREAD_JOIN: usize = 27;
+pub const NR_FUTEX_WAIT: usize = 28;
+pub const NR_FUTEX_WAKE: usize = 29;
+pub const NR_SETPRIORITY: usize = 30;
+pub const NR_GETPRIORITY: usize = 31;
+pub const NR_SCHED_SETAFFINITY: usize = 32;
+pub const NR_SCHED_GETAFFINITY: usize = 33;
+pub const NR_GETRUSAGE: usize = 34;
+
+/// Protection bits of `NR_MMAP` and `NR_MMAP_FILE`. Mappings are always readable.
+pub const PROT_READ: u64 = 1;
+pub const PROT_WRITE: u64 = 2;
+pub const PROT_EXEC: u64 = 4;
+
+/// Flags of `NR_MMAP_FILE`: whether writes to the mapping go back to the file.
+pub const MAP_SHARED: u64 = 1;
+pub const MAP_PRIVATE: u64 = 2;
+
+/// Signal numbers of `NR_KILL` and `NR_SIGACTION`. A process terminated by a
+/// signal exits with status `128 + sig`.
+pub const SIGHUP: u64 = 1;
+pub const SIGINT: u64 = 2;
+pub const SIGQUIT: u64 = 3;
+pub const SIGILL: u64 = 4;
+pub const SIGTRAP: u64 = 5;
+pub const SIGABRT: u64 = 6;
+pub const SIGBUS: u64 = 7;
+pub const SIGFPE: u64 = 8;
+pub const SIGKILL: u64 = 9;
+pub const SIGUSR1: u64 = 10;
+pub const SIGSEGV: u64 = 11;
+pub const SIGUSR2: u64 = 12;
+pub const SIGPIPE: u64 = 13;
+pub const SIGALRM: u64 = 14;
+pub const SIGTERM: u64 = 15;
+pub const SIGCHLD: u64 = 17;
+pub const SIGCONT: u64 = 18;
+pub const SIGSTOP: u64 = 19;
+pub const SIGTSTP: u64 = 20;
+pub const SIGTTIN: u64 = 21;
+pub const SIGTTOU: u64 = 22;
+pub const SIGURG: u64 = 23;
+pub const SIGWINCH: u64 = 28;
+/// Signal numbers are below `NSIG`.
+pub const NSIG: u64 = 32;
+
+/// Handlers of `NR_SIGACTION` selecting the default action and ignoring the signal.
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+
+/// The range of nice values of `NR_SETPRIORITY`. A lower value is a higher priority.
+pub const PRIO_MIN: i64 = -20;
+pub const PRIO_MAX: i64 = 19;
+
+/// `whence` values of `NR_LSEEK`, matching the variants of `io::SeekFrom`.
+pub const SEEK_SET: u64 = 0;
+pub const SEEK_CUR: u64 = 1;
+pub const SEEK_END: u64 = 2;
+
+/// The kind of object a file descriptor refers to.
+#[repr(u64)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FileKind {
+    File = 1,
+    Console = 2,
+    Pipe = 3,
+}
+
+/// The status of an open file descriptor, filled in by `NR_FSTAT`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Stat {
+    /// The size of the file in bytes, 0 for the console. For a pipe, the number of
+    /// bytes waiting to be read.
+    pub size: u64,
+    /// The kind of object the descriptor refers to.
+    pub kind: FileKind,
+}
+
+/// The resource usage of a process, filled in by `NR_GETRUSAGE`. Times are in
+/// microseconds.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Rusage {
+    /// The CPU time the process has used.
+    pub cpu_time: u64,
+    /// When the process started, counted from boot.
+    pub start_time: u64,
+    /// How many times the process gave up the CPU by blocking, sleeping or exiting.
+    pub voluntary_switches: u64,
+    /// How many times the process was preempted while it was ready to go on.
+    pub involuntary_switches: u64,
+    /// The number of pages mapped in the user address space of the process,
+    /// shared with its other threads.
+    pub pages: u64,
+}
+
+/// The maximum number of arguments that can be passed to `exec`.
+pub const MAX_EXEC_ARGS: usize = 16;
@@ -0,0 +1,218 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use core::time::Duration;
+
+use crate::syscall::{futex_wait, futex_wake};
+
+/// States of the futex of `Mutex`.
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// Locked, and other threads may be blocked in `futex_wait`.
+const CONTENDED: u32 = 2;
+
+/// A mutual exclusion lock for user threads. A thread that finds it locked
+/// blocks in the kernel instead of spinning, and unlocking only enters the
+/// kernel when another thread may be blocked.
+///
+/// There is no poisoning: a panicking user program exits with all its threads.
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+/// The lock of a `Mutex`, which is released when the guard is dropped.
+pub struct MutexGuard<'a, T: 'a> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    /// Returns a new, unlocked mutex holding `val`.
+    pub const fn new(val: T) -> Mutex<T> {
+        Mutex {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(val),
+        }
+    }
+
+    /// Locks the mutex without blocking. Returns `None` if it is already locked.
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        match self.state.compare_exchange(UNLOCKED, LOCKED, Acquire, Relaxed) {
+            Ok(_) => Some(MutexGuard { mutex: self }),
+            Err(_) => None,
+        }
+    }
+
+    /// Locks the mutex, blocking the calling thread until it is available.
+    pub fn lock(&self) -> MutexGuard<T> {
+        if let Some(guard) = self.try_lock() {
+            return guard;
+        }
+        // once contended, the mutex stays marked so that the unlocking thread wakes
+        // the next waiter
+        while self.state.swap(CONTENDED, Acquire) != UNLOCKED {
+            let _ = futex_wait(&self.state, CONTENDED, None);
+        }
+        MutexGuard { mutex: self }
+    }
+
+    /// Returns the inner value, consuming the mutex.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Release) == CONTENDED {
+            let _ = futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl<'a, T: 'a> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: 'a> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: 'a> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("Mutex").field("data", &&*guard).finish(),
+            None => f.debug_struct("Mutex").field("data", &"<locked>").finish(),
+        }
+    }
+}
+
+/// A condition variable for user threads, used with a `Mutex`. Waiters block on
+/// a sequence number that every notification bumps, so that a notification sent
+/// between releasing the mutex and blocking is not lost.
+pub struct Condvar {
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    /// Returns a new condition variable.
+    pub const fn new() -> Condvar {
+        Condvar {
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Releases the mutex of `guard`, blocks the calling thread until it is
+    /// notified, and locks the mutex again. Spurious wakeups are possible, so the
+    /// condition should be checked in a loop.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_timeout(guard, None).0
+    }
+
+    /// Like `wait`, but gives up once `timeout` has expired if it is `Some`. The
+    /// returned `bool` is `true` if the wait timed out.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Option<Duration>,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let seq = self.seq.load(Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+        let timed_out = match futex_wait(&self.seq, seq, timeout) {
+            Err(crate::OsError::IoErrorTimedOut) => true,
+            _ => false,
+        };
+        (mutex.lock(), timed_out)
+    }
+
+    /// Wakes one thread blocked in `wait` on this condition variable, if any.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Release);
+        let _ = futex_wake(&self.seq, 1);
+    }
+
+    /// Wakes all the threads blocked in `wait` on this condition variable.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Release);
+        let _ = futex_wake(&self.seq, core::usize::MAX);
+    }
+}
+
+impl fmt::Debug for Condvar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Condvar").finish()
+    }
+}
+
+/// States of the futex of `Once`.
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+/// Running, and other threads may be blocked in `futex_wait`.
+const WAITING: u32 = 2;
+const COMPLETE: u32 = 3;
+
+/// One-time initialization for user threads. The first thread to call
+/// `call_once` runs the closure, and the others block until it has returned.
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    /// Returns a new `Once` whose closure hasn't run yet.
+    pub const fn new() -> Once {
+        Once {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    /// Runs `f` if no call to `call_once` on this `Once` has run its closure yet.
+    /// Returns once the closure of the first call has completed.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+        match self.state.compare_exchange(INCOMPLETE, RUNNING, Acquire, Acquire) {
+            Ok(_) => {
+                f();
+                if self.state.swap(COMPLETE, Release) == WAITING {
+                    let _ = futex_wake(&self.state, core::usize::MAX);
+                }
+            },
+            Err(_) => loop {
+                match self.state.compare_exchange(RUNNING, WAITING, Acquire, Acquire) {
+                    Ok(_) | Err(WAITING) => {
+                        let _ = futex_wait(&self.state, WAITING, None);
+                    },
+                    Err(_) => return, // complete
+                }
+            },
+        }
+    }
+
+    /// Returns `true` if the closure of a `call_once` has completed.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Acquire) == COMPLETE
+    }
+}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Once").field("completed", &self.is_completed()).finish()
+    }
+}
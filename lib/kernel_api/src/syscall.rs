@@ -1,6 +1,8 @@
 use core::fmt;
 use core::fmt::Write;
+use core::sync::atomic::AtomicU32;
 use core::time::Duration;
+use shim::io;
 
 use crate::*;
 
@@ -57,14 +59,47 @@ pub fn time() -> Duration {
     err_or!(ecode, Duration::new(current_time, fraction as u32)).unwrap()
 }
 
-pub fn exit() -> ! {
+/// Terminates the calling process with the exit status `code`, which its
+/// parent collects with `wait` or `waitpid`.
+pub fn exit(code: i32) -> ! {
     unsafe {
-        asm!("svc 3");
+        asm!("mov x0, $0
+              svc $1"
+             :: "r"(code as u64), "i"(NR_EXIT)
+             : "x0"
+             : "volatile");
     };
     loop {}
 }
 
+/// Waits for the child `pid` of the calling process to exit, or for any child
+/// if `pid` is 0. Returns the ID of the child and its exit status.
+pub fn waitpid(pid: u64) -> OsResult<(u64, i32)> {
+    let mut id: u64;
+    let mut code: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $3
+              svc $4
+              mov $0, x0
+              mov $1, x1
+              mov $2, x7"
+             : "=r"(id), "=r"(code), "=r"(ecode)
+             : "r"(pid), "i"(NR_WAIT)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, (id, code as i32))
+}
 
+/// Waits for any child of the calling process to exit. Returns the ID of the
+/// child and its exit status.
+pub fn wait() -> OsResult<(u64, i32)> {
+    waitpid(0)
+}
+
+/// Writes the single byte `b` to the console. `write_fd` writes a whole buffer
+/// with one system call.
 pub fn write(b: u8) {
     let mut ecode: u64;
     unsafe {
@@ -95,18 +130,635 @@ pub fn getpid() -> u64 {
     err_or!(ecode, id).unwrap()
 }
 
+/// Creates a copy of the calling process. Returns the ID of the new process in
+/// the parent and 0 in the child.
+pub fn fork() -> OsResult<u64> {
+    let mut id: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, 0
+              svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(id), "=r"(ecode)
+             : "i"(NR_FORK as u64)
+             : "x0", "x7"
+             : "volatile");
+    };
+    err_or!(ecode, id)
+}
+
+/// Replaces the image of the calling process with the executable at `path`,
+/// passing it `args`. The process ID is kept. Only returns if the executable
+/// could not be loaded.
+pub fn exec(path: &str, args: &[&str]) -> OsError {
+    if args.len() > MAX_EXEC_ARGS {
+        return OsError::InvalidArgument;
+    }
+    let mut argv = [[0u64; 2]; MAX_EXEC_ARGS];
+    for (pair, arg) in argv.iter_mut().zip(args.iter()) {
+        *pair = [arg.as_ptr() as u64, arg.len() as u64];
+    }
+
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              mov x1, $2
+              mov x2, $3
+              mov x3, $4
+              svc $5
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(path.as_ptr() as u64), "r"(path.len() as u64),
+               "r"(argv.as_ptr() as u64), "r"(args.len() as u64), "i"(NR_EXEC)
+             : "x0", "x1", "x2", "x3", "x7"
+             : "volatile");
+    }
+    OsError::from(ecode)
+}
+
+/// Opens the file at the absolute `path` for reading and returns its file
+/// descriptor.
+pub fn open(path: &str) -> OsResult<u64> {
+    let mut fd: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              svc $4
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(fd), "=r"(ecode)
+             : "r"(path.as_ptr() as u64), "r"(path.len() as u64), "i"(NR_OPEN)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, fd)
+}
+
+/// Reads from the file descriptor `fd` into `buf`. Returns the number of bytes
+/// read, 0 at the end of the file.
+pub fn read(fd: u64, buf: &mut [u8]) -> OsResult<usize> {
+    let mut count: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              svc $5
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(count), "=r"(ecode)
+             : "r"(fd), "r"(buf.as_mut_ptr() as u64), "r"(buf.len() as u64), "i"(NR_READ)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, count as usize)
+}
+
+/// Writes `buf` to the file descriptor `fd`. Returns the number of bytes written.
+pub fn write_fd(fd: u64, buf: &[u8]) -> OsResult<usize> {
+    let mut count: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              svc $5
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(count), "=r"(ecode)
+             : "r"(fd), "r"(buf.as_ptr() as u64), "r"(buf.len() as u64), "i"(NR_WRITE_FD)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, count as usize)
+}
+
+/// Closes the file descriptor `fd`.
+pub fn close(fd: u64) -> OsResult<()> {
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              svc $2
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(fd), "i"(NR_CLOSE)
+             : "x0", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+/// Creates a pipe. Returns the file descriptors of its reading end and of its
+/// writing end. Reads block while the pipe is empty and return 0 once every
+/// writing end is closed; writes block while it is full.
+pub fn pipe() -> OsResult<(u64, u64)> {
+    let mut read_fd: u64;
+    let mut write_fd: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("svc $3
+              mov $0, x0
+              mov $1, x1
+              mov $2, x7"
+             : "=r"(read_fd), "=r"(write_fd), "=r"(ecode)
+             : "i"(NR_PIPE)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, (read_fd, write_fd))
+}
+
+/// Makes the file descriptor `new_fd` refer to the same object as `old_fd`,
+/// closing it first if it is open. Returns `new_fd`.
+pub fn dup2(old_fd: u64, new_fd: u64) -> OsResult<u64> {
+    let mut fd: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              svc $4
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(fd), "=r"(ecode)
+             : "r"(old_fd), "r"(new_fd), "i"(NR_DUP2)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, fd)
+}
+
+/// Moves the offset of the file descriptor `fd` to `pos`. Returns the new offset
+/// from the start of the file.
+pub fn lseek(fd: u64, pos: io::SeekFrom) -> OsResult<u64> {
+    let (offset, whence) = match pos {
+        io::SeekFrom::Start(offset) => (offset as i64, SEEK_SET),
+        io::SeekFrom::Current(offset) => (offset, SEEK_CUR),
+        io::SeekFrom::End(offset) => (offset, SEEK_END),
+    };
+    let mut new_offset: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              svc $5
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(new_offset), "=r"(ecode)
+             : "r"(fd), "r"(offset as u64), "r"(whence), "i"(NR_LSEEK)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, new_offset)
+}
+
+/// Returns the status of the file descriptor `fd`.
+pub fn fstat(fd: u64) -> OsResult<Stat> {
+    let mut stat = Stat { size: 0, kind: FileKind::File };
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              mov x1, $2
+              svc $3
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(fd), "r"(&mut stat as *mut Stat as u64), "i"(NR_FSTAT)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, stat)
+}
+
+/// Returns the resource usage of the process `pid`, or of the calling process if
+/// `pid` is 0.
+pub fn getrusage(pid: u64) -> OsResult<Rusage> {
+    let mut usage = Rusage::default();
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              mov x1, $2
+              svc $3
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(pid), "r"(&mut usage as *mut Rusage as u64), "i"(NR_GETRUSAGE)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, usage)
+}
+
+/// Moves the program break to `addr`, or only queries it if `addr` is 0.
+/// Returns the program break.
+pub fn brk(addr: u64) -> OsResult<u64> {
+    let mut current: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              svc $3
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(current), "=r"(ecode)
+             : "r"(addr), "i"(NR_BRK)
+             : "x0", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, current)
+}
+
+/// Grows (or shrinks) the heap by `increment` bytes and returns the previous
+/// program break, the start of the new memory.
+pub fn sbrk(increment: i64) -> OsResult<u64> {
+    let current = brk(0)?;
+    if increment != 0 {
+        brk((current as i64).wrapping_add(increment) as u64)?;
+    }
+    Ok(current)
+}
+
+/// Maps `len` bytes of zero-filled memory with the `PROT_*` protection bits
+/// `prot` and returns their address. `addr` is a page-aligned hint, 0 for any.
+pub fn mmap(addr: u64, len: u64, prot: u64) -> OsResult<u64> {
+    let mut mapped: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              svc $5
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(mapped), "=r"(ecode)
+             : "r"(addr), "r"(len), "r"(prot), "i"(NR_MMAP)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, mapped)
+}
+
+/// Unmaps the `len` bytes from the page-aligned `addr` mapped by `mmap`.
+pub fn munmap(addr: u64, len: u64) -> OsResult<()> {
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              mov x1, $2
+              svc $3
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(addr), "r"(len), "i"(NR_MUNMAP)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+/// Maps `len` bytes of the file open as `fd` from the page-aligned `offset` on
+/// with the `PROT_*` protection bits `prot` and returns their address. `flags` is
+/// `MAP_SHARED` to have writes go back to the file on `munmap` and `msync`, or
+/// `MAP_PRIVATE`. `addr` is a page-aligned hint, 0 for any.
+pub fn mmap_file(addr: u64, len: u64, prot: u64, flags: u64, fd: u64, offset: u64) -> OsResult<u64> {
+    let mut mapped: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              mov x3, $5
+              mov x4, $6
+              mov x5, $7
+              svc $8
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(mapped), "=r"(ecode)
+             : "r"(addr), "r"(len), "r"(prot), "r"(flags), "r"(fd), "r"(offset), "i"(NR_MMAP_FILE)
+             : "x0", "x1", "x2", "x3", "x4", "x5", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, mapped)
+}
+
+/// Writes the shared file mappings in the `len` bytes from the page-aligned
+/// `addr` back to their files and the files to the disk.
+pub fn msync(addr: u64, len: u64) -> OsResult<()> {
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              mov x1, $2
+              svc $3
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(addr), "r"(len), "i"(NR_MSYNC)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+/// Sends the signal `sig` to the process `pid`. With `sig` 0, only checks that
+/// the process exists.
+pub fn kill(pid: u64, sig: u64) -> OsResult<()> {
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              mov x1, $2
+              svc $3
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(pid), "r"(sig), "i"(NR_KILL)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+/// What a process does when it receives a signal.
+#[derive(Copy, Clone)]
+pub enum SigAction {
+    /// The default action of the signal: terminate, ignore, stop or continue.
+    Default,
+    /// Discard the signal.
+    Ignore,
+    /// Call the function with the signal number. The signal is blocked until the
+    /// function returns, after which the interrupted code resumes.
+    Handler(extern "C" fn(u64)),
+}
+
+/// Sets the action of the signal `sig` to `action`. Returns the previous handler:
+/// `SIG_DFL`, `SIG_IGN` or the address of a function. The action of `SIGKILL`
+/// and `SIGSTOP` can't be changed.
+pub fn sigaction(sig: u64, action: SigAction) -> OsResult<u64> {
+    let handler = match action {
+        SigAction::Default => SIG_DFL,
+        SigAction::Ignore => SIG_IGN,
+        SigAction::Handler(function) => function as u64,
+    };
+    let restorer = sigreturn_trampoline as u64;
+    let mut previous: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              svc $5
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(previous), "=r"(ecode)
+             : "r"(sig), "r"(handler), "r"(restorer), "i"(NR_SIGACTION)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, previous)
+}
+
+/// Where signal handlers return to. The kernel enters a handler with the stack
+/// pointer at the signal frame it saved the interrupted context in, and the
+/// handler returns here with the stack pointer restored, so `NR_SIGRETURN` finds
+/// the frame there. Naked so that no prologue moves the stack pointer.
+#[naked]
+extern "C" fn sigreturn_trampoline() -> ! {
+    unsafe {
+        asm!("svc $0"
+             :: "i"(NR_SIGRETURN)
+             :: "volatile");
+    }
+    loop {}
+}
+
+/// Starts a thread in the calling process running `f(arg)` on a stack of its
+/// own. The thread shares the address space and the file descriptors of the
+/// process, and exits with the value `f` returns. Returns the ID of the thread.
+pub fn thread_spawn(f: extern "C" fn(u64) -> i32, arg: u64) -> OsResult<u64> {
+    let exit = thread_exit_trampoline as u64;
+    let mut tid: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              svc $5
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(tid), "=r"(ecode)
+             : "r"(f as u64), "r"(arg), "r"(exit), "i"(NR_THREAD_SPAWN)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, tid)
+}
+
+/// Terminates the calling thread with the exit status `code`, which the thread
+/// joining it collects with `thread_join`. Called from the main thread, it
+/// terminates the whole process as `exit` does.
+pub fn thread_exit(code: i32) -> ! {
+    unsafe {
+        asm!("mov x0, $0
+              svc $1"
+             :: "r"(code as u64), "i"(NR_THREAD_EXIT)
+             : "x0"
+             : "volatile");
+    };
+    loop {}
+}
+
+/// Waits for the thread `tid` of the calling process to exit. Returns its exit
+/// status.
+pub fn thread_join(tid: u64) -> OsResult<i32> {
+    let mut code: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              svc $3
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(code), "=r"(ecode)
+             : "r"(tid), "i"(NR_THREAD_JOIN)
+             : "x0", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, code as i32)
+}
+
+/// Where thread functions return to, with their return value in `x0`, which
+/// becomes the exit status of the thread.
+#[naked]
+extern "C" fn thread_exit_trampoline() -> ! {
+    unsafe {
+        asm!("svc $0"
+             :: "i"(NR_THREAD_EXIT)
+             :: "volatile");
+    }
+    loop {}
+}
+
+/// Blocks the calling thread while the word at `futex` holds `expected`, until
+/// another thread calls `futex_wake` on it or `timeout` expires. Returns
+/// `WouldBlock` right away if the word holds another value, and
+/// `IoErrorTimedOut` if the timeout expired first.
+pub fn futex_wait(futex: &AtomicU32, expected: u32, timeout: Option<Duration>) -> OsResult<()> {
+    let ms = match timeout {
+        // 0 means no timeout to the kernel
+        Some(span) => core::cmp::max(span.as_millis(), 1) as u64,
+        None => 0,
+    };
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              mov x1, $2
+              mov x2, $3
+              svc $4
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(futex as *const AtomicU32 as u64), "r"(expected as u64), "r"(ms), "i"(NR_FUTEX_WAIT)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+/// Wakes up to `count` threads blocked in `futex_wait` on `futex`. Returns the
+/// number of threads woken.
+pub fn futex_wake(futex: &AtomicU32, count: usize) -> OsResult<usize> {
+    let mut woken: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              svc $4
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(woken), "=r"(ecode)
+             : "r"(futex as *const AtomicU32 as u64), "r"(count as u64), "i"(NR_FUTEX_WAKE)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, woken as usize)
+}
+
+/// Sets the nice value of the process `pid`, or of the calling process if `pid`
+/// is 0. Values outside `PRIO_MIN..=PRIO_MAX` are clamped. A process with a lower
+/// nice value runs before any ready process with a higher one. The nice value may
+/// only be raised, even for the calling process: lowering it returns `NoAccess`.
+pub fn setpriority(pid: u64, nice: i64) -> OsResult<()> {
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              mov x1, $2
+              svc $3
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(pid), "r"(nice as u64), "i"(NR_SETPRIORITY)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+/// Returns the nice value of the process `pid`, or of the calling process if
+/// `pid` is 0.
+pub fn getpriority(pid: u64) -> OsResult<i64> {
+    let mut nice: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              svc $3
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(nice), "=r"(ecode)
+             : "r"(pid), "i"(NR_GETPRIORITY)
+             : "x0", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, nice as i64)
+}
+
+/// Sets the cores the process `pid`, or the calling process if `pid` is 0, may
+/// run on: bit `n` of `mask` stands for core `n`. Fails with `InvalidArgument`
+/// if `mask` has none of the cores.
+pub fn sched_setaffinity(pid: u64, mask: u64) -> OsResult<()> {
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $1
+              mov x1, $2
+              svc $3
+              mov $0, x7"
+             : "=r"(ecode)
+             : "r"(pid), "r"(mask), "i"(NR_SCHED_SETAFFINITY)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
 
-struct Console;
+/// Returns the affinity mask of the process `pid`, or of the calling process if
+/// `pid` is 0.
+pub fn sched_getaffinity(pid: u64) -> OsResult<u64> {
+    let mut mask: u64;
+    let mut ecode: u64;
+    unsafe {
+        asm!("mov x0, $2
+              svc $3
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(mask), "=r"(ecode)
+             : "r"(pid), "i"(NR_SCHED_GETAFFINITY)
+             : "x0", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, mask)
+}
+
+/// The size of the line buffer of `Console`.
+pub const CONSOLE_BUFFER_SIZE: usize = 256;
+
+/// The standard output of a user program. Output is buffered and written to
+/// file descriptor 1 with a single system call when a newline is printed, when
+/// the buffer is full, and when the `Console` is dropped, so that lines from
+/// concurrent processes don't interleave.
+pub struct Console {
+    buf: [u8; CONSOLE_BUFFER_SIZE],
+    len: usize,
+}
+
+impl Console {
+    /// Returns a new, empty `Console`.
+    pub const fn new() -> Console {
+        Console {
+            buf: [0; CONSOLE_BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Writes out the buffered output.
+    pub fn flush(&mut self) -> OsResult<()> {
+        let mut written = 0;
+        while written < self.len {
+            written += write_fd(1, &self.buf[written..self.len])?;
+        }
+        self.len = 0;
+        Ok(())
+    }
+}
 
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for b in s.bytes() {
-            write(b);
+        for &byte in s.as_bytes() {
+            self.buf[self.len] = byte;
+            self.len += 1;
+            if byte == b'\n' || self.len == CONSOLE_BUFFER_SIZE {
+                self.flush().map_err(|_| fmt::Error)?;
+            }
         }
         Ok(())
     }
 }
 
+impl Drop for Console {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => ($crate::syscall::vprint(format_args!($($arg)*)));
@@ -114,14 +766,11 @@ macro_rules! print {
 
 #[macro_export]
 macro_rules! println {
- () => (print!("\n"));
-    ($($arg:tt)*) => ({
-        $crate::syscall::vprint(format_args!($($arg)*));
-        $crate::print!("\n");
-    })
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::syscall::vprint(format_args!("{}\n", format_args!($($arg)*))));
 }
 
 pub fn vprint(args: fmt::Arguments) {
-    let mut c = Console;
-    c.write_fmt(args);
+    let mut c = Console::new();
+    let _ = c.write_fmt(args);
 }
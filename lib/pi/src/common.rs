@@ -1,6 +1,8 @@
 /// The address where I/O peripherals are mapped to.
 pub const IO_BASE: usize = 0x3F000000;
-pub const IO_BASE_END: usize = 0x40000000;
+/// The end of the I/O peripherals, including the ARM local peripherals right
+/// above them.
+pub const IO_BASE_END: usize = 0x40000000 + 0x20000;
 
 /// The base address of the `GPIO` registers
 pub const GPIO_BASE: usize = IO_BASE + 0x200000;
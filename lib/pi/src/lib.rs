@@ -9,6 +9,7 @@ pub mod atags;
 pub mod common;
 pub mod gpio;
 pub mod interrupt;
+pub mod local_interrupt;
 pub mod timer;
 pub mod uart;
 pub mod rand;
\ No newline at end of file
@@ -0,0 +1,122 @@
+use core::time::Duration;
+
+use aarch64::*;
+use volatile::prelude::*;
+use volatile::{ReadVolatile, Volatile, WriteVolatile};
+
+/// The base address of the ARM local peripherals of the BCM2836.
+// doc: https://www.raspberrypi.org/documentation/hardware/raspberrypi/bcm2836/QA7_rev3.4.pdf
+const LOCAL_INT_BASE: usize = 0x40000000;
+
+/// The sources of the interrupts of a core (QA7: 4.10).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LocalInterrupt {
+    CntPsIrq = 0,
+    CntPnsIrq = 1,
+    CntHpIrq = 2,
+    CntVIrq = 3,
+    Mailbox0 = 4,
+    Mailbox1 = 5,
+    Mailbox2 = 6,
+    Mailbox3 = 7,
+    Gpu = 8,
+    Pmu = 9,
+    AxiOutstanding = 10,
+    LocalTimer = 11,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    pub control: Volatile<u32>,
+    _unused1: [Volatile<u32>; 8],
+    pub local_ints_routing: Volatile<u32>,
+    _unused2: [Volatile<u32>; 6],
+    pub core_timer_int_control: [Volatile<u32>; 4],
+    pub core_mailbox_int_control: [Volatile<u32>; 4],
+    pub core_irq_source: [ReadVolatile<u32>; 4],
+    pub core_fiq_source: [ReadVolatile<u32>; 4],
+    /// Writing a bit sets it in a mailbox of a core, per core and mailbox.
+    pub core_mailbox_set: [[WriteVolatile<u32>; 4]; 4],
+    /// Reads a mailbox of a core; writing a bit clears it.
+    pub core_mailbox_clear: [[Volatile<u32>; 4]; 4],
+}
+
+/// The local interrupt controller of a core, and its physical timer. Used to
+/// route the interrupts of the core, to check which of them is pending, and to
+/// send it inter-processor interrupts through its mailboxes.
+///
+/// The timer registers are banked per core: the timer methods act on the timer
+/// of the core executing them, which has to be `core`.
+pub struct LocalController {
+    core: usize,
+    registers: &'static mut Registers,
+}
+
+impl LocalController {
+    /// Returns a new handle to the local interrupt controller of `core`.
+    pub fn new(core: usize) -> LocalController {
+        LocalController {
+            core: core,
+            registers: unsafe { &mut *(LOCAL_INT_BASE as *mut Registers) },
+        }
+    }
+
+    /// Enables the non-secure physical timer of the core and routes its
+    /// interrupt to the IRQ line of the core. No interrupt is raised until
+    /// `tick_in()` is called.
+    pub fn enable_local_timer(&mut self) {
+        unsafe {
+            CNTP_CTL_EL0.set(CNTP_CTL_EL0::ENABLE | CNTP_CTL_EL0::IMASK);
+        }
+        self.registers.core_timer_int_control[self.core].or_mask(1 << (LocalInterrupt::CntPnsIrq as u32));
+    }
+
+    /// Returns `true` if the interrupt `int` is pending on the core.
+    pub fn is_pending(&self, int: LocalInterrupt) -> bool {
+        self.registers.core_irq_source[self.core].has_mask(1 << (int as u32))
+    }
+
+    /// Sets up the timer of the core to raise an interrupt `t` duration from now.
+    /// This also acknowledges the previous interrupt of the timer.
+    pub fn tick_in(&mut self, t: Duration) {
+        unsafe {
+            let freq = CNTFRQ_EL0.get_value(CNTFRQ_EL0::ClockFreq) as u128;
+            // the timer value is a signed 32-bit count of counter ticks
+            let ticks = core::cmp::min(freq * t.as_nanos() / 1_000_000_000, 0x7fff_ffff);
+            CNTP_TVAL_EL0.set(ticks as u64);
+            CNTP_CTL_EL0.set(CNTP_CTL_EL0::ENABLE);
+        }
+    }
+
+    /// Masks the interrupt of the timer of the core without setting up a new one,
+    /// so that it stops being raised.
+    pub fn clear_tick(&mut self) {
+        unsafe {
+            CNTP_CTL_EL0.set(CNTP_CTL_EL0::ENABLE | CNTP_CTL_EL0::IMASK);
+        }
+    }
+
+    /// Routes the interrupt of the mailbox `mailbox` of the core to its IRQ line,
+    /// so that a non-zero mailbox interrupts the core.
+    pub fn enable_mailbox(&mut self, mailbox: usize) {
+        self.registers.core_mailbox_int_control[self.core].or_mask(1 << mailbox);
+    }
+
+    /// Sets the bits of `mask` in the mailbox `mailbox` of the core, interrupting
+    /// it if the mailbox is enabled. Any core may send to any core.
+    pub fn send_mailbox(&mut self, mailbox: usize, mask: u32) {
+        self.registers.core_mailbox_set[self.core][mailbox].write(mask);
+    }
+
+    /// Returns the bits set in the mailbox `mailbox` of the core.
+    pub fn read_mailbox(&self, mailbox: usize) -> u32 {
+        self.registers.core_mailbox_clear[self.core][mailbox].read()
+    }
+
+    /// Clears the bits of `mask` in the mailbox `mailbox` of the core. The
+    /// interrupt of the mailbox is raised until all its bits are clear.
+    pub fn clear_mailbox(&mut self, mailbox: usize, mask: u32) {
+        self.registers.core_mailbox_clear[self.core][mailbox].write(mask);
+    }
+}
@@ -45,6 +45,12 @@ impl Timer {
         self.registers.COMPARE[1].write(current_time + t.as_micros() as u32);
         self.registers.CS.or_mask(0b10 as u32);
     }
+
+    /// Acknowledges a match in timer 1 without setting up a new one, so that the
+    /// timer 1 interrupt stops being raised.
+    pub fn clear_tick(&mut self) {
+        self.registers.CS.or_mask(0b10 as u32);
+    }
 }
 
 /// Returns current time.
@@ -66,3 +72,10 @@ pub fn tick_in(t: Duration) {
     timer.tick_in(t);
 }
 
+/// Acknowledges a match in timer 1 without setting up a new one, so that the
+/// timer 1 interrupt stops being raised.
+pub fn clear_tick() {
+    let mut timer = Timer::new();
+    timer.clear_tick();
+}
+
@@ -33,4 +33,9 @@ for d in ${PROGS[@]}; do
 done
 # sudo cp fib/build/libpeter_lib.so $MNT/libpeter_lib.so 
 # sudo cp fib/build/main.elf $MNT/peter
-sudo cp fib/build/real $MNT/real
\ No newline at end of file
+sudo cp fib/build/real $MNT/real
+
+# the kernel reads its symbols from here for backtraces
+if [ -e ../kern/build/kernel.elf ]; then
+    sudo cp ../kern/build/kernel.elf $MNT/kernel.elf
+fi
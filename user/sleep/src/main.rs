@@ -1,31 +1,27 @@
 #![feature(asm)]
-
 #![no_std]
 #![no_main]
 
-use core::panic::PanicInfo;
+extern crate alloc;
 
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
-}
+mod cr0;
+use alloc::vec::Vec;
+use core::time::Duration;
+use kernel_api::println;
+use kernel_api::syscall::{exit, sleep};
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
+fn main() {
+    // how long each sleep actually took
+    let mut slept: Vec<Duration> = Vec::new();
     loop {
-        let ms = 10000;
-        let error: u64;
-        let elapsed_ms: u64;
-
-        unsafe {
-            asm!("mov x0, $2
-                  svc 1
-                  mov $0, x0
-                  mov $1, x7"
-                 : "=r"(elapsed_ms), "=r"(error)
-                 : "r"(ms)
-                 : "x0", "x7"
-                 : "volatile");
+        match sleep(Duration::from_millis(10000)) {
+            Ok(elapsed) => slept.push(elapsed),
+            Err(error) => {
+                println!("sleep failed: {:?}", error);
+                exit(1);
+            },
         }
+        let total = slept.iter().fold(Duration::from_secs(0), |total, elapsed| total + *elapsed);
+        println!("Slept {} times, {} ms in total", slept.len(), total.as_millis());
     }
 }